@@ -7,8 +7,8 @@ use zwire::session::{SessionManager, SimpleSessionBackend};
 
 /*
 CLIENT CONNECTS TO SERVER & PASSES AUTH:
-Client -> Server (bi): RequestAudioTransmission { optional parameters }
-Server -> Client (bi): AwaitAudioTransmission { audio_metadata, uni stream stable_id }
+Client -> Server (bi): RequestTransmission { TransmissionRequest, every parameter optional }
+Server -> Client (bi): ApproveTransmission { TransmissionApproval { audio_metadata, uni stream stable_id } }
 
 Server -> Client (uni): AudioPayload...
 */
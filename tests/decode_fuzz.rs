@@ -0,0 +1,241 @@
+// Every decode path has to reject arbitrary input with an error, never a panic. Inputs are
+// random bytes as well as valid encodings cut short or with a byte flipped, the latter get past
+// the first length checks and reach the deeper ones
+#![cfg(all(feature = "zauth", feature = "zaudio"))]
+
+use proptest::prelude::*;
+use zenet::{
+    zaudio::{
+        AudioMetadata, AudioMetadataCodec, AudioPayloadCodec, TransmissionApprovalCodec,
+        TransmissionRequestCodec,
+    },
+    zauth::{AuthPayload, AuthPayloadCodec},
+    zwire::{
+        codec::{
+            bytes::{ByteStr, Bytes, BytesMut},
+            wired::WireCodec,
+            ChannelCodec, Decoder, Encoder, FragmentCodec, FrameChecksum, FrameCodec, FrameHeader,
+            Lz4Transform,
+        },
+        BufDecoder, Frame, Message,
+    },
+};
+
+pub mod __zwire_macros_support {
+    pub use zenet::zwire::__zwire_macros_support::*;
+}
+
+// Every field kind a derived codec can decode, in one record
+#[derive(Debug, Clone, WireCodec)]
+#[wire(version = 2, extensions = 64)]
+pub struct Everything {
+    #[wire(u8, fixed)]
+    pub flags: u8,
+    #[wire(u16, length_prefix, 32)]
+    pub blob: Bytes,
+    #[wire(u8, length_prefix_string, 64, Hostname)]
+    pub host: ByteStr,
+    #[wire(u8, repeated, 4, (u8, length_prefix_string, 16, PrintableAscii))]
+    pub notes: Vec<ByteStr>,
+    #[wire(u8, nested, AudioMetadataCodec)]
+    pub metadata: AudioMetadata,
+    #[wire(u32, fixed, optional)]
+    pub limit: Option<u32>,
+    #[wire(u8, length_prefix_string, 16, Utf8, tag = 1)]
+    pub label: Option<ByteStr>,
+}
+
+const MAX_INPUT_LENGTH: usize = 512;
+
+fn arbitrary_input() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), 0..MAX_INPUT_LENGTH)
+}
+
+// Decodes until the codec asks for more input or fails, then once more as if the stream ended
+fn decode_stream<D: Decoder>(mut codec: D, input: &[u8]) {
+    let mut source = BytesMut::from(input);
+
+    // Each item consumes at least a byte, so this only bounds a codec that stops making progress
+    for _ in 0..=input.len() {
+        match codec.decode(&mut source) {
+            Ok(Some(_)) => continue,
+            Ok(None) => {
+                let _ = codec.decode_eof(&mut source);
+
+                return;
+            }
+            Err(_) => return,
+        }
+    }
+
+    panic!("decoder returned items without consuming its input");
+}
+
+// Records go through both buffer types the take helpers are implemented for
+fn decode_record<C: BufDecoder + Default>(input: &[u8]) {
+    let _ = C::default().decode_buf(&mut BytesMut::from(input));
+    let _ = C::default().decode_buf(&mut Bytes::copy_from_slice(input));
+}
+
+fn frame_codecs() -> Vec<FrameCodec> {
+    [FrameHeader::U16, FrameHeader::U32, FrameHeader::VarInt]
+        .into_iter()
+        .flat_map(|header| {
+            [FrameChecksum::None, FrameChecksum::Crc32c].map(|checksum| {
+                FrameCodec::builder()
+                    .header(header)
+                    .checksum(checksum)
+                    .build()
+                    .expect("valid frame codec configuration")
+            })
+        })
+        .collect()
+}
+
+fn lz4_codec() -> FrameCodec<Lz4Transform> {
+    FrameCodec::builder()
+        .transform(Lz4Transform::new(0))
+        .build()
+        .expect("valid frame codec configuration")
+}
+
+fn decode_frames(input: &[u8]) {
+    for codec in frame_codecs() {
+        decode_stream(codec, input);
+        decode_stream(ChannelCodec::new(codec), input);
+        decode_stream(
+            FragmentCodec::new(codec).expect("payload room for fragments"),
+            input,
+        );
+    }
+
+    decode_stream(lz4_codec(), input);
+}
+
+fn decode_records(input: &[u8]) {
+    decode_record::<AuthPayloadCodec>(input);
+    decode_record::<AudioPayloadCodec>(input);
+    decode_record::<AudioMetadataCodec>(input);
+    decode_record::<TransmissionApprovalCodec>(input);
+    decode_record::<TransmissionRequestCodec>(input);
+    decode_record::<EverythingCodec>(input);
+}
+
+fn encode<T, C: Encoder<T> + Default>(item: T) -> Vec<u8>
+where
+    C::Error: std::fmt::Debug,
+{
+    let mut destination = BytesMut::new();
+
+    C::default()
+        .encode(item, &mut destination)
+        .expect("valid item encodes");
+
+    destination.to_vec()
+}
+
+fn valid_records() -> Vec<Vec<u8>> {
+    let metadata = AudioMetadata {
+        encoding: 1u8.try_into().expect("pcm"),
+        channels: 2u8.try_into().expect("stereo"),
+        sample_rate: 48_000,
+    };
+
+    vec![
+        encode::<_, AuthPayloadCodec>(AuthPayload {
+            timestamp: 1_700_000_000,
+            nonce: 7,
+            mac: Bytes::from_static(&[0xAB; 32]),
+            client_identifier: "client-1".into(),
+        }),
+        encode::<_, EverythingCodec>(Everything {
+            flags: 3,
+            blob: Bytes::from_static(b"blob"),
+            host: "relay.example.com".into(),
+            notes: vec!["first".into(), "second one".into()],
+            metadata,
+            limit: Some(9),
+            label: Some("label".into()),
+        }),
+    ]
+}
+
+fn valid_frames() -> Vec<(FrameCodec, Vec<u8>)> {
+    frame_codecs()
+        .into_iter()
+        .map(|mut codec| {
+            let mut destination = BytesMut::new();
+            let frame = Frame {
+                message: Message(2),
+                payload: Bytes::from(valid_records().remove(0)),
+            };
+
+            codec
+                .encode(frame, &mut destination)
+                .expect("valid frame encodes");
+
+            (codec, destination.to_vec())
+        })
+        .collect()
+}
+
+// Cuts `input` at `cut` and flips the bits of `mask` into the byte at `position`
+fn corrupt(mut input: Vec<u8>, cut: usize, position: usize, mask: u8) -> Vec<u8> {
+    if !input.is_empty() {
+        let position = position % input.len();
+        input[position] ^= mask;
+    }
+
+    input.truncate(cut % (input.len() + 1));
+
+    input
+}
+
+// The corrupted inputs start out as these, they have to be accepted as they are
+#[test]
+fn valid_records_decode() {
+    let [auth, everything] = <[Vec<u8>; 2]>::try_from(valid_records()).expect("two records");
+
+    assert!(matches!(
+        AuthPayloadCodec::default().decode_buf(&mut BytesMut::from(&auth[..])),
+        Ok(Some(_))
+    ));
+    assert!(matches!(
+        EverythingCodec::default().decode_buf(&mut BytesMut::from(&everything[..])),
+        Ok(Some(Everything { label: Some(_), .. }))
+    ));
+}
+
+proptest! {
+    #[test]
+    fn frame_codecs_survive_arbitrary_input(input in arbitrary_input()) {
+        decode_frames(&input);
+    }
+
+    #[test]
+    fn record_codecs_survive_arbitrary_input(input in arbitrary_input()) {
+        decode_records(&input);
+    }
+
+    #[test]
+    fn record_codecs_survive_corrupted_records(
+        cut in any::<usize>(),
+        position in any::<usize>(),
+        mask in any::<u8>(),
+    ) {
+        for record in valid_records() {
+            decode_records(&corrupt(record, cut, position, mask));
+        }
+    }
+
+    #[test]
+    fn frame_codecs_survive_corrupted_frames(
+        cut in any::<usize>(),
+        position in any::<usize>(),
+        mask in any::<u8>(),
+    ) {
+        for (codec, frame) in valid_frames() {
+            decode_stream(codec, &corrupt(frame, cut, position, mask));
+        }
+    }
+}
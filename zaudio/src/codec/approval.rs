@@ -0,0 +1,126 @@
+use super::{AudioMetadata, AudioMetadataCodec};
+use zwire::codec::wired::WireCodec;
+
+// [metadata] | [u64 stream_id], the stream id names the uni stream the audio will arrive on
+#[derive(Debug, Clone, WireCodec)]
+pub struct TransmissionApproval {
+    #[wire(AudioMetadataCodec, nested)]
+    pub metadata: AudioMetadata,
+    #[wire(u64, fixed)]
+    pub stream_id: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AudioEncoding, Channels};
+    use zwire::{
+        codec::{bytes::BytesMut, wired::WiredRecord, Decoder, Encoder},
+        errors::WireError,
+    };
+
+    // The same record with its metadata behind a length prefix
+    #[derive(Debug, Clone, WireCodec)]
+    #[wire(fields = prefixed_fields)]
+    struct PrefixedApproval {
+        #[wire(u8, nested, AudioMetadataCodec)]
+        metadata: AudioMetadata,
+        #[wire(u64, fixed)]
+        stream_id: u64,
+    }
+
+    fn metadata() -> AudioMetadata {
+        AudioMetadata {
+            encoding: AudioEncoding::PcmS16Le,
+            channels: Channels::Stereo,
+            sample_rate: 48_000,
+        }
+    }
+
+    #[test]
+    fn derives_max_length_from_the_inner_record() {
+        let metadata_length = <AudioMetadataCodec as WiredRecord>::MAX_LENGTH;
+
+        assert_eq!(metadata_length, 6);
+        assert_eq!(
+            <TransmissionApprovalCodec as WiredRecord>::MAX_LENGTH,
+            metadata_length + 8
+        );
+        assert_eq!(
+            <PrefixedApprovalCodec as WiredRecord>::MAX_LENGTH,
+            1 + metadata_length + 8
+        );
+    }
+
+    #[test]
+    fn round_trips_nested_metadata() {
+        let mut encoded = BytesMut::new();
+        TransmissionApprovalCodec::default()
+            .encode(
+                TransmissionApproval {
+                    metadata: metadata(),
+                    stream_id: 3,
+                },
+                &mut encoded,
+            )
+            .unwrap();
+
+        assert_eq!(
+            &encoded[..],
+            [1, 2, 0, 0, 0xBB, 0x80, 0, 0, 0, 0, 0, 0, 0, 3]
+        );
+
+        let decoded = TransmissionApprovalCodec::default()
+            .decode(&mut encoded)
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(decoded.metadata.encoding, AudioEncoding::PcmS16Le));
+        assert!(matches!(decoded.metadata.channels, Channels::Stereo));
+        assert_eq!(decoded.metadata.sample_rate, 48_000);
+        assert_eq!(decoded.stream_id, 3);
+    }
+
+    #[test]
+    fn round_trips_length_prefixed_metadata() {
+        let mut encoded = BytesMut::new();
+        PrefixedApprovalCodec::default()
+            .encode(
+                PrefixedApproval {
+                    metadata: metadata(),
+                    stream_id: 3,
+                },
+                &mut encoded,
+            )
+            .unwrap();
+
+        assert_eq!(&encoded[..7], [6, 1, 2, 0, 0, 0xBB, 0x80]);
+
+        let decoded = PrefixedApprovalCodec::default()
+            .decode(&mut encoded)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(decoded.metadata.sample_rate, 48_000);
+        assert_eq!(decoded.stream_id, 3);
+    }
+
+    #[test]
+    fn rejects_a_truncated_inner_record() {
+        // The prefix holds 3 bytes of the 6 byte metadata, the frame itself is whole
+        let mut source = BytesMut::from(&[3, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 3][..]);
+
+        assert!(matches!(
+            PrefixedApprovalCodec::default().decode(&mut source),
+            Err(WireError::Underflow("metadata", 3, 6))
+        ));
+
+        // Without a prefix a short record is only incomplete
+        let mut source = BytesMut::from(&[1, 2, 0, 0][..]);
+
+        assert!(TransmissionApprovalCodec::default()
+            .decode(&mut source)
+            .unwrap()
+            .is_none());
+    }
+}
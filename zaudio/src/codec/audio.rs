@@ -6,7 +6,7 @@ use zwire::{
         Decoder, Encoder,
     },
     errors::WireError,
-    DecodeFromFrame, EncodeIntoFrame,
+    BufDecoder, DecodeFromFrame, EncodeIntoFrame, WireBuf,
 };
 
 impl EncodeIntoFrame for AudioPayloadCodec {
@@ -41,6 +41,18 @@ impl Decoder for AudioPayloadCodec {
     type Error = WireError;
 
     fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode_buf(source)
+    }
+}
+
+impl BufDecoder for AudioPayloadCodec {
+    type Item = AudioPayload;
+    type Error = WireError;
+
+    fn decode_buf<B: WireBuf>(
+        &mut self,
+        source: &mut B,
+    ) -> Result<Option<Self::Item>, Self::Error> {
         let audio_payload = source.take_length_prefixed::<fields::audio::Wired>()?;
 
         Ok(audio_payload)
@@ -1,72 +1,73 @@
-use crate::{AudioEncoding, AudioMetadata, Channels};
-use zwire::{
-    codec::{
-        bytes::{BytesMut, BytesMutPutExt, BytesMutTakeExt},
-        wired::define_fields,
-        Decoder, Encoder,
-    },
-    errors::WireError,
-    DecodeFromFrame, EncodeIntoFrame,
-};
+use crate::{AudioEncoding, Channels};
+use zwire::codec::wired::WireCodec;
 
-impl EncodeIntoFrame for AudioMetadataCodec {
-    type EncodeItem = AudioMetadata;
+// [u8 encoding] | [u8 channels] | [u32 sample_rate]
+#[derive(Debug, Clone, WireCodec)]
+pub struct AudioMetadata {
+    #[wire(u8, fixed)]
+    pub encoding: AudioEncoding,
+    #[wire(u8, fixed)]
+    pub channels: Channels,
+    #[wire(u32, fixed)]
+    pub sample_rate: u32,
 }
 
-impl DecodeFromFrame for AudioMetadataCodec {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransmissionRequestCodec;
+    use zwire::{
+        codec::{bytes::BytesMut, Decoder, Encoder},
+        errors::WireError,
+    };
 
-#[derive(Clone, Copy, Default)]
-pub struct AudioMetadataCodec {}
+    // Encoded by the hand-written codec this one replaced
+    const STEREO_48K: [u8; 6] = [1, 2, 0, 0, 0xBB, 0x80];
 
-define_fields! {
-    (Encoding, u8, fixed),
-    (Channels, u8, fixed),
-    (SampleRate, u32, fixed),
-}
+    #[test]
+    fn matches_the_baseline_encoding() {
+        let mut encoded = BytesMut::new();
+        AudioMetadataCodec::default()
+            .encode(
+                AudioMetadata {
+                    encoding: AudioEncoding::PcmS16Le,
+                    channels: Channels::Stereo,
+                    sample_rate: 48_000,
+                },
+                &mut encoded,
+            )
+            .unwrap();
 
-impl Encoder<AudioMetadata> for AudioMetadataCodec {
-    type Error = WireError;
+        assert_eq!(&encoded[..], STEREO_48K);
 
-    fn encode(
-        &mut self,
-        audio_metadata: AudioMetadata,
-        destination: &mut BytesMut,
-    ) -> Result<(), Self::Error> {
-        destination.put_single::<fields::encoding::Wired>(audio_metadata.encoding as u8);
-        destination.put_single::<fields::channels::Wired>(audio_metadata.channels as u8);
-        destination.put_single::<fields::samplerate::Wired>(audio_metadata.sample_rate);
+        let mut source = BytesMut::from(&STEREO_48K[..]);
+        let decoded = AudioMetadataCodec::default()
+            .decode(&mut source)
+            .unwrap()
+            .unwrap();
 
-        Ok(())
+        assert!(matches!(decoded.encoding, AudioEncoding::PcmS16Le));
+        assert!(matches!(decoded.channels, Channels::Stereo));
+        assert_eq!(decoded.sample_rate, 48_000);
+        assert!(source.is_empty());
     }
-}
-
-impl Decoder for AudioMetadataCodec {
-    type Item = AudioMetadata;
-    type Error = WireError;
-
-    fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let source_length = source.len();
 
-        if source_length < fields::FIXED_PART_LENGTH {
-            return Ok(None);
-        }
+    #[test]
+    fn rejects_unknown_encodings() {
+        // [u8 encoding = 2] | [u8 channels] | [u32 sample_rate]
+        let mut source = BytesMut::from(&[2, 1, 0, 0, 0xBB, 0x80][..]);
 
-        if source_length > fields::MAX_LENGTH {
-            return Err(WireError::Oversized(
-                "total_length",
-                source_length,
-                fields::MAX_LENGTH,
-            ));
-        }
+        assert!(matches!(
+            AudioMetadataCodec::default().decode(&mut source),
+            Err(WireError::InvalidMessageType(2))
+        ));
 
-        let encoding_code = source.take_single_unchecked::<fields::encoding::Wired>();
-        let channels_code = source.take_single_unchecked::<fields::channels::Wired>();
-        let sample_rate = source.take_single_unchecked::<fields::samplerate::Wired>();
+        // [u8 presence] | [u8 count][u8 encoding...]
+        let mut source = BytesMut::from(&[0, 2, 1, 2][..]);
 
-        Ok(Some(AudioMetadata {
-            encoding: AudioEncoding::try_from(encoding_code)?,
-            channels: Channels::try_from(channels_code)?,
-            sample_rate,
-        }))
+        assert!(matches!(
+            TransmissionRequestCodec::default().decode(&mut source),
+            Err(WireError::InvalidMessageType(2))
+        ));
     }
 }
@@ -1,5 +1,11 @@
+mod approval;
+pub use approval::{TransmissionApproval, TransmissionApprovalCodec};
+
 mod audio;
 pub use audio::AudioPayloadCodec;
 
 mod metadata;
-pub use metadata::AudioMetadataCodec;
+pub use metadata::{AudioMetadata, AudioMetadataCodec};
+
+mod request;
+pub use request::{TransmissionRequest, TransmissionRequestCodec};
@@ -0,0 +1,14 @@
+use crate::{AudioEncoding, Channels};
+use zwire::codec::wired::WireCodec;
+
+// [u8 presence] | [u8 count][u8 encoding...] | [u8 channels]? | [u32 sample_rate]?, encodings are
+// the ones the client can play in order of preference, the server picks whatever is left out
+#[derive(Debug, Clone, Default, WireCodec)]
+pub struct TransmissionRequest {
+    #[wire(u8, repeated, 8, (u8, fixed))]
+    pub encodings: Vec<AudioEncoding>,
+    #[wire(u8, fixed, optional)]
+    pub channels: Option<Channels>,
+    #[wire(u32, fixed, optional)]
+    pub sample_rate: Option<u32>,
+}
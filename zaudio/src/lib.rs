@@ -1,6 +1,9 @@
 mod codec;
 
-pub use codec::{AudioMetadataCodec, AudioPayloadCodec};
+pub use codec::{
+    AudioMetadata, AudioMetadataCodec, AudioPayloadCodec, TransmissionApproval,
+    TransmissionApprovalCodec, TransmissionRequest, TransmissionRequestCodec,
+};
 use zwire::codec::{bytes::Bytes, wired::define_message};
 
 pub mod __zwire_macros_support {
@@ -28,10 +31,3 @@ define_message!(
         ApproveTransmission = 2, // server opens uni 
     }
 );
-
-#[derive(Debug, Clone)]
-pub struct AudioMetadata {
-    pub encoding: AudioEncoding,
-    pub channels: Channels,
-    pub sample_rate: u32,
-}
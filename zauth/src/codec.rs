@@ -1,126 +1,91 @@
-use crate::AuthPayload;
-use zwire::{
-    codec::{
-        bytes::{BytesMut, BytesMutPutExt, BytesMutTakeExt, BytesPeekExt},
-        wired::define_fields,
-        Decoder, Encoder,
-    },
-    errors::WireError,
-    helpers::CheckedAddWire,
-    DecodeFromFrame, EncodeIntoFrame,
+use zwire::codec::{
+    bytes::{ByteStr, Bytes},
+    wired::WireCodec,
 };
 
-impl EncodeIntoFrame for AuthPayloadCodec {
-    type EncodeItem = AuthPayload;
-}
-
-impl DecodeFromFrame for AuthPayloadCodec {}
-
-#[derive(Clone, Copy)]
-pub struct AuthPayloadCodec {
-    max_length: usize,
-}
-
-impl Default for AuthPayloadCodec {
-    fn default() -> Self {
-        Self {
-            max_length: fields::MAX_LENGTH,
-        }
-    }
-}
-
 // [u64 timestamp] | [u128 nonce] | [mac] | [u8 length][client_id...]
-define_fields! {
-    (Timestamp, u64, fixed),
-    (Nonce, u128, fixed),
-    (Mac, 32, fixed),
-    (ClientIdentifier, u8, length_prefix_string, 255, AsciiHyphen),
+#[derive(Debug, Clone, WireCodec)]
+pub struct AuthPayload {
+    #[wire(u64, fixed)]
+    pub timestamp: u64,
+    #[wire(u128, fixed)]
+    pub nonce: u128,
+    #[wire(32, fixed)]
+    pub mac: Bytes,
+    #[wire(u8, length_prefix_string, 255, AsciiHyphen)]
+    pub client_identifier: ByteStr,
 }
 
-impl Encoder<AuthPayload> for AuthPayloadCodec {
-    type Error = WireError;
-
-    fn encode(
-        &mut self,
-        auth_payload: AuthPayload,
-        destination: &mut BytesMut,
-    ) -> Result<(), Self::Error> {
-        let client_id_bytes = auth_payload.client_identifier.as_bytes();
-        let client_id_length = client_id_bytes.len();
-        let client_id_max_length = fields::clientidentifier::MAX_LENGTH;
-
-        if client_id_length > client_id_max_length {
-            return Err(WireError::Oversized(
-                "client_identifier_length",
-                client_id_length,
-                client_id_max_length,
-            ));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zwire::{
+        codec::{bytes::BytesMut, Decoder, Encoder},
+        errors::WireError,
+    };
+
+    // Encoded by the hand-written codec this one replaced
+    #[rustfmt::skip]
+    const BASELINE: [u8; 66] = [
+        0, 0, 0, 0, 0x65, 0x5F, 0x1A, 0x00,
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+        0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+        0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA,
+        0xAA, 0xAA,
+        9, b'c', b'l', b'i', b'e', b'n', b't', b'-', b'0', b'1',
+    ];
+
+    fn payload() -> AuthPayload {
+        AuthPayload {
+            timestamp: 0x655F_1A00,
+            nonce: 0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10,
+            mac: Bytes::from_static(&[0xAA; 32]),
+            client_identifier: ByteStr::from_static("client-01"),
         }
-
-        let total_length = fields::FIXED_PART_LENGTH.checked_add_wire(
-            "FIXED_PART_LENGTH",
-            client_id_length,
-            "client_id_length",
-        )?;
-
-        if total_length > self.max_length {
-            return Err(WireError::Oversized(
-                "total_length",
-                total_length,
-                self.max_length,
-            ));
-        }
-
-        destination.put_single::<fields::timestamp::Wired>(auth_payload.timestamp);
-        destination.put_single::<fields::nonce::Wired>(auth_payload.nonce);
-        destination.put_fixed_bytes::<fields::mac::Wired>(&auth_payload.mac)?;
-        destination.put_length_prefixed_string::<fields::clientidentifier::Wired>(
-            auth_payload.client_identifier,
-        )?;
-
-        Ok(())
     }
-}
 
-impl Decoder for AuthPayloadCodec {
-    type Item = AuthPayload;
-    type Error = WireError;
-
-    fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let Some(client_id_length) = source.peek_at::<fields::clientidentifier::Wired>()?.get()
-        else {
-            return Ok(None);
-        };
+    #[test]
+    fn matches_the_baseline_encoding() {
+        let mut encoded = BytesMut::new();
+        AuthPayloadCodec::default()
+            .encode(payload(), &mut encoded)
+            .unwrap();
+
+        assert_eq!(&encoded[..], BASELINE);
+
+        let mut source = BytesMut::from(&BASELINE[..]);
+        let decoded = AuthPayloadCodec::default()
+            .decode(&mut source)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(decoded.timestamp, payload().timestamp);
+        assert_eq!(decoded.nonce, payload().nonce);
+        assert_eq!(decoded.mac, payload().mac);
+        assert_eq!(decoded.client_identifier, payload().client_identifier);
+        assert!(source.is_empty());
+    }
 
-        let total_length = fields::FIXED_PART_LENGTH.checked_add_wire(
-            "FIXED_PART_LENGTH",
-            client_id_length,
-            "client_id_length",
-        )?;
+    #[test]
+    fn waits_for_the_whole_baseline_record() {
+        for length in 0..BASELINE.len() {
+            let mut source = BytesMut::from(&BASELINE[..length]);
 
-        if total_length > self.max_length {
-            return Err(WireError::Oversized(
-                "total_length",
-                total_length,
-                self.max_length,
-            ));
-        }
-
-        if source.len() < total_length {
-            return Ok(None);
+            assert!(AuthPayloadCodec::default()
+                .decode(&mut source)
+                .unwrap()
+                .is_none());
         }
+    }
 
-        let timestamp = source.take_single_unchecked::<fields::timestamp::Wired>();
-        let nonce = source.take_single_unchecked::<fields::nonce::Wired>();
-        let mac = source.take_fixed_bytes_unchecked::<fields::mac::Wired>();
-        let client_identifier =
-            source.take_length_prefixed_string_unchecked::<fields::clientidentifier::Wired>()?;
+    #[test]
+    fn rejects_identifiers_outside_the_baseline_policy() {
+        let mut source = BytesMut::from(&BASELINE[..]);
+        source[57] = b' ';
 
-        Ok(Some(AuthPayload {
-            client_identifier,
-            timestamp,
-            nonce,
-            mac,
-        }))
+        assert!(matches!(
+            AuthPayloadCodec::default().decode(&mut source),
+            Err(WireError::MalformedString(..))
+        ));
     }
 }
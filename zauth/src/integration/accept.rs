@@ -1,44 +1,37 @@
-use crate::{codec::AuthPayloadCodec, session::AuthSession, AuthMessage, AuthStore, Authenticator};
-use futures::StreamExt;
+use crate::{session::AuthSession, AuthMessage, AuthProtocol, AuthStore, Authenticator};
 use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
-use tokio_util::codec::{Encoder, FramedRead};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::error;
 use zwire::{
     codec::FrameCodec,
     errors::WireError,
     session::{SessionBackend, SessionManager},
-    BytesMut, DecodeFromFrame, Frame,
+    transport::ZwireStream,
+    Frame,
 };
 
-#[allow(clippy::too_many_arguments)]
-async fn perform_auth<S: AsyncWrite + std::marker::Unpin>(
+async fn perform_auth<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
     frame: Option<Frame>,
-    send: &mut S,
+    stream: &mut ZwireStream<R, W>,
     connection_id: usize,
     session_manager: Arc<SessionManager<impl SessionBackend>>,
     authenticator: Arc<Authenticator<impl AuthStore>>,
-    codec_buffer: &mut BytesMut,
-    frame_codec: &mut FrameCodec,
-    auth_payload_codec: &mut AuthPayloadCodec,
 ) -> Result<bool, WireError> {
     let mut auth_status_response = false;
 
     if let Some(frame) = frame {
-        if let Some(auth_payload) = auth_payload_codec.decode_from_frame(frame, codec_buffer)? {
+        if let AuthProtocol::Auth(auth_payload) = AuthProtocol::try_from(frame)? {
             let (auth_status, auth_response_frame) =
-                authenticator.process_auth_payload(&auth_payload.0);
+                authenticator.process_auth_payload(&auth_payload);
 
             if auth_status {
                 session_manager
-                    .authenticate(connection_id, auth_payload.0.client_identifier.to_string());
+                    .authenticate(connection_id, auth_payload.client_identifier.to_string());
 
                 auth_status_response = true;
             }
 
-            frame_codec.encode(auth_response_frame, codec_buffer)?;
-            send.write_all_buf(codec_buffer).await?;
-            codec_buffer.clear();
+            stream.send_frame(auth_response_frame).await?;
         } else {
             error!("Received frame isn't an auth payload");
 
@@ -55,34 +48,24 @@ async fn perform_auth<S: AsyncWrite + std::marker::Unpin>(
             Frame::message_only(AuthMessage::AuthRequired)
         };
 
-        frame_codec.encode(payload, codec_buffer)?;
-        send.write_all_buf(codec_buffer).await?;
-        codec_buffer.clear();
+        stream.send_frame(payload).await?;
     }
 
     Ok(auth_status_response)
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn ensure_auth<S: AsyncWrite + std::marker::Unpin, R: AsyncRead + std::marker::Unpin>(
+async fn ensure_auth<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
     session_manager: Arc<SessionManager<impl SessionBackend>>,
     authenticator: Arc<Authenticator<impl AuthStore>>,
     connection_id: usize,
-    send: &mut S,
-    receive: R,
-    codec_buffer: &mut BytesMut,
-    mut frame_codec: FrameCodec,
-    auth_payload_codec: &mut AuthPayloadCodec,
+    stream: &mut ZwireStream<R, W>,
 ) -> Result<bool, WireError> {
     let mut auth_status = perform_auth(
         None,
-        send,
+        stream,
         connection_id,
         session_manager.clone(),
         authenticator.clone(),
-        codec_buffer,
-        &mut frame_codec,
-        auth_payload_codec,
     )
     .await?;
 
@@ -90,18 +73,13 @@ async fn ensure_auth<S: AsyncWrite + std::marker::Unpin, R: AsyncRead + std::mar
         return Ok(auth_status);
     };
 
-    let mut framed_reader = FramedRead::new(receive, frame_codec);
-
-    if let Some(Ok(frame)) = framed_reader.next().await {
+    if let Ok(Some(frame)) = stream.recv_frame().await {
         auth_status = perform_auth(
             Some(frame),
-            send,
+            stream,
             connection_id,
             session_manager.clone(),
             authenticator.clone(),
-            codec_buffer,
-            &mut frame_codec,
-            auth_payload_codec,
         )
         .await?;
     }
@@ -135,18 +113,14 @@ impl AcceptAuthed for quinn::Endpoint {
             Ok(connecting) => match connecting.await {
                 Err(error) => Err(error),
                 Ok(connection) => {
-                    let (mut send, receive) = connection.open_bi().await?;
-                    let mut codec_buffer = BytesMut::new();
+                    let (send, receive) = connection.open_bi().await?;
+                    let mut stream = ZwireStream::new(receive, send, FrameCodec::default());
 
                     match ensure_auth(
                         session_manager,
                         authenticator,
                         connection.stable_id(),
-                        &mut send,
-                        receive,
-                        &mut codec_buffer,
-                        FrameCodec::default(),
-                        &mut AuthPayloadCodec::default(),
+                        &mut stream,
                     )
                     .await
                     {
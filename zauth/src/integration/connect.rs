@@ -1,89 +1,67 @@
-use crate::{codec::AuthPayloadCodec, AuthMessage, AuthPayload};
-use futures::TryStreamExt;
+use crate::{AuthMessage, AuthPayload, AuthPayloadCodec};
 use std::net::SocketAddr;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
-use tokio::time::{sleep, timeout, Duration};
-use tokio_util::codec::{Encoder, FramedRead};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{sleep, Duration};
 use tracing::{error, warn};
 use zwire::{
-    codec::bytes::ByteStr, codec::FrameCodec, errors::WireError, BytesMut, EncodeIntoFrame, Frame,
+    codec::bytes::ByteStr, codec::FrameCodec, errors::WireError, transport::ZwireStream, Frame,
 };
 
-async fn send_auth_frame<S: AsyncWrite + std::marker::Unpin>(
-    codec_buffer: &mut BytesMut,
-    send: &mut S,
+async fn send_auth_frame<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    stream: &mut ZwireStream<R, W>,
     client_identifier: ByteStr,
     key: &str,
-    frame_codec: &mut FrameCodec,
-    auth_payload_codec: &mut AuthPayloadCodec,
 ) {
     let auth_payload = AuthPayload::new(client_identifier, key).unwrap();
-    let frame = auth_payload_codec
-        .encode_into_frame(auth_payload, AuthMessage::Auth, codec_buffer)
-        .unwrap();
 
-    frame_codec.encode(frame, codec_buffer).unwrap();
-    send.write_all_buf(codec_buffer).await.unwrap();
-    codec_buffer.clear();
+    stream
+        .send::<AuthPayloadCodec>(auth_payload, AuthMessage::Auth)
+        .await
+        .unwrap();
 }
 
-async fn await_auth_response_frame(
-    framed_reader: &mut FramedRead<impl AsyncRead + Unpin, FrameCodec>,
-    timeout_duration: Duration,
+// The stream's recv timeout bounds the wait for each frame, frames of other protocols are skipped
+async fn await_auth_response_frame<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    stream: &mut ZwireStream<R, W>,
 ) -> Result<Option<(AuthMessage, Frame)>, WireError> {
-    let result = timeout(timeout_duration, async {
-        while let Some(frame) = framed_reader.try_next().await? {
-            match AuthMessage::try_from(&frame.message) {
-                Err(_) => continue,
-                Ok(auth_message) => {
+    loop {
+        match stream.recv_frame().await {
+            Ok(Some(frame)) => {
+                if let Ok(auth_message) = AuthMessage::try_from(&frame.message) {
                     return Ok(Some((auth_message, frame)));
                 }
-            };
-        }
-
-        Ok::<Option<(AuthMessage, Frame)>, WireError>(None)
-    })
-    .await;
-
-    match result {
-        Ok(Ok(Some(response))) => Ok(Some(response)),
-        Ok(Ok(None)) => {
-            error!("EOF before auth response was received");
+            }
+            Ok(None) => {
+                error!("EOF before auth response was received");
 
-            Ok(None)
-        }
-        Ok(Err(error)) => Err(error),
-        Err(_elapsed) => {
-            error!("Hit timeout while waiting for auth response");
+                return Ok(None);
+            }
+            Err(WireError::Timeout(..)) => {
+                error!("Hit timeout while waiting for auth response");
 
-            Ok(None)
+                return Ok(None);
+            }
+            Err(error) => return Err(error),
         }
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn ensure_auth<S: AsyncWrite + std::marker::Unpin, R: AsyncRead + std::marker::Unpin>(
+async fn ensure_auth<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
     client_identifier: ByteStr,
     key: &str,
     max_retries: u64,
     retry_cooldown: Duration,
-    mut frame_codec: FrameCodec,
-    auth_payload_codec: &mut AuthPayloadCodec,
-    send: &mut S,
+    frame_codec: FrameCodec,
+    send: &mut W,
     receive: &mut R,
 ) -> Result<bool, quinn::ConnectionError> {
-    let mut codec_buffer = BytesMut::new();
-    let mut framed_reader = FramedRead::new(receive, frame_codec);
-    let mut retries = 0;
-
     const FRAME_RECEIVE_TIMEOUT: u64 = 1;
 
-    match await_auth_response_frame(
-        &mut framed_reader,
-        Duration::from_secs(FRAME_RECEIVE_TIMEOUT),
-    )
-    .await
-    {
+    let mut stream = ZwireStream::new(receive, send, frame_codec)
+        .recv_timeout(Duration::from_secs(FRAME_RECEIVE_TIMEOUT));
+    let mut retries = 0;
+
+    match await_auth_response_frame(&mut stream).await {
         Err(error) => error!("{:#?} | on await_auth_response_frame", error),
         Ok(response) => {
             if let Some((auth_message, _frame)) = response {
@@ -103,22 +81,9 @@ async fn ensure_auth<S: AsyncWrite + std::marker::Unpin, R: AsyncRead + std::mar
 
     // Don't count first try as a retry
     while retries < (max_retries + 1) {
-        send_auth_frame(
-            &mut codec_buffer,
-            send,
-            client_identifier.clone(),
-            key,
-            &mut frame_codec,
-            auth_payload_codec,
-        )
-        .await;
-
-        let auth_status = match await_auth_response_frame(
-            &mut framed_reader,
-            Duration::from_secs(FRAME_RECEIVE_TIMEOUT),
-        )
-        .await
-        {
+        send_auth_frame(&mut stream, client_identifier.clone(), key).await;
+
+        let auth_status = match await_auth_response_frame(&mut stream).await {
             Err(error) => {
                 error!("{:#?} | on await_auth_response_frame", error);
 
@@ -196,7 +161,6 @@ impl ConnectAuthed for quinn::Endpoint {
                         MAX_RETRIES,
                         Duration::from_secs(RETRY_COOLDOWN),
                         FrameCodec::default(),
-                        &mut AuthPayloadCodec::default(),
                         &mut send,
                         &mut receive,
                     )
@@ -7,7 +7,7 @@ mod storage;
 pub mod integration;
 
 pub use authenticator::Authenticator;
-pub use codec::AuthPayloadCodec;
+pub use codec::{AuthPayload, AuthPayloadCodec};
 pub use storage::{memory::InMemoryStore, AuthStore, StorageError};
 
 pub mod __zwire_macros_support {
@@ -18,10 +18,7 @@ use authenticator::auth_mac;
 use hmac::digest::InvalidLength;
 use rand::Rng;
 use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
-use zwire::codec::{
-    bytes::{ByteStr, Bytes},
-    wired::define_message,
-};
+use zwire::codec::{bytes::ByteStr, wired::define_message};
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum ZauthError {
@@ -35,23 +32,15 @@ pub enum ZauthError {
 }
 
 define_message!(
-    AuthMessage,
+    AuthMessage => AuthProtocol,
     {
         AuthRequired = 1,
-        Auth = 2,
+        Auth = 2 => AuthPayload,
         AuthValid = 3,
         AuthInvalid = 4,
     }
 );
 
-#[derive(Debug, Clone)]
-pub struct AuthPayload {
-    pub client_identifier: ByteStr,
-    pub timestamp: u64,
-    pub nonce: u128,
-    pub mac: Bytes,
-}
-
 impl AuthPayload {
     pub fn new(client_identifier: ByteStr, key: &str) -> Result<Self, ZauthError> {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
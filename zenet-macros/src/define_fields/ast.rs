@@ -1,4 +1,5 @@
-use super::types::known_type_size;
+use super::types::{is_fixed_width_number, is_value_only_type, is_varint_type, known_type_size};
+use proc_macro2::Span;
 use syn::{
     parse::{Parse, ParseStream},
     Ident, LitInt, Token, Type,
@@ -8,187 +9,652 @@ use syn::{
 pub enum FieldKind {
     LengthPrefix,
     Fixed,
-    LengthPrefixString { policy_variant: Ident },
+    // The policy is a path to a WiredStringPolicy, or the bare name of a built-in one
+    LengthPrefixString { policy: Box<Type> },
+    // [count prefix] | [element]..., the element is one of the other kinds
+    Repeated { max_count: usize },
+    // A record of another codec embedded as is, it has to have a fixed size
+    Nested,
+    // [length][record...]
+    LengthPrefixNested,
+    // The bitmap generated in front of a table with optional fields
+    Presence,
+    // The schema version leading a versioned table
+    Version { version: u8 },
+    // [length][tag][length][value]..., the block of tagged fields trailing a table
+    Extensions,
 }
 
+#[derive(Clone)]
 pub struct FieldDef {
     pub name: Ident,
     pub ty: Type,
-    pub offset: usize,
+    // `None` behind a varint, whose size is only known from the bytes
+    pub offset: Option<usize>,
     pub kind: FieldKind,
     pub max_length: Option<usize>,
+    pub presence_bit: Option<u32>,
+    // Tagged fields travel in the extensions block instead of the table itself
+    pub tag: Option<u32>,
+    pub element: Option<Box<FieldDef>>,
+    // The codec of nested fields
+    pub record: Option<Type>,
+    // Fixed size records in front of the field, their size is added to `offset`
+    pub offset_records: Vec<Ident>,
 }
 
 pub struct DefineFieldsInput {
     pub fields: Vec<FieldDef>,
 }
 
-// Parse either a type or an integer treated as [u8; N]
+// `version = <u8>, extensions = <max_length>`, both optional. Versioned tables lead with their
+// version, tables with extensions end with a block of tagged fields peers skip when they don't
+// know a tag
+#[derive(Default)]
+pub struct TableOptions {
+    pub version: Option<u8>,
+    pub extensions: Option<usize>,
+}
+
+impl TableOptions {
+    pub fn parse_option(&mut self, key: &Ident, value: &LitInt) -> syn::Result<()> {
+        if key == "version" {
+            self.version = Some(value.base10_parse()?);
+        } else if key == "extensions" {
+            self.extensions = Some(value.base10_parse()?);
+        } else {
+            return Err(syn::Error::new(
+                key.span(),
+                format!(
+                    "unknown option `{}` (expected `version` or `extensions`)",
+                    key
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Parse either a type or an integer treated as [u8; N], WiredVarInt is resolved through the
+// support module since the generated field modules don't see the caller's imports
 fn parse_type_or_len_as_type(input: ParseStream) -> syn::Result<Type> {
     if input.peek(LitInt) {
         let lit: LitInt = input.parse()?;
         let ty: Type = syn::parse_quote! { [u8; #lit] };
         Ok(ty)
     } else {
-        input.parse()
+        let ty: Type = input.parse()?;
+
+        if is_varint_type(&ty) {
+            return Ok(syn::parse_quote! { crate::__zwire_macros_support::WiredVarInt });
+        }
+
+        Ok(ty)
     }
 }
 
-impl Parse for DefineFieldsInput {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let mut parsed_fields = Vec::new();
+pub struct FieldSpec {
+    pub ty: Type,
+    pub offset: Option<usize>,
+    pub kind: FieldKind,
+    pub max_length: Option<usize>,
+    pub optional: bool,
+    pub tag: Option<u32>,
+    pub element: Option<Box<FieldSpec>>,
+    pub record: Option<Type>,
+}
 
-        while !input.is_empty() {
-            let content;
-            syn::parenthesized!(content in input);
+// Width of the presence bitmap, one bit per optional field
+const PRESENCE_TYPES: [(u32, &str); 4] = [(8, "u8"), (16, "u16"), (32, "u32"), (64, "u64")];
 
-            let name: Ident = content.parse()?;
-            content.parse::<Token![,]>()?;
+// A trailing `, optional` or `, tag = <n>` after the kind and its arguments
+fn parse_modifier(content: ParseStream, spec: &mut FieldSpec) -> syn::Result<()> {
+    if !content.peek(Token![,]) {
+        return Ok(());
+    }
+
+    content.parse::<Token![,]>()?;
+    let modifier: Ident = content.parse()?;
+
+    if modifier == "optional" {
+        spec.optional = true;
+    } else if modifier == "tag" {
+        content.parse::<Token![=]>()?;
+        let tag: LitInt = content.parse()?;
+
+        spec.tag = Some(tag.base10_parse()?);
+    } else {
+        return Err(syn::Error::new(
+            modifier.span(),
+            format!(
+                "unknown modifier `{}` (expected `optional` or `tag`)",
+                modifier
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+// `<type>, [le|be,] [offset,] <kind>[, <kind arguments>][, optional | tag = <n>]`, shared with
+// the WireCodec field attributes. Numbers are big-endian unless marked `le`
+pub fn parse_field_spec(content: ParseStream) -> syn::Result<FieldSpec> {
+    let mut spec = parse_field_kind(content)?;
+
+    parse_modifier(content, &mut spec)?;
+
+    Ok(spec)
+}
 
-            let ty: Type = parse_type_or_len_as_type(&content)?;
+fn parse_field_kind(content: ParseStream) -> syn::Result<FieldSpec> {
+    let mut ty: Type = parse_type_or_len_as_type(content)?;
+    content.parse::<Token![,]>()?;
+
+    if content.peek(Ident) && content.peek2(Token![,]) {
+        let fork = content.fork();
+        let marker: Ident = fork.parse()?;
+
+        if marker == "le" || marker == "be" {
+            content.parse::<Ident>()?;
             content.parse::<Token![,]>()?;
 
-            let lookahead = content.lookahead1();
-            let mut offset_opt: Option<usize> = None;
-            let kind_ident: Ident;
+            if !is_fixed_width_number(&ty) {
+                return Err(syn::Error::new(
+                    marker.span(),
+                    "`le`/`be` only apply to u8-u128, i8-i128, f32 and f64",
+                ));
+            }
 
-            if lookahead.peek(LitInt) {
-                let offset_lit: LitInt = content.parse()?;
+            if marker == "le" {
+                ty = syn::parse_quote! { crate::__zwire_macros_support::Le<#ty> };
+            }
+        }
+    }
+
+    let lookahead = content.lookahead1();
+    let mut offset: Option<usize> = None;
+    let kind_ident: Ident;
+
+    if lookahead.peek(LitInt) {
+        let offset_lit: LitInt = content.parse()?;
+
+        offset = Some(offset_lit.base10_parse()?);
+        content.parse::<Token![,]>()?;
+        kind_ident = content.parse()?;
+    } else {
+        kind_ident = content.parse()?;
+    }
+
+    let kind_string = kind_ident.to_string();
 
-                offset_opt = Some(offset_lit.base10_parse()?);
-                content.parse::<Token![,]>()?;
-                kind_ident = content.parse()?;
-            } else {
-                kind_ident = content.parse()?;
+    if kind_string != "fixed" && is_value_only_type(&ty) {
+        return Err(syn::Error::new(
+            kind_ident.span(),
+            "floats and bools can't be used as length prefixes",
+        ));
+    }
+
+    match kind_string.as_str() {
+        "fixed" => Ok(FieldSpec {
+            ty,
+            offset,
+            kind: FieldKind::Fixed,
+            max_length: None,
+            optional: false,
+            tag: None,
+            element: None,
+            record: None,
+        }),
+        "length_prefix" => {
+            if !content.peek(Token![,]) {
+                return Err(syn::Error::new(
+                    kind_ident.span(),
+                    "length_prefix requires: <max_length>",
+                ));
             }
 
-            let kind_string = kind_ident.to_string();
+            content.parse::<Token![,]>()?;
+            let lit: LitInt = content.parse()?;
 
-            let kind: FieldKind = match kind_string.as_str() {
-                "fixed" => FieldKind::Fixed,
-                "length_prefix" => FieldKind::LengthPrefix,
-                "length_prefix_string" => {
-                    if !content.peek(Token![,]) {
-                        return Err(syn::Error::new(
-                            kind_ident.span(),
-                            "length_prefix_string requires: <max_length>, <policy_variant>",
-                        ));
-                    }
+            Ok(FieldSpec {
+                ty,
+                offset,
+                kind: FieldKind::LengthPrefix,
+                max_length: Some(lit.base10_parse()?),
+                optional: false,
+                tag: None,
+                element: None,
+                record: None,
+            })
+        }
+        "length_prefix_string" => {
+            if !content.peek(Token![,]) {
+                return Err(syn::Error::new(
+                    kind_ident.span(),
+                    "length_prefix_string requires: <max_length>, <policy>",
+                ));
+            }
 
-                    content.parse::<Token![,]>()?;
-                    let max_len_lit: LitInt = content.parse()?;
-                    let max_length_val = max_len_lit.base10_parse::<usize>()?;
+            content.parse::<Token![,]>()?;
+            let max_len_lit: LitInt = content.parse()?;
+            let max_length_val = max_len_lit.base10_parse::<usize>()?;
 
-                    content.parse::<Token![,]>()?;
-                    let policy_variant: Ident = content.parse()?;
+            content.parse::<Token![,]>()?;
+            let policy: Type = content.parse()?;
 
-                    parsed_fields.push((
-                        name,
-                        ty,
-                        offset_opt,
-                        FieldKind::LengthPrefixString { policy_variant },
-                        Some(max_length_val),
-                    ));
+            Ok(FieldSpec {
+                ty,
+                offset,
+                kind: FieldKind::LengthPrefixString {
+                    policy: Box::new(policy),
+                },
+                max_length: Some(max_length_val),
+                optional: false,
+                tag: None,
+                element: None,
+                record: None,
+            })
+        }
+        "repeated" => {
+            if !content.peek(Token![,]) {
+                return Err(syn::Error::new(
+                    kind_ident.span(),
+                    "repeated requires: <max_count>, (<element spec>)",
+                ));
+            }
 
-                    let _ = input.parse::<Token![,]>();
+            content.parse::<Token![,]>()?;
+            let max_count_lit: LitInt = content.parse()?;
+            let max_count = max_count_lit.base10_parse::<usize>()?;
 
-                    continue;
-                }
-                other => {
-                    return Err(syn::Error::new(
-                        kind_ident.span(),
-                        format!(
-                            "unknown field kind `{}` (expected `fixed`, `length_prefix`, or `length_prefix_string`)",
-                            other
-                        ),
-                    ));
-                }
-            };
+            content.parse::<Token![,]>()?;
+            let element_content;
+            let parentheses = syn::parenthesized!(element_content in content);
+            let element = parse_field_spec(&element_content)?;
 
-            let mut max_length: Option<usize> = None;
+            if element.offset.is_some()
+                || element.optional
+                || element.tag.is_some()
+                || element.record.is_some()
+                || matches!(element.kind, FieldKind::Repeated { .. })
+            {
+                return Err(syn::Error::new(
+                    parentheses.span.join(),
+                    "elements can't have an offset, a modifier, be nested or repeated themselves",
+                ));
+            }
 
-            match kind {
-                FieldKind::LengthPrefix => {
-                    if !content.peek(Token![,]) {
-                        return Err(syn::Error::new(
-                            kind_ident.span(),
-                            "length_prefix requires: <max_length>",
-                        ));
-                    }
+            Ok(FieldSpec {
+                ty,
+                offset,
+                kind: FieldKind::Repeated { max_count },
+                max_length: None,
+                optional: false,
+                tag: None,
+                element: Some(Box::new(element)),
+                record: None,
+            })
+        }
+        // `<codec>, nested` or `<length prefix>, nested, <codec>`
+        "nested" => {
+            let fork = content.fork();
+            let is_modifier = fork.parse::<Token![,]>().is_ok()
+                && fork
+                    .parse::<Ident>()
+                    .is_ok_and(|ident| ident == "optional" || ident == "tag");
 
-                    content.parse::<Token![,]>()?;
-                    let lit: LitInt = content.parse()?;
+            if !content.peek(Token![,]) || is_modifier {
+                return Ok(FieldSpec {
+                    ty: ty.clone(),
+                    offset,
+                    kind: FieldKind::Nested,
+                    max_length: None,
+                    optional: false,
+                    tag: None,
+                    element: None,
+                    record: Some(ty),
+                });
+            }
 
-                    max_length = Some(lit.base10_parse()?);
-                }
-                FieldKind::Fixed => {
-                    if content.peek(Token![,]) {
-                        return Err(syn::Error::new(
-                            content.span(),
-                            "unexpected extra argument after `fixed`",
-                        ));
-                    }
-                }
-                FieldKind::LengthPrefixString { .. } => {
-                    unreachable!("handled earlier");
-                }
+            content.parse::<Token![,]>()?;
+            let record: Type = content.parse()?;
+
+            Ok(FieldSpec {
+                ty,
+                offset,
+                kind: FieldKind::LengthPrefixNested,
+                max_length: None,
+                optional: false,
+                tag: None,
+                element: None,
+                record: Some(record),
+            })
+        }
+        other => Err(syn::Error::new(
+            kind_ident.span(),
+            format!(
+                "unknown field kind `{}` (expected `fixed`, `length_prefix`, `length_prefix_string`, `repeated` or `nested`)",
+                other
+            ),
+        )),
+    }
+}
+
+fn check_max_length(name: &Ident, kind: &FieldKind, max_length: Option<usize>) -> syn::Result<()> {
+    match kind {
+        FieldKind::LengthPrefix | FieldKind::LengthPrefixString { .. } => {
+            if max_length.is_none() {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "missing max_length for length-prefix variant",
+                ));
+            }
+        }
+        // An extensions block without an explicit size fits exactly the tagged fields it knows
+        FieldKind::Extensions => {}
+        FieldKind::Fixed
+        | FieldKind::Presence
+        | FieldKind::Version { .. }
+        | FieldKind::Repeated { .. }
+        | FieldKind::Nested
+        | FieldKind::LengthPrefixNested => {
+            if max_length.is_some() {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "fixed field should not have max_length",
+                ));
             }
+        }
+    }
 
-            parsed_fields.push((name, ty, offset_opt, kind, max_length));
-            let _ = input.parse::<Token![,]>();
+    Ok(())
+}
+
+// Assigns offsets in declaration order unless a field gives an explicit one. Versioned tables get
+// their version as the first field and tables with optional fields a presence bitmap after it,
+// later offsets assume every field is present. Fields behind a varint have no offset until one is
+// given explicitly. Tagged fields go into the extensions block at the end, so they don't have an
+// offset
+pub fn resolve_fields(
+    parsed_fields: Vec<(Ident, FieldSpec)>,
+    options: &TableOptions,
+) -> syn::Result<Vec<FieldDef>> {
+    let span = parsed_fields
+        .first()
+        .map_or_else(Span::call_site, |(name, _)| name.span());
+    let optional_count = parsed_fields
+        .iter()
+        .filter(|(_, spec)| spec.optional)
+        .count();
+    let has_extensions =
+        options.extensions.is_some() || parsed_fields.iter().any(|(_, spec)| spec.tag.is_some());
+
+    for (reserved, used) in [
+        ("version", options.version.is_some()),
+        ("presence", optional_count > 0),
+        ("extensions", has_extensions),
+    ] {
+        if !used {
+            continue;
+        }
+
+        if let Some((name, _)) = parsed_fields
+            .iter()
+            .find(|(name, _)| name.to_string().eq_ignore_ascii_case(reserved))
+        {
+            return Err(syn::Error::new(
+                name.span(),
+                format!(
+                    "`{}` is reserved for the generated field of that name",
+                    reserved
+                ),
+            ));
         }
+    }
+
+    let mut current_offset: usize = 0;
+    let mut fields = Vec::with_capacity(parsed_fields.len() + 3);
+
+    if let Some(version) = options.version {
+        fields.push(FieldDef {
+            name: Ident::new("Version", span),
+            ty: syn::parse_quote! { u8 },
+            offset: Some(0),
+            kind: FieldKind::Version { version },
+            max_length: None,
+            presence_bit: None,
+            tag: None,
+            element: None,
+            record: None,
+            offset_records: Vec::new(),
+        });
+
+        current_offset = 1;
+    }
+
+    if optional_count > 0 {
+        let Some((_, presence_type)) = PRESENCE_TYPES
+            .iter()
+            .find(|(bits, _)| optional_count <= *bits as usize)
+        else {
+            return Err(syn::Error::new(span, "at most 64 fields can be optional"));
+        };
+
+        let ty: Type = syn::parse_str(presence_type)?;
+        let size = known_type_size(&ty).expect("presence types have a known size");
 
-        let mut current_offset: usize = 0;
-        let mut fields = Vec::with_capacity(parsed_fields.len());
+        fields.push(FieldDef {
+            name: Ident::new("Presence", span),
+            ty,
+            offset: Some(current_offset),
+            kind: FieldKind::Presence,
+            max_length: None,
+            presence_bit: None,
+            tag: None,
+            element: None,
+            record: None,
+            offset_records: Vec::new(),
+        });
 
-        for (name, ty, offset_opt, kind, max_length) in parsed_fields {
-            let size = known_type_size(&ty).ok_or_else(|| {
-                syn::Error::new(
+        current_offset += size;
+    }
+
+    let mut next_presence_bit: u32 = 0;
+    let mut behind_varint = false;
+    let mut offset_records: Vec<Ident> = Vec::new();
+    let mut tags: Vec<u32> = Vec::new();
+
+    for (name, spec) in parsed_fields {
+        let FieldSpec {
+            ty,
+            offset: offset_opt,
+            kind,
+            max_length,
+            optional,
+            tag,
+            element,
+            record,
+        } = spec;
+
+        // A fixed size record's size is only known once its codec is compiled
+        let size = if matches!(kind, FieldKind::Nested) || is_varint_type(&ty) {
+            Some(0)
+        } else {
+            known_type_size(&ty)
+        };
+
+        let size = size.ok_or_else(|| {
+            syn::Error::new(
+                name.span(),
+                "automatic offsets only support integers, floats, bool, WiredVarInt, [u8; N] and nested records; provide explicit offset",
+            )
+        })?;
+
+        let offset = match (tag, offset_opt) {
+            (Some(tag), _) if tags.contains(&tag) => {
+                return Err(syn::Error::new(
                     name.span(),
-                    "automatic offsets only support u8/u16/u32/u64/u128 and [u8; N]; provide explicit offset",
-                )
-            })?;
-
-            let offset = match offset_opt {
-                Some(explicit) => {
-                    current_offset = explicit + size;
-                    explicit
-                }
-                None => {
-                    let auto = current_offset;
-                    current_offset += size;
-                    auto
-                }
-            };
-
-            match kind {
-                FieldKind::LengthPrefix | FieldKind::LengthPrefixString { .. } => {
-                    if max_length.is_none() {
-                        return Err(syn::Error::new(
-                            name.span(),
-                            "missing max_length for length-prefix variant",
-                        ));
-                    }
-                }
-                FieldKind::Fixed => {
-                    if max_length.is_some() {
-                        return Err(syn::Error::new(
-                            name.span(),
-                            "fixed field should not have max_length",
-                        ));
-                    }
+                    format!("tag {} is used by another field", tag),
+                ));
+            }
+            (Some(_), Some(_)) => {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "tagged fields live in the extensions block and can't have an offset",
+                ));
+            }
+            (Some(tag), None) => {
+                tags.push(tag);
+                0
+            }
+            (None, _) if !tags.is_empty() => {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "tagged fields have to come after every other field",
+                ));
+            }
+            (None, Some(_)) if optional_count > 0 => {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "explicit offsets can't be combined with optional fields",
+                ));
+            }
+            (None, Some(explicit)) => {
+                offset_records.clear();
+                behind_varint = false;
+                current_offset = explicit + size;
+                explicit
+            }
+            (None, None) => {
+                let auto = current_offset;
+                current_offset += size;
+                auto
+            }
+        };
+        let offset = (!behind_varint || tag.is_some()).then_some(offset);
+
+        if tag.is_some() && optional {
+            return Err(syn::Error::new(
+                name.span(),
+                "tagged fields are optional already",
+            ));
+        }
+
+        check_max_length(&name, &kind, max_length)?;
+
+        // Elements report errors under the name of the list they're in
+        let element = match element {
+            Some(element) => {
+                check_max_length(&name, &element.kind, element.max_length)?;
+
+                Some(Box::new(FieldDef {
+                    name: name.clone(),
+                    ty: element.ty,
+                    offset: Some(0),
+                    kind: element.kind,
+                    max_length: element.max_length,
+                    presence_bit: None,
+                    tag: None,
+                    element: None,
+                    record: None,
+                    offset_records: Vec::new(),
+                }))
+            }
+            None => None,
+        };
+
+        let presence_bit = optional.then(|| {
+            let bit = next_presence_bit;
+            next_presence_bit += 1;
+            bit
+        });
+
+        let field_offset_records = if tag.is_some() {
+            Vec::new()
+        } else {
+            offset_records.clone()
+        };
+
+        if is_varint_type(&ty) && tag.is_none() {
+            behind_varint = true;
+        }
+
+        if matches!(kind, FieldKind::Nested) && tag.is_none() {
+            offset_records.push(Ident::new(&name.to_string().to_lowercase(), name.span()));
+        }
+
+        fields.push(FieldDef {
+            name,
+            ty,
+            offset,
+            kind,
+            max_length,
+            presence_bit,
+            tag,
+            element,
+            record,
+            offset_records: field_offset_records,
+        });
+    }
+
+    // Present even without tagged fields, a table reserving the block today can grow tomorrow
+    if has_extensions {
+        fields.push(FieldDef {
+            name: Ident::new("Extensions", span),
+            ty: syn::parse_quote! { crate::__zwire_macros_support::WiredVarInt },
+            offset: (!behind_varint).then_some(current_offset),
+            kind: FieldKind::Extensions,
+            max_length: options.extensions,
+            presence_bit: None,
+            tag: None,
+            element: None,
+            record: None,
+            offset_records,
+        });
+    }
+
+    Ok(fields)
+}
+
+impl Parse for DefineFieldsInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut options = TableOptions::default();
+
+        // Table options come first and end with a `;`
+        if input.peek(Ident) {
+            loop {
+                let key: Ident = input.parse()?;
+                input.parse::<Token![=]>()?;
+                let value: LitInt = input.parse()?;
+
+                options.parse_option(&key, &value)?;
+
+                if input.parse::<Token![;]>().is_ok() {
+                    break;
                 }
+
+                input.parse::<Token![,]>()?;
             }
+        }
 
-            fields.push(FieldDef {
-                name,
-                ty,
-                offset,
-                kind,
-                max_length,
-            });
+        let mut parsed_fields = Vec::new();
+
+        while !input.is_empty() {
+            let content;
+            syn::parenthesized!(content in input);
+
+            let name: Ident = content.parse()?;
+            content.parse::<Token![,]>()?;
+
+            let spec = parse_field_spec(&content)?;
+
+            parsed_fields.push((name, spec));
+            let _ = input.parse::<Token![,]>();
         }
 
-        Ok(DefineFieldsInput { fields })
+        Ok(DefineFieldsInput {
+            fields: resolve_fields(parsed_fields, &options)?,
+        })
     }
 }
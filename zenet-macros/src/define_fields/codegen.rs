@@ -1,154 +1,579 @@
 use super::{
-    ast::{DefineFieldsInput, FieldKind},
-    types::is_u8_array_type,
+    ast::{DefineFieldsInput, FieldDef, FieldKind},
+    types::{is_u8_array_type, rebase_string_policy, rebase_type},
 };
 use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
-use syn::Ident;
+use quote::{format_ident, quote};
+use syn::{Ident, Type};
 
 pub fn expand_define_fields(input: DefineFieldsInput) -> TokenStream2 {
-    let fields = input.fields;
+    expand_fields_module(&format_ident!("fields"), &input.fields)
+}
 
-    // fixed prefix sizes
-    let fixed_length_terms = fields.iter().map(|field| {
-        if let Some(n) = is_u8_array_type(&field.ty) {
-            quote! { #n }
-        } else {
-            let ty = &field.ty;
-            quote! { <#ty as crate::__zwire_macros_support::WiredInt>::SIZE }
-        }
-    });
+pub fn expand_fields_module(module_name: &Ident, fields: &[FieldDef]) -> TokenStream2 {
+    let presence_ty = fields
+        .iter()
+        .find(|field| matches!(field.kind, FieldKind::Presence))
+        .map(|field| &field.ty);
 
-    // sum of MAX_LENGTH for variable-length fields
-    let max_length_terms = fields.iter().filter_map(|field| match field.kind {
-        FieldKind::LengthPrefix | FieldKind::LengthPrefixString { .. } => {
-            let module_ident =
-                Ident::new(&field.name.to_string().to_lowercase(), field.name.span());
-            Some(quote! { #module_ident::MAX_LENGTH })
-        }
-        FieldKind::Fixed => None,
+    // Tagged fields only count through the extensions block
+    let table_fields: Vec<&FieldDef> = fields.iter().filter(|field| field.tag.is_none()).collect();
+    let tagged_fields: Vec<&FieldDef> = fields.iter().filter(|field| field.tag.is_some()).collect();
+
+    // fixed prefix sizes, varints count with their minimum size and optional fields not at all
+    let fixed_length_terms = table_fields
+        .iter()
+        .filter(|field| field.presence_bit.is_none())
+        .map(|field| {
+            if matches!(field.kind, FieldKind::Nested) {
+                let module_ident = module_ident(field);
+                quote! { #module_ident::MAX_LENGTH }
+            } else if let Some(n) = is_u8_array_type(&field.ty) {
+                quote! { #n }
+            } else {
+                let ty = &field.ty;
+                quote! { <#ty as crate::__zwire_macros_support::WiredInt>::MIN_SIZE }
+            }
+        });
+
+    let max_length_terms = table_fields
+        .iter()
+        .map(|field| max_length_term(field, quote! {}));
+
+    let version_item = fields.iter().find_map(|field| match field.kind {
+        FieldKind::Version { version } => Some(quote! {
+            // The schema version this side encodes, decoding accepts any
+            pub const VERSION: u8 = #version;
+        }),
+        _ => None,
     });
 
     let fields_modules = fields.iter().map(|field| {
-        let module_ident = Ident::new(&field.name.to_string().to_lowercase(), field.name.span());
+        let module_ident = module_ident(field);
         let ty = &field.ty;
-        let offset_value = field.offset;
-        let name_str = field.name.to_string().to_lowercase();
 
-        let is_lp = matches!(field.kind, FieldKind::LengthPrefix);
-        let policy_variant_opt = match &field.kind {
-            FieldKind::LengthPrefixString { policy_variant } => Some(policy_variant),
+        // Optional fields know their bit, the bitmap knows every bit in use
+        let extra_items = match (field.presence_bit, presence_ty, field.tag) {
+            (Some(bit), Some(presence_ty), _) => Some(quote! {
+                pub const PRESENCE_BIT: #presence_ty = 1 << #bit;
+            }),
+            (_, _, Some(tag)) => Some(expand_tagged_items(field, &module_ident, tag)),
+            _ if matches!(field.kind, FieldKind::Presence) => {
+                let bits = fields.iter().filter_map(|field| field.presence_bit);
+
+                Some(quote! {
+                    pub const KNOWN_BITS: #ty = 0 #( | 1 << #bits )*;
+                })
+            }
+            // Every entry costs a tag and a length on top of its value
+            _ if matches!(field.kind, FieldKind::Extensions) => {
+                let entry_lengths = tagged_fields
+                    .iter()
+                    .map(|field| max_length_term(field, quote! { super:: }));
+
+                Some(quote! {
+                    pub const KNOWN_LENGTH: usize = 0 #(
+                        + 2 * <crate::__zwire_macros_support::WiredVarInt as crate::__zwire_macros_support::WiredInt>::SIZE
+                        + #entry_lengths
+                    )*;
+
+                    const _: () = assert!(
+                        KNOWN_LENGTH <= MAX_LENGTH,
+                        "the extensions block has to fit every tagged field",
+                    );
+                })
+            }
             _ => None,
         };
 
-        let wired_field_impl_item = quote! {
-            impl crate::__zwire_macros_support::WiredField for Wired {
-                const FIELD_NAME: &'static str = #name_str;
-                const OFFSET: usize = #offset_value;
+        expand_field_module(field, &module_ident, extra_items)
+    });
+
+    let layout = expand_layout(&table_fields);
+
+    quote! {
+        pub mod #module_name {
+            pub const FIXED_PART_LENGTH: usize = 0 #( + #fixed_length_terms )* ;
+            pub const MAX_LENGTH: usize = 0 #( + #max_length_terms )* ;
+            #version_item
+            #layout
+            #(#fields_modules)*
+        }
+    }
+}
+
+// Largest encoding of a field, varints count with their maximum size. `scope` leads the paths
+// to sibling field modules
+fn max_length_term(field: &FieldDef, scope: TokenStream2) -> TokenStream2 {
+    let module_ident = module_ident(field);
+    let ty = &field.ty;
+
+    match field.kind {
+        FieldKind::Nested => quote! { #scope #module_ident::MAX_LENGTH },
+        FieldKind::LengthPrefix
+        | FieldKind::LengthPrefixString { .. }
+        | FieldKind::Repeated { .. }
+        | FieldKind::LengthPrefixNested
+        | FieldKind::Extensions => quote! {
+            (<#ty as crate::__zwire_macros_support::WiredInt>::SIZE + #scope #module_ident::MAX_LENGTH)
+        },
+        FieldKind::Fixed | FieldKind::Presence | FieldKind::Version { .. } => {
+            match is_u8_array_type(ty) {
+                Some(n) => quote! { #n },
+                None => quote! { <#ty as crate::__zwire_macros_support::WiredInt>::SIZE },
             }
-        };
+        }
+    }
+}
 
-        let (max_length_item_pub, max_length_item) = match field.kind {
-            FieldKind::LengthPrefix | FieldKind::LengthPrefixString { .. } => {
-                let max_length = field.max_length.expect("parser guarantees max_length");
-                (
-                    Some(quote! { pub const MAX_LENGTH: usize = #max_length; }),
-                    Some(quote! { const MAX_LENGTH: usize = #max_length; }),
-                )
+// A tagged field's tag, and how to tell whether its value in an extension entry is whole
+fn expand_tagged_items(field: &FieldDef, module_ident: &Ident, tag: u32) -> TokenStream2 {
+    let measure = measure_field(field);
+
+    quote! {
+        pub const TAG: crate::__zwire_macros_support::VarInt =
+            crate::__zwire_macros_support::VarInt::from_u32(#tag);
+
+        // Size of the value at the start of `source`, `None` if it's cut short
+        pub fn measure(
+            source: &[u8],
+        ) -> Result<Option<usize>, crate::__zwire_macros_support::WireError> {
+            #[allow(unused_imports)]
+            use crate::__zwire_macros_support::{
+                CheckedAddWire, WireError, WiredFixedBytes, WiredInt, WiredLengthPrefixed,
+                WiredNested, WiredRecord, WiredRepeated,
+            };
+            use super::#module_ident;
+
+            let mut cursor: usize = 0;
+
+            #measure
+
+            if source.len() < cursor {
+                return Ok(None);
             }
-            FieldKind::Fixed => (None, None),
-        };
 
-        if let Some(length) = is_u8_array_type(ty) {
-            return quote! {
-                pub mod #module_ident {
-                    pub struct Wired;
+            Ok(Some(cursor))
+        }
+    }
+}
+
+// Walks the fields in order without consuming anything, a field's offset depends on the actual
+// size of everything before it
+fn expand_layout(fields: &[&FieldDef]) -> TokenStream2 {
+    let measure = fields.iter().map(|field| {
+        let measure = measure_field(field);
+
+        if field.presence_bit.is_none() {
+            return measure;
+        }
+
+        let module_ident = module_ident(field);
+
+        quote! {
+            if presence & #module_ident::PRESENCE_BIT != 0 {
+                #measure
+            }
+        }
+    });
+
+    quote! {
+        pub struct Layout;
+
+        impl crate::__zwire_macros_support::WiredLayout for Layout {
+            const FIXED_PART_LENGTH: usize = FIXED_PART_LENGTH;
+            const MAX_LENGTH: usize = MAX_LENGTH;
 
-                    #wired_field_impl_item
+            #[allow(unused_variables)]
+            fn measure(
+                source: &[u8],
+                max_length: usize,
+            ) -> Result<Option<usize>, crate::__zwire_macros_support::WireError> {
+                #[allow(unused_imports)]
+                use crate::__zwire_macros_support::{
+                    CheckedAddWire, WireError, WiredFixedBytes, WiredInt, WiredLengthPrefixed,
+                    WiredNested, WiredRecord, WiredRepeated,
+                };
 
-                    impl crate::__zwire_macros_support::WiredFixedBytes for Wired {
-                        const LENGTH: usize = #length;
-                        type Output = crate::__zwire_macros_support::Bytes;
+                let mut cursor: usize = 0;
 
-                        #[inline]
-                        fn from_bytes(bytes: crate::__zwire_macros_support::Bytes) -> Self::Output {
-                            bytes
-                        }
+                #(
+                    #measure
+
+                    if cursor > max_length {
+                        return Err(WireError::Oversized("total_length", cursor, max_length));
                     }
+                )*
 
-                    #max_length_item_pub
+                if source.len() < cursor {
+                    return Ok(None);
                 }
+
+                Ok(Some(cursor))
+            }
+        }
+    }
+}
+
+fn measure_field(field: &FieldDef) -> TokenStream2 {
+    let module_ident = module_ident(field);
+    let wired = quote! { #module_ident::Wired };
+    let name = module_ident.to_string();
+
+    match &field.kind {
+        FieldKind::Presence => quote! {
+            let Some(presence) = source.get(cursor..).and_then(<#wired as WiredInt>::read_raw)
+            else {
+                return Ok(None);
+            };
+
+            if presence & !#module_ident::KNOWN_BITS != 0 {
+                return Err(WireError::UnknownPresenceBits(#name, u64::from(presence)));
+            }
+
+            cursor = cursor.checked_add_wire("cursor", <#wired as WiredInt>::SIZE, #name)?;
+        },
+        FieldKind::Fixed if is_u8_array_type(&field.ty).is_some() => quote! {
+            cursor = cursor.checked_add_wire("cursor", <#wired as WiredFixedBytes>::LENGTH, #name)?;
+        },
+        FieldKind::Fixed | FieldKind::Version { .. } => quote! {
+            let Some(value) = source.get(cursor..) else {
+                return Ok(None);
+            };
+            let Some(size) = <#wired as WiredInt>::encoded_size(value) else {
+                return Ok(None);
+            };
+            <#wired as WiredInt>::check(value, #name)?;
+            cursor = cursor.checked_add_wire("cursor", size, #name)?;
+        },
+        FieldKind::Repeated { .. } => quote! {
+            let Some(size) = source
+                .get(cursor..)
+                .map(<#wired as WiredRepeated>::measure)
+                .transpose()?
+                .flatten()
+            else {
+                return Ok(None);
+            };
+            cursor = cursor.checked_add_wire("cursor", size, #name)?;
+        },
+        FieldKind::Nested => quote! {
+            cursor = cursor.checked_add_wire(
+                "cursor",
+                <<#wired as WiredNested>::Codec as WiredRecord>::MAX_LENGTH,
+                #name,
+            )?;
+        },
+        FieldKind::LengthPrefix
+        | FieldKind::LengthPrefixString { .. }
+        | FieldKind::LengthPrefixNested
+        | FieldKind::Extensions => quote! {
+            let Some(size) = source
+                .get(cursor..)
+                .and_then(<<#wired as WiredLengthPrefixed>::LengthPrefix as WiredInt>::encoded_size)
+            else {
+                return Ok(None);
+            };
+            let header_end = cursor.checked_add_wire("cursor", size, #name)?;
+
+            let Some(prefix) = source.get(cursor..header_end) else {
+                return Ok(None);
             };
+            let Some(payload_length) =
+                <<#wired as WiredLengthPrefixed>::LengthPrefix as WiredInt>::read(prefix, #name)?
+            else {
+                return Ok(None);
+            };
+            let max_payload_length = <#wired as WiredLengthPrefixed>::MAX_LENGTH;
+
+            if payload_length > max_payload_length {
+                return Err(WireError::Oversized(#name, payload_length, max_payload_length));
+            }
+
+            cursor = header_end.checked_add_wire("cursor", payload_length, #name)?;
+        },
+    }
+}
+
+fn module_ident(field: &FieldDef) -> Ident {
+    Ident::new(&field.name.to_string().to_lowercase(), field.name.span())
+}
+
+fn expand_field_module(
+    field: &FieldDef,
+    module_ident: &Ident,
+    extra_items: Option<TokenStream2>,
+) -> TokenStream2 {
+    let ty = &field.ty;
+    let offset_value = field.offset;
+    let name_str = field.name.to_string().to_lowercase();
+
+    let is_lp = matches!(
+        field.kind,
+        FieldKind::LengthPrefix | FieldKind::LengthPrefixNested | FieldKind::Extensions
+    );
+    let policy_opt = match &field.kind {
+        FieldKind::LengthPrefixString { policy } => Some(rebase_string_policy(policy, 2)),
+        _ => None,
+    };
+
+    let offset_records = &field.offset_records;
+
+    // Fields behind a varint fail to compile once their OFFSET is used, e.g. by peek_at
+    let offset_item = match offset_value {
+        Some(offset_value) => quote! {
+            const OFFSET: usize = #offset_value #( + super::#offset_records::MAX_LENGTH )*;
+        },
+        None => {
+            let message = format!(
+                "`{}` comes after a varint and has no static offset, use peek_layout",
+                name_str
+            );
+
+            quote! { const OFFSET: usize = panic!(#message); }
+        }
+    };
+
+    let wired_field_impl_item = quote! {
+        impl crate::__zwire_macros_support::WiredField for Wired {
+            const FIELD_NAME: &'static str = #name_str;
+            #offset_item
         }
+    };
 
-        let length_field_impl = if is_lp || policy_variant_opt.is_some() {
+    let (max_length_item_pub, max_length_item) = match field.kind {
+        FieldKind::LengthPrefix | FieldKind::LengthPrefixString { .. } => {
+            let max_length = field.max_length.expect("parser guarantees max_length");
+            (
+                Some(quote! { pub const MAX_LENGTH: usize = #max_length; }),
+                Some(quote! { const MAX_LENGTH: usize = #max_length; }),
+            )
+        }
+        FieldKind::LengthPrefixNested => (
             Some(quote! {
-                impl crate::__zwire_macros_support::WiredLengthPrefixed for Wired {
-                    type LengthPrefix = #ty;
-                    #max_length_item
-                }
-            })
-        } else {
-            None
-        };
+                pub const MAX_LENGTH: usize =
+                    <Codec as crate::__zwire_macros_support::WiredRecord>::MAX_LENGTH;
+            }),
+            Some(quote! { const MAX_LENGTH: usize = MAX_LENGTH; }),
+        ),
+        FieldKind::Extensions => {
+            let max_length = match field.max_length {
+                Some(max_length) => quote! { #max_length },
+                None => quote! { KNOWN_LENGTH },
+            };
 
-        let wired_string_impl = policy_variant_opt.map(|policy_variant| quote! {
-            impl crate::__zwire_macros_support::WiredString for Wired {
-                type Inner = Wired;
+            (
+                Some(quote! { pub const MAX_LENGTH: usize = #max_length; }),
+                Some(quote! { const MAX_LENGTH: usize = MAX_LENGTH; }),
+            )
+        }
+        FieldKind::Fixed
+        | FieldKind::Presence
+        | FieldKind::Version { .. }
+        | FieldKind::Repeated { .. }
+        | FieldKind::Nested => (None, None),
+    };
 
-                const POLICY: crate::__zwire_macros_support::WiredStringPolicyKind =
-                    crate::__zwire_macros_support::WiredStringPolicyKind::#policy_variant;
-            }
-        });
+    // Field modules sit two levels below the caller, whose imports the codec path is written for
+    let nested_impl = field.record.as_ref().map(|record| {
+        let record = rebase_type(record, 2);
 
         quote! {
+            pub type Codec = #record;
+
+            impl crate::__zwire_macros_support::WiredNested for Wired {
+                type Codec = Codec;
+            }
+        }
+    });
+
+    if matches!(field.kind, FieldKind::Nested) {
+        return quote! {
             pub mod #module_ident {
-                pub struct Wired(pub #ty);
+                pub struct Wired;
 
                 #wired_field_impl_item
 
-                use crate::__zwire_macros_support::{WiredInt, WireError};
+                #nested_impl
 
-                impl WiredInt for Wired {
-                    type Int = #ty;
-                    type ByteArray = <#ty as WiredInt>::ByteArray;
+                pub const MAX_LENGTH: usize =
+                    <Codec as crate::__zwire_macros_support::WiredRecord>::MAX_LENGTH;
 
-                    const MAX: usize = <#ty as WiredInt>::MAX;
-                    const SIZE: usize = <#ty as WiredInt>::SIZE;
+                const _: () = assert!(
+                    <Codec as crate::__zwire_macros_support::WiredRecord>::FIXED_PART_LENGTH
+                        == MAX_LENGTH,
+                    "records without a length prefix need a fixed size, use `<length prefix>, nested, <codec>`",
+                );
 
-                    fn read_raw_unchecked(source: &[u8]) -> Self::Int {
-                        <#ty as WiredInt>::read_raw_unchecked(source)
-                    }
+                #extra_items
+            }
+        };
+    }
 
-                    fn read_unchecked(source: &[u8], field_name: &'static str) -> Result<usize, WireError> {
-                        <#ty as WiredInt>::read_unchecked(source, field_name)
-                    }
+    if let (FieldKind::Repeated { max_count }, Some(element)) = (&field.kind, &field.element) {
+        return expand_repeated_module(
+            module_ident,
+            ty,
+            max_count,
+            element,
+            wired_field_impl_item,
+            extra_items,
+        );
+    }
 
-                    fn read(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError> {
-                        <#ty as WiredInt>::read(source, field_name)
-                    }
+    if let Some(length) = is_u8_array_type(ty) {
+        return quote! {
+            pub mod #module_ident {
+                pub struct Wired;
 
-                    fn to_bytes_from_usize(value: usize) -> Self::ByteArray {
-                        <#ty as WiredInt>::to_bytes_from_usize(value)
-                    }
+                #wired_field_impl_item
 
-                    fn to_bytes(value: Self::Int) -> Self::ByteArray {
-                        <#ty as WiredInt>::to_bytes(value)
+                impl crate::__zwire_macros_support::WiredFixedBytes for Wired {
+                    const LENGTH: usize = #length;
+                    type Output = crate::__zwire_macros_support::Bytes;
+
+                    #[inline]
+                    fn from_bytes(bytes: crate::__zwire_macros_support::Bytes) -> Self::Output {
+                        bytes
                     }
                 }
 
                 #max_length_item_pub
-                #length_field_impl
-                #wired_string_impl
+                #extra_items
+            }
+        };
+    }
+
+    let length_field_impl = if is_lp || policy_opt.is_some() {
+        Some(quote! {
+            impl crate::__zwire_macros_support::WiredLengthPrefixed for Wired {
+                type LengthPrefix = #ty;
+                #max_length_item
+            }
+        })
+    } else {
+        None
+    };
+
+    let wired_string_impl = policy_opt.map(|policy| {
+        quote! {
+            impl crate::__zwire_macros_support::WiredString for Wired {
+                type Inner = Wired;
+                type Policy = #policy;
             }
         }
     });
 
     quote! {
-        pub mod fields {
-            pub const FIXED_PART_LENGTH: usize = 0 #( + #fixed_length_terms )* ;
-            pub const MAX_LENGTH: usize = FIXED_PART_LENGTH #( + #max_length_terms )* ;
-            #(#fields_modules)*
+        pub mod #module_ident {
+            pub struct Wired(pub #ty);
+
+            #wired_field_impl_item
+
+            use crate::__zwire_macros_support::{WiredInt, WireError};
+
+            impl WiredInt for Wired {
+                type Int = <#ty as WiredInt>::Int;
+                type ByteArray = <#ty as WiredInt>::ByteArray;
+
+                const MAX: usize = <#ty as WiredInt>::MAX;
+                const SIZE: usize = <#ty as WiredInt>::SIZE;
+                const MIN_SIZE: usize = <#ty as WiredInt>::MIN_SIZE;
+
+                fn encoded_size(source: &[u8]) -> Option<usize> {
+                    <#ty as WiredInt>::encoded_size(source)
+                }
+
+                fn read_raw(source: &[u8]) -> Option<Self::Int> {
+                    <#ty as WiredInt>::read_raw(source)
+                }
+
+                fn check(source: &[u8], field_name: &'static str) -> Result<(), WireError> {
+                    <#ty as WiredInt>::check(source, field_name)
+                }
+
+                fn read(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError> {
+                    <#ty as WiredInt>::read(source, field_name)
+                }
+
+                fn to_bytes_from_usize(
+                    value: usize,
+                    field_name: &'static str,
+                ) -> Result<Self::ByteArray, WireError> {
+                    <#ty as WiredInt>::to_bytes_from_usize(value, field_name)
+                }
+
+                fn to_bytes(value: Self::Int) -> Self::ByteArray {
+                    <#ty as WiredInt>::to_bytes(value)
+                }
+            }
+
+            #max_length_item_pub
+            #extra_items
+            #length_field_impl
+            #wired_string_impl
+            #nested_impl
+        }
+    }
+}
+
+// The list itself only has a count prefix, its elements get their own `element` module
+fn expand_repeated_module(
+    module_ident: &Ident,
+    count_prefix_ty: &Type,
+    max_count: &usize,
+    element: &FieldDef,
+    wired_field_impl_item: TokenStream2,
+    extra_items: Option<TokenStream2>,
+) -> TokenStream2 {
+    // The element module is one level further down than a field's
+    let mut element = element.clone();
+
+    if let FieldKind::LengthPrefixString { policy } = &mut element.kind {
+        **policy = rebase_string_policy(policy, 1);
+    }
+
+    let element_module = expand_field_module(&element, &format_ident!("element"), None);
+
+    let element_wrapper = match &element.kind {
+        FieldKind::Fixed if is_u8_array_type(&element.ty).is_some() => {
+            quote! { FixedBytesElement }
+        }
+        FieldKind::LengthPrefix => quote! { LengthPrefixedElement },
+        FieldKind::LengthPrefixString { .. } => quote! { StringElement },
+        FieldKind::Fixed
+        | FieldKind::Presence
+        | FieldKind::Version { .. }
+        | FieldKind::Repeated { .. }
+        | FieldKind::Nested
+        | FieldKind::LengthPrefixNested
+        | FieldKind::Extensions => {
+            quote! { ScalarElement }
+        }
+    };
+
+    quote! {
+        pub mod #module_ident {
+            pub struct Wired;
+
+            #wired_field_impl_item
+
+            pub type Element = crate::__zwire_macros_support::#element_wrapper<element::Wired>;
+
+            impl crate::__zwire_macros_support::WiredRepeated for Wired {
+                type CountPrefix = #count_prefix_ty;
+                type Element = Element;
+
+                const MAX_COUNT: usize = MAX_COUNT;
+            }
+
+            pub const MAX_COUNT: usize = #max_count;
+            pub const MAX_LENGTH: usize =
+                MAX_COUNT * <Element as crate::__zwire_macros_support::WiredElement>::MAX_LENGTH;
+
+            #extra_items
+
+            #element_module
         }
     }
 }
@@ -1,7 +1,10 @@
 mod types;
+pub use types::is_u8_array_type;
 
 mod ast;
-pub use ast::DefineFieldsInput;
+pub use ast::{
+    parse_field_spec, resolve_fields, DefineFieldsInput, FieldDef, FieldKind, TableOptions,
+};
 
 mod codegen;
-pub use codegen::expand_define_fields;
+pub use codegen::{expand_define_fields, expand_fields_module};
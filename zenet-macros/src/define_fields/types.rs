@@ -1,7 +1,14 @@
-use syn::{Expr, ExprLit, ExprParen, Lit, PathArguments, Type, TypeArray, TypePath};
+use syn::{
+    Expr, ExprLit, ExprParen, GenericArgument, Lit, PathArguments, Type, TypeArray, TypePath,
+};
 
-// size for u8/u16/u32/u64/u128 & [u8; N]
+// size for u8-u128, i8-i128, f32/f64, bool, Le<T> & [u8; N]. `None` for WiredVarInt, its size
+// depends on the value
 pub fn known_type_size(ty: &Type) -> Option<usize> {
+    if let Some(inner) = little_endian_inner(ty) {
+        return known_type_size(inner);
+    }
+
     #[allow(clippy::collapsible_if)]
     if let Type::Path(TypePath { path, qself: None }) = ty {
         if path.segments.len() == 1 {
@@ -9,11 +16,11 @@ pub fn known_type_size(ty: &Type) -> Option<usize> {
 
             if matches!(segment.arguments, PathArguments::None) {
                 return match segment.ident.to_string().as_str() {
-                    "u8" => Some(1),
-                    "u16" => Some(2),
-                    "u32" => Some(4),
-                    "u64" => Some(8),
-                    "u128" => Some(16),
+                    "u8" | "i8" | "bool" => Some(1),
+                    "u16" | "i16" => Some(2),
+                    "u32" | "i32" | "f32" => Some(4),
+                    "u64" | "i64" | "f64" => Some(8),
+                    "u128" | "i128" => Some(16),
                     _ => None,
                 };
             }
@@ -24,9 +31,133 @@ pub fn known_type_size(ty: &Type) -> Option<usize> {
     is_u8_array_type(ty)
 }
 
+// Types the `le`/`be` markers apply to
+pub fn is_fixed_width_number(ty: &Type) -> bool {
+    let Type::Path(TypePath { path, qself: None }) = ty else {
+        return false;
+    };
+
+    path.get_ident().is_some_and(|ident| {
+        matches!(
+            ident.to_string().as_str(),
+            "u8" | "u16"
+                | "u32"
+                | "u64"
+                | "u128"
+                | "i8"
+                | "i16"
+                | "i32"
+                | "i64"
+                | "i128"
+                | "f32"
+                | "f64"
+        )
+    })
+}
+
+// Floats and bools, they can't carry a length
+pub fn is_value_only_type(ty: &Type) -> bool {
+    let ty = little_endian_inner(ty).unwrap_or(ty);
+
+    let Type::Path(TypePath { path, qself: None }) = ty else {
+        return false;
+    };
+
+    path.get_ident()
+        .is_some_and(|ident| matches!(ident.to_string().as_str(), "f32" | "f64" | "bool"))
+}
+
+// The T of a Le<T> wrapper, as produced by the `le` marker
+fn little_endian_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(TypePath { path, qself: None }) = ty else {
+        return None;
+    };
+    let segment = path.segments.last()?;
+
+    if segment.ident != "Le" {
+        return None;
+    }
+
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+
+    match arguments.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+// String policies zwire ships, a bare one of these names means the built-in
+const BUILTIN_STRING_POLICIES: [&str; 5] = [
+    "Utf8",
+    "AsciiHyphen",
+    "PrintableAscii",
+    "Hostname",
+    "NfcText",
+];
+
+// Like rebase_type, except the built-in policies resolve to zwire's whatever the caller imports.
+// Their own types of the same name can still be had as `self::<Name>`
+pub fn rebase_string_policy(policy: &Type, depth: usize) -> Type {
+    if let Type::Path(TypePath { path, qself: None }) = policy
+        && path.leading_colon.is_none()
+        && path.segments.len() == 1
+        && BUILTIN_STRING_POLICIES
+            .iter()
+            .any(|name| path.segments[0].ident == name)
+    {
+        return syn::parse_quote!(crate::__zwire_macros_support::string_policy::#path);
+    }
+
+    rebase_type(policy, depth)
+}
+
+// Makes a path written in the caller's module resolve `depth` modules further down, absolute
+// paths and primitives stay as they are
+pub fn rebase_type(ty: &Type, depth: usize) -> Type {
+    let Type::Path(TypePath { path, qself: None }) = ty else {
+        return ty.clone();
+    };
+
+    let first = &path.segments[0].ident;
+
+    if path.leading_colon.is_some()
+        || first == "crate"
+        || (path.segments.len() == 1 && (known_type_size(ty).is_some() || is_varint_type(ty)))
+    {
+        return ty.clone();
+    }
+
+    let mut rebased = path.clone();
+
+    if first == "self" {
+        rebased.segments = rebased.segments.into_iter().skip(1).collect();
+    }
+
+    for _ in 0..depth {
+        rebased.segments.insert(0, syn::parse_quote!(super));
+    }
+
+    Type::Path(TypePath {
+        qself: None,
+        path: rebased,
+    })
+}
+
+// Detect if the type is WiredVarInt, either bare or as a path
+pub fn is_varint_type(ty: &Type) -> bool {
+    match ty {
+        Type::Path(TypePath { path, qself: None }) => path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "WiredVarInt"),
+        _ => false,
+    }
+}
+
 // Detect if the type is exactly [u8; N] and return N.
 pub fn is_u8_array_type(ty: &Type) -> Option<usize> {
-
     #[allow(clippy::collapsible_if)]
     if let Type::Array(TypeArray { elem, len, .. }) = ty {
         if let Type::Path(TypePath { path, qself: None }) = &**elem {
@@ -1,12 +1,14 @@
 use syn::{
-    braced,
+    braced, parenthesized,
     parse::{Parse, ParseStream},
-    Ident, LitInt, Token,
+    Ident, LitInt, Token, Type,
 };
 
 pub struct Variant {
     pub name: Ident,
     pub value: LitInt,
+    // `=> <record>`, the payload frames with this message carry
+    pub payload: Option<Type>,
 }
 
 impl Parse for Variant {
@@ -17,19 +19,65 @@ impl Parse for Variant {
 
         let value: LitInt = input.parse()?;
 
-        Ok(Variant { name, value })
+        let payload = if input.peek(Token![=>]) {
+            input.parse::<Token![=>]>()?;
+
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        Ok(Variant {
+            name,
+            value,
+            payload,
+        })
     }
 }
 
+// Codes a message enum can be carried as. U16 and U32 frame headers only fit u8 codes, wider
+// ones need the VarInt header
+const REPR_TYPES: [&str; 3] = ["u8", "u16", "u32"];
+
 pub struct DefineMessageInput {
     pub enum_name: Ident,
+    pub repr: Ident,
+    // `=> <Protocol>`, an enum of the messages along with their decoded payloads
+    pub protocol: Option<Ident>,
     pub variants: Vec<Variant>,
+    // `Name(_)`, keeps codes without a variant instead of rejecting them
+    pub unknown: Option<Ident>,
 }
 
 impl Parse for DefineMessageInput {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let enum_name: Ident = input.parse()?;
 
+        // `<Name>[: u8|u16|u32] [=> <Protocol>], { ... }`
+        let repr = if input.peek(Token![:]) {
+            input.parse::<Token![:]>()?;
+            let repr: Ident = input.parse()?;
+
+            if !REPR_TYPES.iter().any(|ty| repr == ty) {
+                return Err(syn::Error::new(
+                    repr.span(),
+                    "message codes have to be a u8, u16 or u32",
+                ));
+            }
+
+            repr
+        } else {
+            Ident::new("u8", enum_name.span())
+        };
+
+        let protocol: Option<Ident> = if input.peek(Token![=>]) {
+            input.parse::<Token![=>]>()?;
+
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
         if input.peek(Token![,]) {
             let _comma: Token![,] = input.parse()?;
         }
@@ -38,17 +86,45 @@ impl Parse for DefineMessageInput {
         braced!(content in input);
 
         let mut variants = Vec::new();
+        let mut unknown = None;
 
         while !content.is_empty() {
-            let variant: Variant = content.parse()?;
-            let _ = content.parse::<Token![,]>();
+            if let Some(name) = &unknown {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "the catch-all variant has to be the last one",
+                ));
+            }
+
+            if content.peek(Ident) && content.peek2(syn::token::Paren) {
+                let name: Ident = content.parse()?;
+                let raw;
+                parenthesized!(raw in content);
+                raw.parse::<Token![_]>()?;
+
+                unknown = Some(name);
+            } else {
+                let variant: Variant = content.parse()?;
 
-            variants.push(variant);
+                if let (Some(payload), None) = (&variant.payload, &protocol) {
+                    return Err(syn::Error::new_spanned(
+                        payload,
+                        "payloads need a protocol enum to go in, `<Name> => <Protocol>`",
+                    ));
+                }
+
+                variants.push(variant);
+            }
+
+            let _ = content.parse::<Token![,]>();
         }
 
         Ok(DefineMessageInput {
             enum_name,
+            repr,
+            protocol,
             variants,
+            unknown,
         })
     }
 }
@@ -1,16 +1,214 @@
-use crate::define_message::DefineMessageInput;
+use crate::define_message::{DefineMessageInput, Variant};
 use proc_macro2::TokenStream;
 use quote::quote;
+use syn::Ident;
+
+// One variant per message, the ones with a payload carry it decoded. Payloads go through the
+// codec their record type names, so a message can't be paired with the wrong one
+fn expand_protocol(
+    protocol: &Ident,
+    enum_name: &Ident,
+    repr: &Ident,
+    variants: &[Variant],
+    unknown: Option<&Ident>,
+) -> TokenStream {
+    let support = quote! { crate::__zwire_macros_support };
+
+    let variant_decls = variants.iter().map(|v| {
+        let name = &v.name;
+
+        match &v.payload {
+            Some(payload) => quote! { #name(#payload), },
+            None => quote! { #name, },
+        }
+    });
+
+    let message_arms = variants.iter().map(|v| {
+        let name = &v.name;
+
+        match &v.payload {
+            Some(_) => quote! { #protocol::#name(_) => #enum_name::#name, },
+            None => quote! { #protocol::#name => #enum_name::#name, },
+        }
+    });
+
+    let encode_arms = variants.iter().map(|v| {
+        let name = &v.name;
+
+        match &v.payload {
+            Some(payload) => quote! {
+                #protocol::#name(record) => {
+                    let start_offset = codec_buffer.len();
+
+                    <<#payload as #support::WiredPayload>::Codec as Default>::default()
+                        .encode(record, codec_buffer)?;
+
+                    Ok(#support::Frame {
+                        message: #enum_name::#name.into(),
+                        payload: codec_buffer.split_off(start_offset).freeze(),
+                    })
+                }
+            },
+            None => quote! {
+                #protocol::#name => Ok(#support::Frame::message_only(#enum_name::#name)),
+            },
+        }
+    });
+
+    let decode_arms = variants.iter().map(|v| {
+        let name = &v.name;
+        let name_str = name.to_string();
+
+        match &v.payload {
+            Some(payload) => quote! {
+                #enum_name::#name => {
+                    let payload_length = payload.len();
+                    let mut codec =
+                        <<#payload as #support::WiredPayload>::Codec as Default>::default();
+
+                    match codec.decode_buf(&mut payload)? {
+                        Some(record) => Ok(#protocol::#name(record)),
+                        None => Err(#support::WireError::Underflow(
+                            #name_str,
+                            payload_length,
+                            <<#payload as #support::WiredPayload>::Codec as #support::WiredRecord>::FIXED_PART_LENGTH,
+                        )),
+                    }
+                }
+            },
+            None => quote! { #enum_name::#name => Ok(#protocol::#name), },
+        }
+    });
+
+    // Messages this side doesn't know keep their code and raw payload
+    let (unknown_decl, unknown_message, unknown_encode, unknown_decode) = match unknown {
+        Some(unknown) => (
+            Some(quote! { #unknown(#repr, #support::Bytes), }),
+            Some(quote! { #protocol::#unknown(code, _) => #enum_name::#unknown(*code), }),
+            Some(quote! {
+                #protocol::#unknown(code, payload) => Ok(#support::Frame {
+                    message: #enum_name::#unknown(code).into(),
+                    payload,
+                }),
+            }),
+            Some(quote! { #enum_name::#unknown(code) => Ok(#protocol::#unknown(code, payload)), }),
+        ),
+        None => (None, None, None, None),
+    };
+
+    quote! {
+        #[derive(Debug, Clone)]
+        pub enum #protocol {
+            #(#variant_decls)*
+            #unknown_decl
+        }
+
+        impl #protocol {
+            pub fn message(&self) -> #enum_name {
+                match self {
+                    #(#message_arms)*
+                    #unknown_message
+                }
+            }
+
+            // Payloads are encoded at the end of `codec_buffer` and split off, as in EncodeIntoFrame
+            pub fn encode_into_frame(
+                self,
+                codec_buffer: &mut #support::BytesMut,
+            ) -> Result<#support::Frame, #support::WireError> {
+                #[allow(unused_imports)]
+                use #support::Encoder;
+
+                match self {
+                    #(#encode_arms)*
+                    #unknown_encode
+                }
+            }
+        }
+
+        // Encoding can fail on a payload's limits, hence no plain From
+        impl ::core::convert::TryFrom<#protocol> for #support::Frame {
+            type Error = #support::WireError;
+
+            fn try_from(message: #protocol) -> Result<Self, Self::Error> {
+                message.encode_into_frame(&mut #support::BytesMut::new())
+            }
+        }
+
+        // The whole payload has to be there, frames aren't split across reads
+        impl ::core::convert::TryFrom<#support::Frame> for #protocol {
+            type Error = #support::WireError;
+
+            fn try_from(frame: #support::Frame) -> Result<Self, Self::Error> {
+                #[allow(unused_imports)]
+                use #support::BufDecoder;
+
+                #[allow(unused_mut)]
+                let mut payload = frame.payload;
+
+                match #enum_name::try_from(&frame.message)? {
+                    #(#decode_arms)*
+                    #unknown_decode
+                }
+            }
+        }
+    }
+}
 
 pub fn expand_define_message(input: DefineMessageInput) -> TokenStream {
     let enum_name = input.enum_name;
+    let repr = input.repr;
     let variants = input.variants;
+    let unknown = input.unknown;
+
+    let protocol = input
+        .protocol
+        .as_ref()
+        .map(|protocol| expand_protocol(protocol, &enum_name, &repr, &variants, unknown.as_ref()));
 
+    let support = quote! { crate::__zwire_macros_support };
+
+    // The catch-all variant carries its code, so the enum can't have discriminants
+    let repr_attr = unknown.is_none().then(|| quote! { #[repr(#repr)] });
+
+    // Compared by code, `Unknown(1)` is the same message as the variant coded 1
+    let (derives, code_equality) = match &unknown {
+        Some(_) => (
+            quote! { #[derive(Debug, Clone, Copy, Eq)] },
+            Some(quote! {
+                impl PartialEq for #enum_name {
+                    fn eq(&self, other: &Self) -> bool {
+                        #repr::from(*self) == #repr::from(*other)
+                    }
+                }
+
+                impl ::core::hash::Hash for #enum_name {
+                    fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                        #repr::from(*self).hash(state);
+                    }
+                }
+            }),
+        ),
+        None => (
+            quote! { #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)] },
+            None,
+        ),
+    };
     let variant_decls = variants.iter().map(|v| {
         let name = &v.name;
         let value = &v.value;
 
-        quote! { #name = #value, }
+        match unknown {
+            Some(_) => quote! { #name, },
+            None => quote! { #name = #value, },
+        }
+    });
+
+    let from_code_arms = variants.iter().map(|v| {
+        let name = &v.name;
+        let value = &v.value;
+
+        quote! { #value => #enum_name::#name, }
     });
 
     let try_from_arms = variants.iter().map(|v| {
@@ -20,51 +218,214 @@ pub fn expand_define_message(input: DefineMessageInput) -> TokenStream {
         quote! { #value => Ok(#enum_name::#name), }
     });
 
-    let from_enum_arms = variants.iter().map(|v| {
+    let into_code_arms = variants.iter().map(|v| {
         let name = &v.name;
         let value = &v.value;
 
-        quote! { #enum_name::#name => #value as u8, }
+        quote! { #enum_name::#name => #value as #repr, }
+    });
+
+    let name_arms = variants.iter().map(|v| {
+        let name = &v.name;
+        let name_str = name.to_string();
+
+        quote! { #enum_name::#name => #name_str, }
+    });
+
+    let from_str_arms = variants.iter().map(|v| {
+        let name = &v.name;
+        let name_str = name.to_string();
+
+        quote! { #name_str => Ok(#enum_name::#name), }
     });
 
+    let all = variants.iter().map(|v| &v.name);
+    let variant_count = variants.len();
+
+    let (unknown_decl, unknown_into_code, unknown_name, unknown_display, unknown_from_str) =
+        match &unknown {
+            Some(unknown) => {
+                let unknown_str = unknown.to_string();
+                let prefix = format!("{}(", unknown);
+
+                let doc = format!(
+                    "Codes without a variant of their own. Build it with `{}::from(code)`, which \
+                     picks the named variant for a known code, one built by hand still compares \
+                     and hashes by its code",
+                    enum_name
+                );
+
+                (
+                    Some(quote! {
+                        #[doc = #doc]
+                        #unknown(#repr),
+                    }),
+                    Some(quote! { #enum_name::#unknown(code) => code, }),
+                    Some(quote! { #enum_name::#unknown(_) => #unknown_str, }),
+                    Some(quote! {
+                        if let #enum_name::#unknown(code) = self {
+                            return write!(formatter, "{}({})", #unknown_str, code);
+                        }
+                    }),
+                    // Reads back what Display wrote for it
+                    Some(quote! {
+                        if let Some(code) = name
+                            .strip_prefix(#prefix)
+                            .and_then(|rest| rest.strip_suffix(')'))
+                            .and_then(|code| code.parse::<#repr>().ok())
+                        {
+                            return Ok(#enum_name::from(code));
+                        }
+                    }),
+                )
+            }
+            None => (None, None, None, None, None),
+        };
+
+    // Codes without a variant are an error unless there's a catch-all for them
+    let code_conversions = match &unknown {
+        Some(unknown) => quote! {
+            impl From<#repr> for #enum_name {
+                fn from(code: #repr) -> Self {
+                    match code {
+                        #(#from_code_arms)*
+                        other => #enum_name::#unknown(other),
+                    }
+                }
+            }
+        },
+        None => quote! {
+            impl ::core::convert::TryFrom<#repr> for #enum_name {
+                type Error = #support::WireError;
+
+                fn try_from(code: #repr) -> Result<Self, Self::Error> {
+                    match code {
+                        #(#try_from_arms)*
+                        other => Err(#support::WireError::InvalidMessageType(other.into())),
+                    }
+                }
+            }
+        },
+    };
+
+    // Messages carry u32 codes, narrower enums reject the ones they can't hold
+    let from_message = match (&unknown, repr == "u32") {
+        (Some(_), true) => quote! {
+            impl From<&#support::Message> for #enum_name {
+                fn from(message: &#support::Message) -> Self {
+                    From::<u32>::from(message.0)
+                }
+            }
+        },
+        (Some(_), false) => quote! {
+            impl ::core::convert::TryFrom<&#support::Message> for #enum_name {
+                type Error = #support::WireError;
+
+                fn try_from(message: &#support::Message) -> Result<Self, Self::Error> {
+                    #repr::try_from(message.0)
+                        .map(From::from)
+                        .map_err(|_| #support::WireError::InvalidMessageType(message.0))
+                }
+            }
+        },
+        (None, true) => quote! {
+            impl ::core::convert::TryFrom<&#support::Message> for #enum_name {
+                type Error = #support::WireError;
+
+                fn try_from(message: &#support::Message) -> Result<Self, Self::Error> {
+                    ::core::convert::TryFrom::<u32>::try_from(message.0)
+                }
+            }
+        },
+        (None, false) => quote! {
+            impl ::core::convert::TryFrom<&#support::Message> for #enum_name {
+                type Error = #support::WireError;
+
+                fn try_from(message: &#support::Message) -> Result<Self, Self::Error> {
+                    let code = #repr::try_from(message.0)
+                        .map_err(|_| #support::WireError::InvalidMessageType(message.0))?;
+
+                    ::core::convert::TryFrom::<#repr>::try_from(code)
+                }
+            }
+        },
+    };
+
+    let into_message = if repr == "u32" {
+        quote! { #support::Message(message.into()) }
+    } else {
+        quote! { #support::Message(u32::from(#repr::from(message))) }
+    };
+
+    let message_conversions = quote! {
+        #from_message
+
+        impl From<#enum_name> for #support::Message {
+            fn from(message: #enum_name) -> Self {
+                #into_message
+            }
+        }
+    };
+
     quote! {
-        #[repr(u8)]
-        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #repr_attr
+        #derives
         pub enum #enum_name {
             #(#variant_decls)*
+            #unknown_decl
         }
 
-        impl std::convert::TryFrom<u8> for #enum_name {
-            type Error = crate::__zwire_macros_support::WireError;
+        impl #enum_name {
+            // Every variant with a code of its own, in declaration order
+            pub const ALL: [#enum_name; #variant_count] = [#(#enum_name::#all),*];
 
-            fn try_from(code: u8) -> Result<Self, Self::Error> {
-                match code {
-                    #(#try_from_arms)*
-                    other => Err(crate::__zwire_macros_support::WireError::InvalidMessageType(other)),
+            pub const fn name(&self) -> &'static str {
+                match self {
+                    #(#name_arms)*
+                    #unknown_name
                 }
             }
         }
 
-        impl std::convert::TryFrom<&crate::__zwire_macros_support::Message> for #enum_name {
-            type Error = crate::__zwire_macros_support::WireError;
+        impl ::core::fmt::Display for #enum_name {
+            fn fmt(&self, formatter: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                #unknown_display
 
-            fn try_from(message: &crate::__zwire_macros_support::Message) -> Result<Self, Self::Error> {
-                std::convert::TryFrom::<u8>::try_from(message.0)
+                formatter.write_str(self.name())
             }
         }
 
-        impl From<#enum_name> for u8 {
-            fn from(message: #enum_name) -> Self {
-                match message {
-                    #(#from_enum_arms)*
+        // By variant name, e.g. for config files
+        impl ::core::str::FromStr for #enum_name {
+            type Err = #support::WireError;
+
+            fn from_str(name: &str) -> Result<Self, Self::Err> {
+                #unknown_from_str
+
+                match name {
+                    #(#from_str_arms)*
+                    other => Err(#support::WireError::InvalidMessageName(
+                        #support::String::from(other),
+                    )),
                 }
             }
         }
 
-        impl From<#enum_name> for crate::__zwire_macros_support::Message {
+        #code_equality
+
+        #code_conversions
+
+        impl From<#enum_name> for #repr {
             fn from(message: #enum_name) -> Self {
-                crate::__zwire_macros_support::Message(message.into())
+                match message {
+                    #(#into_code_arms)*
+                    #unknown_into_code
+                }
             }
         }
+
+        #message_conversions
+
+        #protocol
     }
 }
@@ -1,5 +1,5 @@
 mod ast;
 mod codegen;
 
-pub use ast::DefineMessageInput;
+pub use ast::{DefineMessageInput, Variant};
 pub use codegen::expand_define_message;
@@ -0,0 +1,182 @@
+use crate::define_fields::{parse_field_spec, resolve_fields, FieldDef, FieldKind, TableOptions};
+use quote::format_ident;
+use syn::{
+    parse::{Parse, ParseStream},
+    Data, DeriveInput, Fields, GenericArgument, Ident, LitInt, PathArguments, Type, TypePath,
+    Visibility,
+};
+
+pub struct WireCodecField {
+    pub member: Ident,
+    // The T of an optional or tagged field's Option<T> or a repeated field's Vec<T>
+    pub ty: Type,
+    pub def: FieldDef,
+}
+
+pub struct WireCodecInput {
+    pub vis: Visibility,
+    pub ident: Ident,
+    pub codec: Ident,
+    pub fields_module: Ident,
+    pub version: Option<FieldDef>,
+    pub presence: Option<FieldDef>,
+    pub extensions: Option<FieldDef>,
+    pub fields: Vec<WireCodecField>,
+}
+
+// The T of a `wrapper<T>`, e.g. Option<T>
+fn generic_inner<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(TypePath { path, qself: None }) = ty else {
+        return None;
+    };
+    let segment = path.segments.last()?;
+
+    if segment.ident != wrapper {
+        return None;
+    }
+
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+
+    match arguments.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+impl Parse for WireCodecInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let input: DeriveInput = input.parse()?;
+
+        if !input.generics.params.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &input.generics,
+                "WireCodec doesn't support generic structs",
+            ));
+        }
+
+        // Struct level: #[wire(codec = <Ident>, fields = <ident>, version = <u8>,
+        // extensions = <max_length>)], all optional
+        let mut codec = format_ident!("{}Codec", input.ident);
+        let mut fields_module = format_ident!("fields");
+        let mut options = TableOptions::default();
+
+        for attr in input
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("wire"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("codec") {
+                    codec = meta.value()?.parse()?;
+
+                    Ok(())
+                } else if meta.path.is_ident("fields") {
+                    fields_module = meta.value()?.parse()?;
+
+                    Ok(())
+                } else if let Some(key) = meta
+                    .path
+                    .get_ident()
+                    .filter(|key| *key == "version" || *key == "extensions")
+                {
+                    let value: LitInt = meta.value()?.parse()?;
+
+                    options.parse_option(key, &value)
+                } else {
+                    Err(meta.error("expected `codec`, `fields`, `version` or `extensions`"))
+                }
+            })?;
+        }
+
+        let Data::Struct(data) = &input.data else {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "WireCodec can only be derived for structs",
+            ));
+        };
+
+        let Fields::Named(named) = &data.fields else {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "WireCodec requires named fields",
+            ));
+        };
+
+        // Field level: #[wire(<define_fields spec>)], the wire order is the declaration order
+        let mut parsed_fields = Vec::with_capacity(named.named.len());
+        let mut members = Vec::with_capacity(named.named.len());
+
+        for field in &named.named {
+            let member = field.ident.clone().expect("named fields have idents");
+
+            let Some(attr) = field.attrs.iter().find(|attr| attr.path().is_ident("wire")) else {
+                return Err(syn::Error::new_spanned(
+                    &member,
+                    "missing #[wire(...)] attribute",
+                ));
+            };
+
+            let spec = attr.parse_args_with(parse_field_spec)?;
+
+            let mut ty = &field.ty;
+
+            if spec.optional || spec.tag.is_some() {
+                ty = generic_inner(ty, "Option").ok_or_else(|| {
+                    syn::Error::new_spanned(
+                        ty,
+                        "optional and tagged fields have to be an Option<T>",
+                    )
+                })?;
+            }
+
+            if matches!(spec.kind, FieldKind::Repeated { .. }) {
+                ty = generic_inner(ty, "Vec").ok_or_else(|| {
+                    syn::Error::new_spanned(ty, "repeated fields have to be a Vec<T>")
+                })?;
+            }
+
+            let ty = ty.clone();
+
+            parsed_fields.push((member.clone(), spec));
+            members.push((member, ty));
+        }
+
+        let mut definitions = resolve_fields(parsed_fields, &options)?;
+
+        // The version and the presence bitmap lead the table, the extensions block ends it. None
+        // of them has a struct member
+        let version = match definitions.first() {
+            Some(def) if matches!(def.kind, FieldKind::Version { .. }) => {
+                Some(definitions.remove(0))
+            }
+            _ => None,
+        };
+        let presence = match definitions.first() {
+            Some(def) if matches!(def.kind, FieldKind::Presence) => Some(definitions.remove(0)),
+            _ => None,
+        };
+        let extensions = match definitions.last() {
+            Some(def) if matches!(def.kind, FieldKind::Extensions) => definitions.pop(),
+            _ => None,
+        };
+
+        let fields = members
+            .into_iter()
+            .zip(definitions)
+            .map(|((member, ty), def)| WireCodecField { member, ty, def })
+            .collect();
+
+        Ok(WireCodecInput {
+            vis: input.vis,
+            ident: input.ident,
+            codec,
+            fields_module,
+            version,
+            presence,
+            extensions,
+            fields,
+        })
+    }
+}
@@ -0,0 +1,628 @@
+use super::ast::{WireCodecField, WireCodecInput};
+use crate::define_fields::{expand_fields_module, is_u8_array_type, FieldDef, FieldKind};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::Ident;
+
+enum WireShape {
+    Int,
+    FixedBytes,
+    LengthPrefixed,
+    String,
+    Repeated,
+    Nested,
+    LengthPrefixedNested,
+}
+
+fn wire_shape(def: &FieldDef) -> WireShape {
+    match def.kind {
+        FieldKind::Fixed if is_u8_array_type(&def.ty).is_some() => WireShape::FixedBytes,
+        FieldKind::Fixed | FieldKind::Presence | FieldKind::Version { .. } => WireShape::Int,
+        FieldKind::LengthPrefix | FieldKind::Extensions => WireShape::LengthPrefixed,
+        FieldKind::LengthPrefixString { .. } => WireShape::String,
+        FieldKind::Repeated { .. } => WireShape::Repeated,
+        FieldKind::Nested => WireShape::Nested,
+        FieldKind::LengthPrefixNested => WireShape::LengthPrefixedNested,
+    }
+}
+
+// Locals can't clash with the generated code's own bindings, e.g. a field called `source`
+fn value_ident(member: &Ident) -> Ident {
+    format_ident!("{}_value", member)
+}
+
+fn wired_path(fields_module: &Ident, def: &FieldDef) -> TokenStream2 {
+    let module_ident = Ident::new(&def.name.to_string().to_lowercase(), def.name.span());
+
+    quote! { #fields_module::#module_ident::Wired }
+}
+
+fn presence_bit_path(fields_module: &Ident, def: &FieldDef) -> TokenStream2 {
+    let module_ident = Ident::new(&def.name.to_string().to_lowercase(), def.name.span());
+
+    quote! { #fields_module::#module_ident::PRESENCE_BIT }
+}
+
+fn module_path(fields_module: &Ident, def: &FieldDef) -> TokenStream2 {
+    let module_ident = Ident::new(&def.name.to_string().to_lowercase(), def.name.span());
+
+    quote! { #fields_module::#module_ident }
+}
+
+// Extensions are encoded into their block up front, which then goes out like any length-prefixed
+// field
+fn encode_extensions(
+    fields_module: &Ident,
+    extensions: &FieldDef,
+    tagged: &[&WireCodecField],
+) -> (TokenStream2, TokenStream2) {
+    let entries = tagged.iter().map(|field| {
+        let member = &field.member;
+        let value = value_ident(member);
+        let tag = module_path(fields_module, &field.def);
+        let (prepare, put) = encode_value(fields_module, field, quote! { #value });
+
+        quote! {
+            if let Some(#value) = item.#member {
+                let mut total_length: usize = 0;
+
+                #prepare
+
+                let mut entry = BytesMut::with_capacity(total_length);
+
+                {
+                    let destination = &mut entry;
+
+                    #put
+                }
+
+                extensions.put_extension(#tag::TAG, &entry)?;
+            }
+        }
+    });
+
+    let mutability = (!tagged.is_empty()).then(|| quote! { mut });
+    let block = WireCodecField {
+        member: Ident::new("extensions", extensions.name.span()),
+        ty: extensions.ty.clone(),
+        def: extensions.clone(),
+    };
+    let (prepare, put) = encode_value(fields_module, &block, quote! { extensions.freeze() });
+
+    (
+        quote! {
+            let #mutability extensions = BytesMut::new();
+
+            #(#entries)*
+            #prepare
+        },
+        put,
+    )
+}
+
+// Entries with a tag this side doesn't know are skipped, they come from a newer version of the
+// table. A known tag has to show up at most once and carry a whole value
+fn take_extensions(
+    fields_module: &Ident,
+    extensions: &FieldDef,
+    tagged: &[&WireCodecField],
+) -> TokenStream2 {
+    let wired = wired_path(fields_module, extensions);
+
+    if tagged.is_empty() {
+        return quote! {
+            if source.take_length_prefixed::<#wired>()?.is_none() {
+                return Err(WireError::Truncated("extensions"));
+            }
+        };
+    }
+
+    let values: Vec<Ident> = tagged
+        .iter()
+        .map(|field| value_ident(&field.member))
+        .collect();
+    let entries = tagged.iter().map(|field| {
+        let value = value_ident(&field.member);
+        let module = module_path(fields_module, &field.def);
+        let name = field.member.to_string();
+        let take = take_value(fields_module, field);
+
+        quote! {
+            #module::TAG => {
+                if #value.is_some() {
+                    return Err(WireError::DuplicateExtension(#name, tag.into_inner()));
+                }
+
+                if #module::measure(&entry)?.is_none() {
+                    return Err(WireError::InvalidExtension(#name));
+                }
+
+                #value = Some({
+                    let source = &mut entry;
+
+                    #take
+
+                    #value
+                });
+            }
+        }
+    });
+
+    quote! {
+        let Some(mut extensions) = source.take_length_prefixed::<#wired>()? else {
+            return Err(WireError::Truncated("extensions"));
+        };
+        #( let mut #values = None; )*
+
+        while !extensions.is_empty() {
+            let Some((tag, mut entry)) = extensions.take_extension()? else {
+                return Err(WireError::InvalidExtension("extensions"));
+            };
+
+            match tag {
+                #(#entries)*
+                _ => {}
+            }
+        }
+    }
+}
+
+// Lengths of every field are summed up front, so nothing is written when a limit is exceeded.
+// Optional fields set their presence bit and are skipped entirely when absent
+fn encode_field(fields_module: &Ident, field: &WireCodecField) -> (TokenStream2, TokenStream2) {
+    let member = &field.member;
+    let value = value_ident(member);
+
+    if field.def.presence_bit.is_none() {
+        return encode_value(fields_module, field, quote! { item.#member });
+    }
+
+    let bit = presence_bit_path(fields_module, &field.def);
+    let (prepare, put) = encode_value(fields_module, field, quote! { #value });
+
+    (
+        quote! {
+            let #value = match item.#member {
+                Some(#value) => {
+                    #prepare
+                    presence |= #bit;
+
+                    Some(#value)
+                }
+                None => None,
+            };
+        },
+        quote! {
+            if let Some(#value) = #value {
+                #put
+            }
+        },
+    )
+}
+
+fn encode_value(
+    fields_module: &Ident,
+    field: &WireCodecField,
+    source: TokenStream2,
+) -> (TokenStream2, TokenStream2) {
+    let value = value_ident(&field.member);
+    let wired = wired_path(fields_module, &field.def);
+    let name = field.member.to_string();
+
+    match wire_shape(&field.def) {
+        WireShape::Int => (
+            quote! {
+                let #value: <#wired as WiredInt>::Int = #source.into();
+                total_length = total_length.checked_add_wire(
+                    "total_length",
+                    <#wired as WiredInt>::to_bytes(#value).as_ref().len(),
+                    #name,
+                )?;
+            },
+            quote! { destination.put_single::<#wired>(#value); },
+        ),
+        WireShape::FixedBytes => (
+            quote! {
+                let #value = #source;
+                total_length = total_length.checked_add_wire(
+                    "total_length",
+                    <#wired as WiredFixedBytes>::LENGTH,
+                    #name,
+                )?;
+            },
+            quote! { destination.put_fixed_bytes::<#wired>(&#value)?; },
+        ),
+        // Elements convert like single fields do, e.g. Vec<AudioEncoding> goes out as its u8 codes
+        WireShape::Repeated => (
+            quote! {
+                let #value: Vec<<<#wired as WiredRepeated>::Element as WiredElement>::Value> =
+                    #source.into_iter().map(Into::into).collect();
+                total_length = total_length.checked_add_wire(
+                    "total_length",
+                    <#wired as WiredRepeated>::encoded_length(&#value)?,
+                    #name,
+                )?;
+            },
+            quote! { destination.put_repeated::<#wired>(&#value)?; },
+        ),
+        // Fixed size records, encoding checks the record itself
+        WireShape::Nested => (
+            quote! {
+                let #value = #source;
+                total_length = total_length.checked_add_wire(
+                    "total_length",
+                    <<#wired as WiredNested>::Codec as WiredRecord>::MAX_LENGTH,
+                    #name,
+                )?;
+            },
+            quote! { destination.put_nested::<#wired>(#value)?; },
+        ),
+        WireShape::LengthPrefixed | WireShape::String | WireShape::LengthPrefixedNested => {
+            let shape = wire_shape(&field.def);
+
+            let put = if matches!(shape, WireShape::String) {
+                quote! { destination.put_length_prefixed_string::<#wired>(#value)?; }
+            } else {
+                quote! { destination.put_length_prefixed::<#wired>(&#value)?; }
+            };
+
+            // Records are encoded up front, their length is only known afterwards
+            let value_source = if matches!(shape, WireShape::LengthPrefixedNested) {
+                quote! {
+                    {
+                        let mut record = BytesMut::new();
+                        <<#wired as WiredNested>::Codec as Default>::default()
+                            .encode(#source, &mut record)?;
+
+                        record.freeze()
+                    }
+                }
+            } else {
+                source
+            };
+
+            (
+                quote! {
+                    let #value = #value_source;
+                    let payload_length = #value.len();
+                    let max_payload_length = <#wired as WiredLengthPrefixed>::MAX_LENGTH;
+
+                    if payload_length > max_payload_length {
+                        return Err(WireError::Oversized(#name, payload_length, max_payload_length));
+                    }
+
+                    total_length = total_length
+                        .checked_add_wire(
+                            "total_length",
+                            <<#wired as WiredLengthPrefixed>::LengthPrefix as WiredInt>::to_bytes_from_usize(
+                                payload_length,
+                                #name,
+                            )?
+                            .as_ref()
+                            .len(),
+                            #name,
+                        )?
+                        .checked_add_wire("total_length", payload_length, #name)?;
+                },
+                put,
+            )
+        }
+    }
+}
+
+fn take_field(fields_module: &Ident, field: &WireCodecField) -> TokenStream2 {
+    let value = value_ident(&field.member);
+    let take = take_value(fields_module, field);
+
+    if field.def.presence_bit.is_none() {
+        return take;
+    }
+
+    let bit = presence_bit_path(fields_module, &field.def);
+
+    quote! {
+        let #value = if presence & #bit != 0 {
+            #take
+
+            Some(#value)
+        } else {
+            None
+        };
+    }
+}
+
+// The layout measured the whole record before anything is taken, so a take coming up short
+// means the two disagree. That's an error like any other malformed input, never a panic
+fn take_value(fields_module: &Ident, field: &WireCodecField) -> TokenStream2 {
+    let value = value_ident(&field.member);
+    let ty = &field.ty;
+    let wired = wired_path(fields_module, &field.def);
+    let name = field.member.to_string();
+
+    match wire_shape(&field.def) {
+        WireShape::Int => quote! {
+            let Some(#value) = source.take_single::<#wired>()? else {
+                return Err(WireError::Truncated(#name));
+            };
+            let #value: #ty = ::core::convert::TryFrom::try_from(#value)?;
+        },
+        WireShape::FixedBytes => quote! {
+            let Some(#value) = source.take_fixed_bytes::<#wired>() else {
+                return Err(WireError::Truncated(#name));
+            };
+        },
+        WireShape::LengthPrefixed => quote! {
+            let Some(#value) = source.take_length_prefixed::<#wired>()? else {
+                return Err(WireError::Truncated(#name));
+            };
+        },
+        WireShape::String => quote! {
+            let Some(#value) = source.take_length_prefixed_string::<#wired>()? else {
+                return Err(WireError::Truncated(#name));
+            };
+        },
+        WireShape::Nested => quote! {
+            let Some(#value) = source.take_nested::<#wired>()? else {
+                return Err(WireError::Underflow(
+                    #name,
+                    source.len(),
+                    <<#wired as WiredNested>::Codec as WiredRecord>::MAX_LENGTH,
+                ));
+            };
+        },
+        WireShape::LengthPrefixedNested => quote! {
+            let Some(#value) = source.take_length_prefixed_nested::<#wired>()? else {
+                return Err(WireError::Truncated(#name));
+            };
+        },
+        WireShape::Repeated => quote! {
+            let Some(#value) = source.take_repeated::<#wired>()? else {
+                return Err(WireError::Truncated(#name));
+            };
+            let #value = #value
+                .into_iter()
+                .map(<#ty as ::core::convert::TryFrom<_>>::try_from)
+                .collect::<Result<Vec<#ty>, _>>()?;
+        },
+    }
+}
+
+pub fn expand_wire_codec(input: WireCodecInput) -> TokenStream2 {
+    let WireCodecInput {
+        vis,
+        ident,
+        codec,
+        fields_module,
+        version,
+        presence,
+        extensions,
+        fields,
+    } = input;
+
+    let definitions: Vec<FieldDef> = version
+        .iter()
+        .chain(presence.iter())
+        .cloned()
+        .chain(fields.iter().map(|field| field.def.clone()))
+        .chain(extensions.iter().cloned())
+        .collect();
+    let fields_module_tokens = expand_fields_module(&fields_module, &definitions);
+
+    let (tagged, table): (Vec<&WireCodecField>, Vec<&WireCodecField>) =
+        fields.iter().partition(|field| field.def.tag.is_some());
+
+    let (prepare, put): (Vec<_>, Vec<_>) = table
+        .iter()
+        .map(|field| encode_field(&fields_module, field))
+        .unzip();
+    let take = table.iter().map(|field| take_field(&fields_module, field));
+    let members = fields.iter().map(|field| &field.member);
+    let values = fields.iter().map(|field| value_ident(&field.member));
+
+    let support = quote! { crate::__zwire_macros_support };
+
+    // The version goes first, so any version of the table can read it. Every version decodes,
+    // older or newer than VERSION: the fields before the extensions block can't change between
+    // versions, tags a newer sender added are skipped and the ones an older sender lacks are None
+    let (prepare_version, put_version, take_version, decoded_version) = match &version {
+        Some(def) => {
+            let wired = wired_path(&fields_module, def);
+
+            (
+                quote! {
+                    total_length = total_length.checked_add_wire(
+                        "total_length",
+                        <#wired as WiredInt>::SIZE,
+                        "version",
+                    )?;
+                },
+                quote! { destination.put_single::<#wired>(#fields_module::VERSION); },
+                quote! {
+                    let Some(version) = source.take_single::<#wired>()? else {
+                        return Err(WireError::Truncated("version"));
+                    };
+                },
+                quote! { self.decoded_version = Some(version); },
+            )
+        }
+        None => Default::default(),
+    };
+
+    // The bitmap comes next, it tells the decoder which optional fields follow
+    let (prepare_presence, put_presence, take_presence) = match &presence {
+        Some(def) => {
+            let wired = wired_path(&fields_module, def);
+
+            (
+                quote! {
+                    let mut presence: <#wired as WiredInt>::Int = 0;
+                    total_length = total_length.checked_add_wire(
+                        "total_length",
+                        <#wired as WiredInt>::SIZE,
+                        "presence",
+                    )?;
+                },
+                quote! { destination.put_single::<#wired>(presence); },
+                quote! {
+                    let Some(presence) = source.take_single::<#wired>()? else {
+                        return Err(WireError::Truncated("presence"));
+                    };
+                },
+            )
+        }
+        None => Default::default(),
+    };
+
+    let (prepare_extensions, put_extensions, take_extensions) = match &extensions {
+        Some(def) => {
+            let (prepare, put) = encode_extensions(&fields_module, def, &tagged);
+
+            (prepare, put, take_extensions(&fields_module, def, &tagged))
+        }
+        None => Default::default(),
+    };
+
+    // Versioned codecs remember what they decoded last
+    let (version_member, version_init, version_accessor) = match &version {
+        Some(_) => (
+            Some(quote! { decoded_version: Option<u8>, }),
+            Some(quote! { decoded_version: None, }),
+            Some(quote! {
+                // Version of the last decoded record, tells which tagged fields its sender knew.
+                // It isn't checked against VERSION, a record from any version is accepted
+                pub fn decoded_version(&self) -> Option<u8> {
+                    self.decoded_version
+                }
+            }),
+        ),
+        None => (None, None, None),
+    };
+
+    quote! {
+        #fields_module_tokens
+
+        #[derive(Debug, Clone, Copy)]
+        #vis struct #codec {
+            max_length: usize,
+            #version_member
+        }
+
+        impl Default for #codec {
+            fn default() -> Self {
+                Self {
+                    max_length: #fields_module::MAX_LENGTH,
+                    #version_init
+                }
+            }
+        }
+
+        impl #codec {
+            // Only lowers the limit, the table's MAX_LENGTH still bounds every field
+            pub fn with_max_length(max_length: usize) -> Self {
+                Self {
+                    max_length: max_length.min(#fields_module::MAX_LENGTH),
+                    #version_init
+                }
+            }
+
+            #version_accessor
+        }
+
+        impl #support::EncodeIntoFrame for #codec {
+            type EncodeItem = #ident;
+        }
+
+        impl #support::DecodeFromFrame for #codec {}
+
+        impl #support::WiredRecord for #codec {
+            type Record = #ident;
+
+            const FIXED_PART_LENGTH: usize = #fields_module::FIXED_PART_LENGTH;
+            const MAX_LENGTH: usize = #fields_module::MAX_LENGTH;
+        }
+
+        impl #support::WiredPayload for #ident {
+            type Codec = #codec;
+        }
+
+        impl #support::Encoder<#ident> for #codec {
+            type Error = #support::WireError;
+
+            fn encode(
+                &mut self,
+                item: #ident,
+                destination: &mut #support::BytesMut,
+            ) -> Result<(), Self::Error> {
+                #[allow(unused_imports)]
+                use #support::{
+                    BytesMut, BytesMutPutExt, CheckedAddWire, Encoder, Vec, WireError,
+                    WiredElement, WiredFixedBytes, WiredInt, WiredLengthPrefixed, WiredNested,
+                    WiredRecord, WiredRepeated,
+                };
+
+                let mut total_length: usize = 0;
+
+                #prepare_version
+                #prepare_presence
+                #(#prepare)*
+                #prepare_extensions
+
+                if total_length > self.max_length {
+                    return Err(WireError::Oversized("total_length", total_length, self.max_length));
+                }
+
+                destination.reserve(total_length);
+
+                #put_version
+                #put_presence
+                #(#put)*
+                #put_extensions
+
+                Ok(())
+            }
+        }
+
+        impl #support::BufDecoder for #codec {
+            type Item = #ident;
+            type Error = #support::WireError;
+
+            fn decode_buf<B: #support::WireBuf>(
+                &mut self,
+                source: &mut B,
+            ) -> Result<Option<Self::Item>, Self::Error> {
+                #[allow(unused_imports)]
+                use #support::{
+                    BytesMutTakeExt, Vec, WireError, WiredLayout, WiredNested, WiredRecord,
+                };
+
+                // Nothing is consumed before the whole record arrived
+                if <#fields_module::Layout as WiredLayout>::measure(&source[..], self.max_length)?
+                    .is_none()
+                {
+                    return Ok(None);
+                }
+
+                #take_version
+                #take_presence
+                #(#take)*
+                #take_extensions
+
+                #decoded_version
+
+                Ok(Some(#ident { #(#members: #values),* }))
+            }
+        }
+
+        impl #support::Decoder for #codec {
+            type Item = #ident;
+            type Error = #support::WireError;
+
+            fn decode(
+                &mut self,
+                source: &mut #support::BytesMut,
+            ) -> Result<Option<Self::Item>, Self::Error> {
+                #support::BufDecoder::decode_buf(self, source)
+            }
+        }
+    }
+}
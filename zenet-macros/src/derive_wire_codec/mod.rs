@@ -0,0 +1,5 @@
+mod ast;
+mod codegen;
+
+pub use ast::WireCodecInput;
+pub use codegen::expand_wire_codec;
@@ -4,6 +4,9 @@ use define_fields::{expand_define_fields, DefineFieldsInput};
 mod define_message;
 use define_message::{DefineMessageInput, expand_define_message};
 
+mod derive_wire_codec;
+use derive_wire_codec::{expand_wire_codec, WireCodecInput};
+
 use proc_macro::TokenStream;
 use syn::parse_macro_input;
 
@@ -22,3 +25,11 @@ pub fn define_message(input: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+#[proc_macro_derive(WireCodec, attributes(wire))]
+pub fn derive_wire_codec(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as WireCodecInput);
+    let expanded = expand_wire_codec(input);
+
+    TokenStream::from(expanded)
+}
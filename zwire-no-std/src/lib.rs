@@ -0,0 +1,99 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use zwire::{
+    codec::{
+        bytes::{ByteStr, Bytes, BytesMut, BytesMutPutExt, BytesMutTakeExt, BytesPeekExt},
+        wired::{define_fields, define_message, WireCodec},
+        Decoder, Encoder, FrameChecksum, FrameCodec, Lz4Transform,
+    },
+    errors::WireError,
+    Frame,
+};
+
+pub mod __zwire_macros_support {
+    pub use zwire::__zwire_macros_support::*;
+}
+
+define_message!(Encoding, { Pcm = 1 });
+
+define_message!(
+    Kind => KindProtocol,
+    {
+        Ping = 1,
+        Chunk = 2 => Chunk,
+        Other(_),
+    }
+);
+
+pub mod table {
+    use super::define_fields;
+
+    // [u16 code] | [u8 length][name...]
+    define_fields! {
+        (Code, u16, fixed),
+        (Name, u8, length_prefix_string, 32, Hostname),
+    }
+}
+
+#[derive(Debug, Clone, WireCodec)]
+#[wire(version = 2, extensions = 64)]
+pub struct Chunk {
+    #[wire(bool, fixed)]
+    pub last: bool,
+    #[wire(u16, length_prefix, 512)]
+    pub body: Bytes,
+    #[wire(u8, length_prefix_string, 64, NfcText<1, 16>)]
+    pub source: ByteStr,
+    #[wire(u8, repeated, 4, (u8, length_prefix_string, 16, PrintableAscii))]
+    pub notes: Vec<ByteStr>,
+    #[wire(u8, repeated, 4, (u8, fixed))]
+    pub encodings: Vec<Encoding>,
+    #[wire(u32, fixed, optional)]
+    pub limit: Option<u32>,
+    #[wire(u8, length_prefix_string, 16, Utf8, tag = 1)]
+    pub label: Option<ByteStr>,
+}
+
+// Sends the chunk through a checksummed, compressing frame codec and back
+pub fn round_trip(chunk: Chunk) -> Result<Option<Chunk>, WireError> {
+    let mut codec = FrameCodec::builder()
+        .checksum(FrameChecksum::Crc32c)
+        .transform(Lz4Transform::new(64))
+        .build()?;
+    let mut buffer = BytesMut::new();
+
+    codec.encode(Frame::try_from(KindProtocol::Chunk(chunk))?, &mut buffer)?;
+
+    let Some(frame) = codec.decode(&mut buffer)? else {
+        return Ok(None);
+    };
+
+    match KindProtocol::try_from(frame)? {
+        KindProtocol::Chunk(chunk) => Ok(Some(chunk)),
+        _ => Ok(None),
+    }
+}
+
+// Writes a table entry by hand and reads it back field by field
+pub fn table_round_trip(code: u16, name: ByteStr) -> Result<Option<(u16, ByteStr)>, WireError> {
+    let mut buffer = BytesMut::new();
+
+    buffer.put_single::<table::fields::code::Wired>(code);
+    buffer.put_length_prefixed_string::<table::fields::name::Wired>(name)?;
+
+    if buffer.peek_layout::<table::fields::Layout>()?.is_none() {
+        return Ok(None);
+    }
+
+    let Some(code) = buffer.take_single::<table::fields::code::Wired>()? else {
+        return Ok(None);
+    };
+    let Some(name) = buffer.take_length_prefixed_string::<table::fields::name::Wired>()? else {
+        return Ok(None);
+    };
+
+    Ok(Some((code, name)))
+}
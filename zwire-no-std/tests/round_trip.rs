@@ -0,0 +1,35 @@
+use zwire::codec::bytes::{ByteStr, Bytes};
+use zwire_no_std::{round_trip, table_round_trip, Chunk, Encoding};
+
+#[test]
+fn round_trips_derived_records() {
+    let chunk = Chunk {
+        last: true,
+        body: Bytes::from(vec![7; 256]),
+        source: ByteStr::from_static("caf\u{E9}"),
+        notes: vec![ByteStr::from_static("take 2")],
+        encodings: vec![Encoding::Pcm],
+        limit: Some(48_000),
+        label: Some(ByteStr::from_static("mono")),
+    };
+
+    let decoded = round_trip(chunk).unwrap().unwrap();
+
+    assert!(decoded.last);
+    assert_eq!(&decoded.body[..], [7; 256]);
+    assert_eq!(&decoded.source[..], "caf\u{E9}");
+    assert_eq!(decoded.notes, [ByteStr::from_static("take 2")]);
+    assert!(matches!(decoded.encodings[..], [Encoding::Pcm]));
+    assert_eq!(decoded.limit, Some(48_000));
+    assert_eq!(decoded.label.as_deref(), Some("mono"));
+}
+
+#[test]
+fn round_trips_table_fields() {
+    let (code, name) = table_round_trip(7, ByteStr::from_static("relay.local"))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(code, 7);
+    assert_eq!(&name[..], "relay.local");
+}
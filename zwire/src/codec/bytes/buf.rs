@@ -0,0 +1,21 @@
+use ::bytes::{Buf, Bytes, BytesMut};
+use core::ops::Deref;
+
+// Sources the take and peek helpers work on, both hand out sub-slices without copying
+pub trait WireBuf: Buf + Deref<Target = [u8]> {
+    fn split_bytes(&mut self, length: usize) -> Bytes;
+}
+
+impl WireBuf for BytesMut {
+    #[inline]
+    fn split_bytes(&mut self, length: usize) -> Bytes {
+        self.split_to(length).freeze()
+    }
+}
+
+impl WireBuf for Bytes {
+    #[inline]
+    fn split_bytes(&mut self, length: usize) -> Bytes {
+        self.split_to(length)
+    }
+}
@@ -1,11 +1,17 @@
+mod buf;
 mod peek;
+#[cfg(feature = "std")]
+mod pool;
 mod put;
 mod take;
 
+#[cfg(feature = "std")]
+pub use self::pool::{BufferLease, BufferPool, PoolStats};
 pub use self::{
+    buf::WireBuf,
     peek::{BytesPeekExt, PeekLength},
     put::BytesMutPutExt,
-    take::BytesMutTakeExt,
+    take::{BytesMutTakeExt, BytesMutTakeUncheckedExt},
 };
+pub use ::bytes::{Bytes, BytesMut};
 pub use bytestr::ByteStr;
-pub use tokio_util::bytes::{Bytes, BytesMut};
@@ -1,11 +1,14 @@
-use super::super::wired::{WiredInt, WiredLengthPrefixed};
+use super::{
+    super::wired::{WiredInt, WiredLayout, WiredLengthPrefixed},
+    WireBuf,
+};
 use crate::{helpers::CheckedAddWire, WireError};
-use std::marker::PhantomData;
-use tokio_util::bytes::BytesMut;
+use core::marker::PhantomData;
 
 pub struct PeekLength<I: WiredLengthPrefixed> {
     ready: bool,
     length: usize,
+    header_length: usize,
     _phantom: PhantomData<I>,
 }
 
@@ -24,28 +27,43 @@ impl<I: WiredLengthPrefixed> PeekLength<I> {
     pub fn get_with_header(&self) -> Option<usize> {
         let length = self.get()?;
 
-        length.checked_add(I::LengthPrefix::SIZE)
+        length.checked_add(self.header_length)
     }
 }
 
 pub trait BytesPeekExt {
+    // Reads the prefix at the field's static OFFSET, only right for a single variable-length
+    // field at the end of a record. Invalid after a varint, whose size moves every later field,
+    // those fields have no OFFSET and need peek_layout
     fn peek_at<I: WiredLengthPrefixed>(&self) -> Result<PeekLength<I>, WireError>;
+
+    // Total length of a whole record, whatever its layout. `None` until all of it arrived
+    fn peek_layout<L: WiredLayout>(&self) -> Result<Option<usize>, WireError>;
 }
 
-impl BytesPeekExt for BytesMut {
+impl<B: WireBuf> BytesPeekExt for B {
     fn peek_at<I: WiredLengthPrefixed>(&self) -> Result<PeekLength<I>, WireError> {
         const DEFAULT_LENGTH: usize = 0;
 
+        let not_ready = PeekLength {
+            ready: false,
+            length: DEFAULT_LENGTH,
+            header_length: I::LengthPrefix::SIZE,
+            _phantom: PhantomData,
+        };
+
         let start_offset = I::OFFSET;
+        let Some(header_length) = self
+            .get(start_offset..)
+            .and_then(I::LengthPrefix::encoded_size)
+        else {
+            return Ok(not_ready);
+        };
         let end_offset =
-            start_offset.checked_add_wire("OFFSET", I::LengthPrefix::SIZE, "LENGTH_HEADER_SIZE")?;
+            start_offset.checked_add_wire("OFFSET", header_length, "LENGTH_HEADER_SIZE")?;
 
         if self.len() < end_offset {
-            return Ok(PeekLength {
-                ready: false,
-                length: DEFAULT_LENGTH,
-                _phantom: PhantomData,
-            });
+            return Ok(not_ready);
         }
 
         let prefix = &self[start_offset..end_offset];
@@ -54,13 +72,105 @@ impl BytesPeekExt for BytesMut {
             Some(length) => PeekLength {
                 ready: true,
                 length,
+                header_length,
                 _phantom: PhantomData,
             },
-            None => PeekLength {
-                ready: false,
-                length: DEFAULT_LENGTH,
-                _phantom: PhantomData,
-            },
+            None => not_ready,
         })
     }
+
+    #[inline]
+    fn peek_layout<L: WiredLayout>(&self) -> Result<Option<usize>, WireError> {
+        L::measure(self, L::MAX_LENGTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{bytes::BytesMut, wired::WireCodec, Encoder},
+        Bytes,
+    };
+
+    // [u8 length][name...] | [u16 length][body...] | [u32 tail]
+    #[derive(Debug, Clone, WireCodec)]
+    struct Entry {
+        #[wire(u8, length_prefix, 16)]
+        name: Bytes,
+        #[wire(u16, length_prefix, 100)]
+        body: Bytes,
+        #[wire(u32, fixed)]
+        tail: u32,
+    }
+
+    fn encode(name: &'static [u8], body: &'static [u8]) -> BytesMut {
+        let mut destination = BytesMut::new();
+        EntryCodec::default()
+            .encode(
+                Entry {
+                    name: Bytes::from_static(name),
+                    body: Bytes::from_static(body),
+                    tail: 9,
+                },
+                &mut destination,
+            )
+            .unwrap();
+
+        destination
+    }
+
+    #[test]
+    fn measures_records_with_several_variable_length_fields() {
+        for (name, body) in [(&b""[..], &b""[..]), (b"ab", b"body"), (b"name", &[7; 100])] {
+            let encoded = encode(name, body);
+
+            assert_eq!(encoded.len(), 1 + name.len() + 2 + body.len() + 4);
+            assert_eq!(
+                encoded.peek_layout::<fields::Layout>().unwrap(),
+                Some(encoded.len())
+            );
+
+            // Bytes of the next record don't count
+            let mut followed = encoded.clone();
+            followed.extend_from_slice(&[1, 2, 3]);
+
+            assert_eq!(
+                followed.peek_layout::<fields::Layout>().unwrap(),
+                Some(encoded.len())
+            );
+        }
+    }
+
+    #[test]
+    fn measures_partial_records_as_none() {
+        let encoded = encode(b"ab", b"body");
+
+        for length in 0..encoded.len() {
+            assert_eq!(
+                BytesMut::from(&encoded[..length])
+                    .peek_layout::<fields::Layout>()
+                    .unwrap(),
+                None,
+                "{length} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_records_past_max_length() {
+        let encoded = encode(b"ab", b"body");
+
+        assert!(matches!(
+            <fields::Layout as WiredLayout>::measure(&encoded, encoded.len() - 1),
+            Err(WireError::Oversized("total_length", length, max))
+                if length == encoded.len() && max == encoded.len() - 1
+        ));
+
+        // The body claims 200 bytes, more than its field takes, before any of them arrived
+        assert!(matches!(
+            BytesMut::from(&[0, 0, 200][..]).peek_layout::<fields::Layout>(),
+            Err(WireError::Oversized("body", 200, 100))
+        ));
+    }
 }
@@ -0,0 +1,286 @@
+use super::BytesMut;
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+const SHARED_MAX_BUFFERS: usize = 1024;
+const SHARED_BUFFER_CAPACITY: usize = 8 * 1024;
+
+// Buffers that grew past this many times the pool's capacity are dropped instead of kept
+const MAX_GROWTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    // Leases served from a pooled buffer
+    pub hits: u64,
+    // Leases that had to allocate
+    pub misses: u64,
+    pub leased: usize,
+    pub leased_high_water: usize,
+    pub pooled: usize,
+    pub pooled_high_water: usize,
+}
+
+struct Shared {
+    buffers: Mutex<Vec<BytesMut>>,
+    max_buffers: usize,
+    buffer_capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    leased: AtomicUsize,
+    leased_high_water: AtomicUsize,
+    pooled_high_water: AtomicUsize,
+}
+
+// Scratch buffers to reuse across connections and codecs, keeping at most `max_buffers` of them
+// around while they're not leased. Clones share the buffers
+#[derive(Clone)]
+pub struct BufferPool {
+    shared: Arc<Shared>,
+}
+
+impl BufferPool {
+    // Leases start out with at least `buffer_capacity` bytes
+    pub fn new(max_buffers: usize, buffer_capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                buffers: Mutex::new(Vec::new()),
+                max_buffers,
+                buffer_capacity: buffer_capacity.max(1),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                leased: AtomicUsize::new(0),
+                leased_high_water: AtomicUsize::new(0),
+                pooled_high_water: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    // The process wide pool ZwireStream, DatagramTransport and FragmentCodec lease from unless
+    // they're given another one. Keeps up to 1024 buffers of 8 KiB
+    pub fn shared() -> &'static BufferPool {
+        static SHARED: OnceLock<BufferPool> = OnceLock::new();
+
+        SHARED.get_or_init(|| BufferPool::new(SHARED_MAX_BUFFERS, SHARED_BUFFER_CAPACITY))
+    }
+
+    // The buffer goes back to the pool when the lease is dropped
+    pub fn lease(&self) -> BufferLease {
+        let pooled = self.shared.buffers.lock().unwrap().pop();
+
+        let buffer = match pooled {
+            Some(buffer) => {
+                self.shared.hits.fetch_add(1, Ordering::Relaxed);
+
+                buffer
+            }
+            None => {
+                self.shared.misses.fetch_add(1, Ordering::Relaxed);
+
+                BytesMut::with_capacity(self.shared.buffer_capacity)
+            }
+        };
+
+        let leased = self.shared.leased.fetch_add(1, Ordering::Relaxed) + 1;
+        self.shared
+            .leased_high_water
+            .fetch_max(leased, Ordering::Relaxed);
+
+        BufferLease {
+            buffer,
+            pool: self.clone(),
+        }
+    }
+
+    // Takes in a buffer that wasn't leased from here, e.g. a detached lease's. It's cleared
+    // first, and dropped if the pool is full or the buffer is too small or too big to keep
+    pub fn release(&self, mut buffer: BytesMut) {
+        let buffer_capacity = self.shared.buffer_capacity;
+
+        buffer.clear();
+
+        // Whatever was split off and frozen still shares the allocation, it can only be reused
+        // once those bytes are gone
+        if buffer.capacity() < buffer_capacity && !buffer.try_reclaim(buffer_capacity) {
+            return;
+        }
+
+        if buffer.capacity() > buffer_capacity.saturating_mul(MAX_GROWTH) {
+            return;
+        }
+
+        let mut buffers = self.shared.buffers.lock().unwrap();
+
+        if buffers.len() >= self.shared.max_buffers {
+            return;
+        }
+
+        buffers.push(buffer);
+
+        self.shared
+            .pooled_high_water
+            .fetch_max(buffers.len(), Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.shared.hits.load(Ordering::Relaxed),
+            misses: self.shared.misses.load(Ordering::Relaxed),
+            leased: self.shared.leased.load(Ordering::Relaxed),
+            leased_high_water: self.shared.leased_high_water.load(Ordering::Relaxed),
+            pooled: self.shared.buffers.lock().unwrap().len(),
+            pooled_high_water: self.shared.pooled_high_water.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// A buffer on loan from a BufferPool, derefs to the BytesMut
+pub struct BufferLease {
+    buffer: BytesMut,
+    pool: BufferPool,
+}
+
+impl BufferLease {
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    // Ends the lease without giving the buffer back
+    pub fn detach(mut self) -> BytesMut {
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+impl Deref for BufferLease {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl DerefMut for BufferLease {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl Drop for BufferLease {
+    fn drop(&mut self) {
+        self.pool.shared.leased.fetch_sub(1, Ordering::Relaxed);
+
+        // A detached lease leaves an empty buffer behind, release drops it
+        self.pool.release(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPACITY: usize = 64;
+
+    #[test]
+    fn counts_hits_misses_and_high_water_marks() {
+        let pool = BufferPool::new(4, CAPACITY);
+
+        let first = pool.lease();
+        let second = pool.lease();
+
+        assert!(first.capacity() >= CAPACITY);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                hits: 0,
+                misses: 2,
+                leased: 2,
+                leased_high_water: 2,
+                pooled: 0,
+                pooled_high_water: 0,
+            }
+        );
+
+        drop(first);
+        drop(second);
+
+        let third = pool.lease();
+
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                hits: 1,
+                misses: 2,
+                leased: 1,
+                leased_high_water: 2,
+                pooled: 1,
+                pooled_high_water: 2,
+            }
+        );
+
+        drop(third);
+    }
+
+    #[test]
+    fn keeps_at_most_max_buffers() {
+        let pool = BufferPool::new(1, CAPACITY);
+
+        let leases = [pool.lease(), pool.lease()];
+        drop(leases);
+
+        assert_eq!(pool.stats().pooled, 1);
+    }
+
+    #[test]
+    fn drops_buffers_that_grew_past_max_growth() {
+        let pool = BufferPool::new(4, CAPACITY);
+
+        let mut kept = pool.lease();
+        kept.reserve(CAPACITY * MAX_GROWTH);
+        assert_eq!(kept.capacity(), CAPACITY * MAX_GROWTH);
+
+        let mut grown = pool.lease();
+        grown.reserve(CAPACITY * MAX_GROWTH + 1);
+
+        drop(grown);
+        assert_eq!(pool.stats().pooled, 0);
+
+        drop(kept);
+        assert_eq!(pool.stats().pooled, 1);
+    }
+
+    #[test]
+    fn drops_buffers_still_shared_with_frozen_bytes() {
+        let pool = BufferPool::new(4, CAPACITY);
+
+        let mut lease = pool.lease();
+        lease.extend_from_slice(&[1; CAPACITY]);
+        let frozen = lease.split().freeze();
+
+        drop(lease);
+
+        assert_eq!(pool.stats().pooled, 0);
+        assert_eq!(frozen, &[1; CAPACITY][..]);
+    }
+
+    #[test]
+    fn takes_detached_buffers_back_on_release() {
+        let pool = BufferPool::new(4, CAPACITY);
+
+        let mut buffer = pool.lease().detach();
+        buffer.extend_from_slice(b"left over");
+
+        assert_eq!(pool.stats().leased, 0);
+        assert_eq!(pool.stats().pooled, 0);
+
+        pool.release(buffer);
+
+        let lease = pool.lease();
+
+        assert_eq!(pool.stats().hits, 1);
+        assert!(lease.is_empty());
+    }
+}
@@ -1,11 +1,17 @@
 use crate::{
     codec::bytes::ByteStr,
-    codec::wired::{WiredFixedBytes, WiredInt, WiredLengthPrefixed, WiredString},
+    codec::{
+        wired::{
+            NestedRecord, VarInt, WiredElement, WiredFixedBytes, WiredInt, WiredLengthPrefixed,
+            WiredNested, WiredRepeated, WiredString, WiredStringPolicy, WiredVarInt,
+        },
+        Encoder,
+    },
     errors::{MalformedStringError, MalformedStringKind},
     WireError,
 };
-use std::cmp::Ordering;
-use tokio_util::bytes::{BufMut, Bytes, BytesMut};
+use ::bytes::{BufMut, Bytes, BytesMut};
+use core::cmp::Ordering;
 
 pub trait BytesMutPutExt {
     fn put_single<I: WiredInt>(&mut self, value: <I as WiredInt>::Int);
@@ -18,6 +24,16 @@ pub trait BytesMutPutExt {
         &mut self,
         payload: impl Into<Bytes>,
     ) -> Result<(), WireError>;
+    fn put_repeated<R: WiredRepeated>(
+        &mut self,
+        values: &[<R::Element as WiredElement>::Value],
+    ) -> Result<(), WireError>;
+    fn put_nested<N: WiredNested>(&mut self, record: NestedRecord<N>) -> Result<(), WireError>;
+    fn put_length_prefixed_nested<N: WiredNested + WiredLengthPrefixed>(
+        &mut self,
+        record: NestedRecord<N>,
+    ) -> Result<(), WireError>;
+    fn put_extension(&mut self, tag: VarInt, value: &[u8]) -> Result<(), WireError>;
 }
 
 impl BytesMutPutExt for BytesMut {
@@ -67,7 +83,8 @@ impl BytesMutPutExt for BytesMut {
             ));
         }
 
-        let payload_length_bytes = I::LengthPrefix::to_bytes_from_usize(payload_length);
+        let payload_length_bytes =
+            I::LengthPrefix::to_bytes_from_usize(payload_length, I::FIELD_NAME)?;
 
         self.put_slice(payload_length_bytes.as_ref());
         self.put_slice(payload);
@@ -85,7 +102,7 @@ impl BytesMutPutExt for BytesMut {
                 kind: MalformedStringKind::InvalidUtf8(error),
             })?;
 
-        if let Err(mut error) = I::POLICY.validate(&byte_string) {
+        if let Err(mut error) = I::Policy::validate(&byte_string) {
             error.field = Some(I::FIELD_NAME);
 
             return Err(WireError::MalformedString(error));
@@ -93,4 +110,65 @@ impl BytesMutPutExt for BytesMut {
 
         self.put_length_prefixed::<I::Inner>(&byte_string.into_bytes())
     }
+
+    // Lengths are checked up front, an element failing its own checks (e.g. a string policy)
+    // takes the partially written list back out
+    fn put_repeated<R: WiredRepeated>(
+        &mut self,
+        values: &[<R::Element as WiredElement>::Value],
+    ) -> Result<(), WireError> {
+        let total_length = R::encoded_length(values)?;
+        let start_offset = self.len();
+
+        self.reserve(total_length);
+        self.put_slice(R::CountPrefix::to_bytes_from_usize(values.len(), R::FIELD_NAME)?.as_ref());
+
+        for value in values {
+            if let Err(error) = R::Element::put(self, value) {
+                self.truncate(start_offset);
+
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn put_nested<N: WiredNested>(&mut self, record: NestedRecord<N>) -> Result<(), WireError> {
+        N::Codec::default().encode(record, self)
+    }
+
+    // The record's length is only known once it's encoded
+    fn put_length_prefixed_nested<N: WiredNested + WiredLengthPrefixed>(
+        &mut self,
+        record: NestedRecord<N>,
+    ) -> Result<(), WireError> {
+        let mut payload = BytesMut::new();
+
+        N::Codec::default().encode(record, &mut payload)?;
+
+        let payload_length = payload.len();
+
+        if payload_length > N::MAX_LENGTH {
+            return Err(WireError::Oversized(
+                N::FIELD_NAME,
+                payload_length,
+                N::MAX_LENGTH,
+            ));
+        }
+
+        self.put_length_prefixed::<N>(&payload.freeze())
+    }
+
+    // One entry of a table's extensions block: [tag] | [value length] | [value], both varints
+    fn put_extension(&mut self, tag: VarInt, value: &[u8]) -> Result<(), WireError> {
+        let value_length = WiredVarInt::to_bytes_from_usize(value.len(), "extension")?;
+
+        self.put_single::<WiredVarInt>(tag);
+        self.put_slice(value_length.as_ref());
+        self.put_slice(value);
+
+        Ok(())
+    }
 }
@@ -1,56 +1,100 @@
-use super::super::wired::{WiredFixedBytes, WiredInt, WiredLengthPrefixed, WiredString};
+use super::super::wired::{
+    NestedRecord, VarInt, WiredElement, WiredField, WiredFixedBytes, WiredInt, WiredLengthPrefixed,
+    WiredNested, WiredRecord, WiredRepeated, WiredString, WiredStringPolicy, WiredVarInt,
+};
 use crate::{
-    codec::bytes::ByteStr,
+    codec::bytes::{ByteStr, WireBuf},
     errors::{MalformedStringError, MalformedStringKind, WireError},
     helpers::CheckedAddWire,
+    BufDecoder,
 };
-use tokio_util::bytes::{Buf, Bytes, BytesMut};
+use ::bytes::Bytes;
+use alloc::vec::Vec;
 
+// Every method is total: a source too short for the value reads as `None` and nothing is
+// consumed, malformed contents are an error. None of them panics, whatever the input
 pub trait BytesMutTakeExt {
-    fn take_single<I: WiredInt>(&mut self) -> Option<<I as WiredInt>::Int>;
-    fn take_single_unchecked<I: WiredInt>(&mut self) -> <I as WiredInt>::Int;
+    // The value is checked before it's read, e.g. a bool byte other than 0 or 1 is an error
+    fn take_single<I: WiredInt + WiredField>(
+        &mut self,
+    ) -> Result<Option<<I as WiredInt>::Int>, WireError>;
 
-    fn take_fixed_bytes_unchecked<F: WiredFixedBytes>(&mut self) -> F::Output;
     fn take_fixed_bytes<F: WiredFixedBytes>(&mut self) -> Option<F::Output>;
 
-    fn take_length_prefixed_unchecked<I: WiredLengthPrefixed>(
-        &mut self,
-    ) -> Result<Bytes, WireError>;
     fn take_length_prefixed<I: WiredLengthPrefixed>(&mut self) -> Result<Option<Bytes>, WireError>;
 
     fn take_length_prefixed_string<I: WiredString>(&mut self)
         -> Result<Option<ByteStr>, WireError>;
-    fn take_length_prefixed_string_unchecked<I: WiredString>(
+
+    fn take_repeated<R: WiredRepeated>(
         &mut self,
-    ) -> Result<ByteStr, WireError>;
+    ) -> Result<Option<Vec<<R::Element as WiredElement>::Value>>, WireError>;
+
+    fn take_nested<N: WiredNested>(&mut self) -> Result<Option<NestedRecord<N>>, WireError>;
+    fn take_length_prefixed_nested<N: WiredNested + WiredLengthPrefixed>(
+        &mut self,
+    ) -> Result<Option<NestedRecord<N>>, WireError>;
+
+    fn take_extension(&mut self) -> Result<Option<(VarInt, Bytes)>, WireError>;
 }
 
-impl BytesMutTakeExt for BytesMut {
-    #[inline]
-    fn take_single_unchecked<I: WiredInt>(&mut self) -> <I as WiredInt>::Int {
-        let size = I::SIZE;
-        let value = I::read_raw_unchecked(&self[..size]);
+// Opt-in shorthands for callers that already measured the whole record, e.g. with
+// WiredLayout::measure. Where the checked methods return `None` these panic, so they have to be
+// imported explicitly and nothing decoding peer input in zwire or the derived codecs uses them
+pub trait BytesMutTakeUncheckedExt {
+    fn take_single_unchecked<I: WiredInt + WiredField>(
+        &mut self,
+    ) -> Result<<I as WiredInt>::Int, WireError>;
 
-        self.advance(size);
+    fn take_fixed_bytes_unchecked<F: WiredFixedBytes>(&mut self) -> F::Output;
 
-        value
-    }
+    fn take_length_prefixed_unchecked<I: WiredLengthPrefixed>(
+        &mut self,
+    ) -> Result<Bytes, WireError>;
 
-    #[inline]
-    fn take_single<I: WiredInt>(&mut self) -> Option<<I as WiredInt>::Int> {
-        let size = I::SIZE;
-        let value = I::read_raw(&self[..size])?;
+    fn take_length_prefixed_string_unchecked<I: WiredString>(
+        &mut self,
+    ) -> Result<ByteStr, WireError>;
 
-        self.advance(size);
+    fn take_repeated_unchecked<R: WiredRepeated>(
+        &mut self,
+    ) -> Result<Vec<<R::Element as WiredElement>::Value>, WireError>;
 
-        Some(value)
-    }
+    fn take_length_prefixed_nested_unchecked<N: WiredNested + WiredLengthPrefixed>(
+        &mut self,
+    ) -> Result<NestedRecord<N>, WireError>;
+}
+
+// Bytes after the record are skipped, a newer peer may have appended fields to it
+fn decode_nested_payload<N: WiredNested>(mut payload: Bytes) -> Result<NestedRecord<N>, WireError> {
+    let payload_length = payload.len();
+
+    N::Codec::default()
+        .decode_buf(&mut payload)?
+        .ok_or(WireError::Underflow(
+            N::FIELD_NAME,
+            payload_length,
+            N::Codec::FIXED_PART_LENGTH,
+        ))
+}
 
+impl<B: WireBuf> BytesMutTakeExt for B {
     #[inline]
-    fn take_fixed_bytes_unchecked<F: WiredFixedBytes>(&mut self) -> F::Output {
-        let chunk: Bytes = self.split_to(F::LENGTH).freeze();
+    fn take_single<I: WiredInt + WiredField>(
+        &mut self,
+    ) -> Result<Option<<I as WiredInt>::Int>, WireError> {
+        I::check(self, I::FIELD_NAME)?;
+
+        let Some(size) = I::encoded_size(self) else {
+            return Ok(None);
+        };
+        let Some(value) = self.get(..size).and_then(I::read_raw) else {
+            return Ok(None);
+        };
+
+        self.advance(size);
 
-        F::from_bytes(chunk)
+        Ok(Some(value))
     }
 
     #[inline]
@@ -59,42 +103,20 @@ impl BytesMutTakeExt for BytesMut {
             return None;
         }
 
-        Some(self.take_fixed_bytes_unchecked::<F>())
-    }
-
-    fn take_length_prefixed_unchecked<I: WiredLengthPrefixed>(
-        &mut self,
-    ) -> Result<Bytes, WireError> {
-        let size = I::LengthPrefix::SIZE;
-        let max_payload_length = I::MAX_LENGTH;
-        let expected_payload_length =
-            I::LengthPrefix::read_unchecked(&self[..size], "payload_length")?;
-
-        if expected_payload_length > max_payload_length {
-            return Err(WireError::Oversized(
-                I::FIELD_NAME,
-                expected_payload_length,
-                max_payload_length,
-            ));
-        }
-
-        self.advance(size);
-
-        let bytes = self.split_to(expected_payload_length).freeze();
-
-        Ok(bytes)
+        Some(F::from_bytes(self.split_bytes(F::LENGTH)))
     }
 
     fn take_length_prefixed<I: WiredLengthPrefixed>(&mut self) -> Result<Option<Bytes>, WireError> {
-        let size = I::LengthPrefix::SIZE;
+        let Some(size) = I::LengthPrefix::encoded_size(self) else {
+            return Ok(None);
+        };
         let max_payload_length = I::MAX_LENGTH;
 
-        if self.len() < size {
+        let Some(prefix) = self.get(..size) else {
             return Ok(None);
-        }
+        };
 
-        let Some(expected_payload_length) = I::LengthPrefix::read(&self[..size], "payload_length")?
-        else {
+        let Some(expected_payload_length) = I::LengthPrefix::read(prefix, "payload_length")? else {
             return Ok(None);
         };
 
@@ -118,48 +140,262 @@ impl BytesMutTakeExt for BytesMut {
 
         self.advance(size);
 
-        let bytes = self.split_to(expected_payload_length).freeze();
+        let bytes = self.split_bytes(expected_payload_length);
 
         Ok(Some(bytes))
     }
 
-    fn take_length_prefixed_string_unchecked<I: WiredString>(
+    fn take_length_prefixed_string<I: WiredString>(
         &mut self,
-    ) -> Result<ByteStr, WireError> {
-        let payload = self.take_length_prefixed_unchecked::<I::Inner>()?;
+    ) -> Result<Option<ByteStr>, WireError> {
+        let Some(payload) = self.take_length_prefixed::<I::Inner>()? else {
+            return Ok(None);
+        };
 
         let byte_string = ByteStr::from_utf8(payload).map_err(|error| MalformedStringError {
             field: Some(I::FIELD_NAME),
             kind: MalformedStringKind::InvalidUtf8(error),
         })?;
 
-        if let Err(mut error) = I::POLICY.validate(&byte_string) {
+        if let Err(mut error) = I::Policy::validate(&byte_string) {
             error.field = Some(I::FIELD_NAME);
 
             return Err(WireError::MalformedString(error));
         };
 
-        Ok(byte_string)
+        Ok(Some(byte_string))
     }
 
-    fn take_length_prefixed_string<I: WiredString>(
+    // Nothing is consumed until every element arrived. An element measure saw whole but its take
+    // doesn't is an error, the list is inconsistent rather than incomplete. So is an element
+    // failing its own checks, e.g. a string policy, which leaves the list partly consumed
+    fn take_repeated<R: WiredRepeated>(
         &mut self,
-    ) -> Result<Option<ByteStr>, WireError> {
-        let Some(payload) = self.take_length_prefixed::<I::Inner>()? else {
+    ) -> Result<Option<Vec<<R::Element as WiredElement>::Value>>, WireError> {
+        if R::measure(self)?.is_none() {
             return Ok(None);
+        }
+
+        let (Some(size), Some(count)) = (
+            R::CountPrefix::encoded_size(self),
+            R::CountPrefix::read(self, R::FIELD_NAME)?,
+        ) else {
+            return Err(WireError::Truncated(R::FIELD_NAME));
         };
 
-        let byte_string = ByteStr::from_utf8(payload).map_err(|error| MalformedStringError {
-            field: Some(I::FIELD_NAME),
-            kind: MalformedStringKind::InvalidUtf8(error),
-        })?;
+        if count > R::MAX_COUNT {
+            return Err(WireError::Oversized(R::FIELD_NAME, count, R::MAX_COUNT));
+        }
 
-        if let Err(mut error) = I::POLICY.validate(&byte_string) {
-            error.field = Some(I::FIELD_NAME);
+        self.advance(size);
 
-            return Err(WireError::MalformedString(error));
+        let mut values = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let Some(value) = R::Element::take(self)? else {
+                return Err(WireError::Truncated(R::FIELD_NAME));
+            };
+
+            values.push(value);
+        }
+
+        Ok(Some(values))
+    }
+
+    // Records without a length prefix have a fixed size, the codec itself tells when it's complete
+    #[inline]
+    fn take_nested<N: WiredNested>(&mut self) -> Result<Option<NestedRecord<N>>, WireError> {
+        N::Codec::default().decode_buf(self)
+    }
+
+    fn take_length_prefixed_nested<N: WiredNested + WiredLengthPrefixed>(
+        &mut self,
+    ) -> Result<Option<NestedRecord<N>>, WireError> {
+        let Some(payload) = self.take_length_prefixed::<N>()? else {
+            return Ok(None);
         };
 
-        Ok(Some(byte_string))
+        decode_nested_payload::<N>(payload).map(Some)
+    }
+
+    // Tag and value of the next entry in an extensions block, `None` until the whole entry is there
+    fn take_extension(&mut self) -> Result<Option<(VarInt, Bytes)>, WireError> {
+        let (Some(tag_size), Some(tag)) =
+            (WiredVarInt::encoded_size(self), WiredVarInt::read_raw(self))
+        else {
+            return Ok(None);
+        };
+        let Some(length_source) = self.get(tag_size..) else {
+            return Ok(None);
+        };
+        let (Some(length_size), Some(value_length)) = (
+            WiredVarInt::encoded_size(length_source),
+            WiredVarInt::read(length_source, "extension")?,
+        ) else {
+            return Ok(None);
+        };
+        let header_size = tag_size.checked_add_wire("tag_size", length_size, "extension")?;
+        let total_length =
+            header_size.checked_add_wire("header_size", value_length, "extension")?;
+
+        if self.len() < total_length {
+            return Ok(None);
+        }
+
+        self.advance(header_size);
+
+        Ok(Some((tag, self.split_bytes(value_length))))
+    }
+}
+
+// Incomplete input can't be told from a caller's mistake here, so it ends in a panic
+const INCOMPLETE: &str = "unchecked take on a source that doesn't hold the whole value";
+
+impl<B: WireBuf> BytesMutTakeUncheckedExt for B {
+    #[inline]
+    fn take_single_unchecked<I: WiredInt + WiredField>(
+        &mut self,
+    ) -> Result<<I as WiredInt>::Int, WireError> {
+        Ok(self.take_single::<I>()?.expect(INCOMPLETE))
+    }
+
+    #[inline]
+    fn take_fixed_bytes_unchecked<F: WiredFixedBytes>(&mut self) -> F::Output {
+        self.take_fixed_bytes::<F>().expect(INCOMPLETE)
+    }
+
+    #[inline]
+    fn take_length_prefixed_unchecked<I: WiredLengthPrefixed>(
+        &mut self,
+    ) -> Result<Bytes, WireError> {
+        Ok(self.take_length_prefixed::<I>()?.expect(INCOMPLETE))
+    }
+
+    #[inline]
+    fn take_length_prefixed_string_unchecked<I: WiredString>(
+        &mut self,
+    ) -> Result<ByteStr, WireError> {
+        Ok(self.take_length_prefixed_string::<I>()?.expect(INCOMPLETE))
+    }
+
+    #[inline]
+    fn take_repeated_unchecked<R: WiredRepeated>(
+        &mut self,
+    ) -> Result<Vec<<R::Element as WiredElement>::Value>, WireError> {
+        Ok(self.take_repeated::<R>()?.expect(INCOMPLETE))
+    }
+
+    #[inline]
+    fn take_length_prefixed_nested_unchecked<N: WiredNested + WiredLengthPrefixed>(
+        &mut self,
+    ) -> Result<NestedRecord<N>, WireError> {
+        Ok(self.take_length_prefixed_nested::<N>()?.expect(INCOMPLETE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{bytes::BytesMut, wired::WireCodec, Decoder, Encoder};
+
+    // [u8 version = 1] | [u16 id] | [varint length][tag 1: u32 rate]
+    #[derive(Debug, Clone, PartialEq, WireCodec)]
+    #[wire(fields = older_fields, version = 1, extensions = 64)]
+    struct Older {
+        #[wire(u16, fixed)]
+        id: u16,
+        #[wire(u32, fixed, tag = 1)]
+        rate: Option<u32>,
+    }
+
+    // Version 2 of the same table, adding tag 2
+    #[derive(Debug, Clone, PartialEq, WireCodec)]
+    #[wire(fields = newer_fields, version = 2, extensions = 64)]
+    struct Newer {
+        #[wire(u16, fixed)]
+        id: u16,
+        #[wire(u32, fixed, tag = 1)]
+        rate: Option<u32>,
+        #[wire(u8, length_prefix, 16, tag = 2)]
+        label: Option<Bytes>,
+    }
+
+    fn encode<C: Encoder<T, Error = WireError> + Default, T>(item: T) -> BytesMut {
+        let mut destination = BytesMut::new();
+        C::default().encode(item, &mut destination).unwrap();
+
+        destination
+    }
+
+    #[test]
+    fn skips_tags_a_newer_version_added() {
+        let mut source = encode::<NewerCodec, _>(Newer {
+            id: 7,
+            rate: Some(48_000),
+            label: Some(Bytes::from_static(b"studio")),
+        });
+
+        let mut codec = OlderCodec::default();
+        let decoded = codec.decode(&mut source).unwrap().unwrap();
+
+        assert_eq!(
+            decoded,
+            Older {
+                id: 7,
+                rate: Some(48_000),
+            }
+        );
+        assert_eq!(codec.decoded_version(), Some(2));
+        assert!(source.is_empty());
+    }
+
+    #[test]
+    fn reads_tags_an_older_version_lacks_as_none() {
+        let mut source = encode::<OlderCodec, _>(Older { id: 7, rate: None });
+
+        let mut codec = NewerCodec::default();
+        let decoded = codec.decode(&mut source).unwrap().unwrap();
+
+        assert_eq!(
+            decoded,
+            Newer {
+                id: 7,
+                rate: None,
+                label: None,
+            }
+        );
+        assert_eq!(codec.decoded_version(), Some(1));
+    }
+
+    #[test]
+    fn rejects_duplicate_and_malformed_entries() {
+        let decode = |block: &[u8]| {
+            let mut source = BytesMut::from(&[1, 0, 7, block.len() as u8][..]);
+            source.extend_from_slice(block);
+
+            let mut codec = OlderCodec::default();
+            let result = codec.decode(&mut source);
+
+            assert_eq!(codec.decoded_version(), None);
+
+            result
+        };
+
+        assert!(matches!(
+            decode(&[1, 4, 0, 0, 0, 1, 1, 4, 0, 0, 0, 2]),
+            Err(WireError::DuplicateExtension("rate", 1))
+        ));
+
+        // The entry claims more bytes than the block holds
+        assert!(matches!(
+            decode(&[1, 4, 0, 0]),
+            Err(WireError::InvalidExtension("extensions"))
+        ));
+
+        // A whole entry, too short for the u32 behind its tag
+        assert!(matches!(
+            decode(&[1, 2, 0, 0]),
+            Err(WireError::InvalidExtension("rate"))
+        ));
     }
 }
@@ -0,0 +1,517 @@
+use super::{
+    bytes::{BufferLease, BufferPool, BytesMut, BytesMutPutExt},
+    wired::{define_fields, WiredInt},
+    Decoder, Encoder, FrameCodec, FrameTransform, NoTransform,
+};
+use crate::{errors::WireError, helpers::CheckedAddWire, Frame, Message};
+use ::bytes::Bytes;
+use std::time::{Duration, Instant};
+
+// [u8 flags] | [chunk...], carried as the payload of every inner frame
+define_fields! {
+    (Flags, u8, fixed),
+}
+
+const FRAGMENT_START: u8 = 0b01;
+const FRAGMENT_END: u8 = 0b10;
+
+const DEFAULT_MAX_MESSAGE_LENGTH: usize = 1024 * 1024;
+const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Reassembly {
+    message: Message,
+    buffer: BytesMut,
+    started_at: Instant,
+}
+
+pub struct FragmentCodec<T: FrameTransform = NoTransform> {
+    inner: FrameCodec<T>,
+    chunk_length: usize,
+    max_message_length: usize,
+    reassembly_timeout: Duration,
+    pending: Option<Reassembly>,
+    scratch: BufferLease,
+}
+
+impl<T: FrameTransform> FragmentCodec<T> {
+    pub fn new(inner: FrameCodec<T>) -> Result<Self, WireError> {
+        let chunk_length = inner
+            .max_payload_length()
+            .saturating_sub(fields::FIXED_PART_LENGTH);
+
+        if chunk_length == 0 {
+            return Err(WireError::Underflow(
+                "max_payload_length",
+                inner.max_payload_length(),
+                fields::FIXED_PART_LENGTH + 1,
+            ));
+        }
+
+        Ok(Self {
+            inner,
+            chunk_length,
+            max_message_length: DEFAULT_MAX_MESSAGE_LENGTH,
+            reassembly_timeout: DEFAULT_REASSEMBLY_TIMEOUT,
+            pending: None,
+            scratch: BufferPool::shared().lease(),
+        })
+    }
+
+    pub fn max_message_length(mut self, max_message_length: usize) -> Self {
+        self.max_message_length = max_message_length;
+        self
+    }
+
+    // How long an incomplete message may wait for its remaining fragments. Checked whenever new
+    // bytes are decoded and by expire, which a quiet peer leaves as the only check
+    pub fn reassembly_timeout(mut self, reassembly_timeout: Duration) -> Self {
+        self.reassembly_timeout = reassembly_timeout;
+        self
+    }
+
+    // Leases the buffer chunks are assembled in from `pool` instead of the shared one
+    pub fn buffer_pool(mut self, pool: &BufferPool) -> Self {
+        self.scratch = pool.lease();
+        self
+    }
+
+    pub fn inner(&self) -> &FrameCodec<T> {
+        &self.inner
+    }
+
+    // When the incomplete message expires, `None` without one
+    pub fn deadline(&self) -> Option<Instant> {
+        let reassembly = self.pending.as_ref()?;
+
+        Some(reassembly.started_at + self.reassembly_timeout)
+    }
+
+    // Drops the incomplete message and its buffer if it expired by `now`. Meant for a timer set
+    // to the deadline, e.g. `framed.decoder_mut().expire(Instant::now())`
+    pub fn expire(&mut self, now: Instant) -> Result<(), WireError> {
+        if self.deadline().is_some_and(|deadline| now > deadline) {
+            self.pending = None;
+
+            return Err(WireError::ReassemblyTimeout(self.reassembly_timeout));
+        }
+
+        Ok(())
+    }
+
+    fn accept_fragment(&mut self, frame: Frame) -> Result<Option<Frame>, WireError> {
+        let mut payload = frame.payload;
+
+        let Some(flags) = payload
+            .get(..fields::FIXED_PART_LENGTH)
+            .and_then(<fields::flags::Wired as WiredInt>::read_raw)
+        else {
+            return Err(WireError::Underflow(
+                "fragment_header",
+                payload.len(),
+                fields::FIXED_PART_LENGTH,
+            ));
+        };
+
+        let chunk = payload.split_off(fields::FIXED_PART_LENGTH);
+        let is_start = flags & FRAGMENT_START != 0;
+        let is_end = flags & FRAGMENT_END != 0;
+
+        match self.pending.take() {
+            None if !is_start => Err(WireError::UnexpectedFragment(
+                "continuation without a start fragment",
+            )),
+            None if is_end => Ok(Some(Frame {
+                message: frame.message,
+                payload: chunk,
+            })),
+            None => {
+                self.check_length(chunk.len())?;
+
+                let mut buffer = BytesMut::with_capacity(chunk.len() * 2);
+                buffer.extend_from_slice(&chunk);
+
+                self.pending = Some(Reassembly {
+                    message: frame.message,
+                    buffer,
+                    started_at: Instant::now(),
+                });
+
+                Ok(None)
+            }
+            Some(_) if is_start => Err(WireError::UnexpectedFragment(
+                "start fragment while a message is incomplete",
+            )),
+            Some(reassembly) if reassembly.message.0 != frame.message.0 => Err(
+                WireError::UnexpectedFragment("message code changed between fragments"),
+            ),
+            Some(mut reassembly) => {
+                let reassembled_length = reassembly.buffer.len().checked_add_wire(
+                    "reassembled_length",
+                    chunk.len(),
+                    "chunk_length",
+                )?;
+
+                self.check_length(reassembled_length)?;
+                reassembly.buffer.extend_from_slice(&chunk);
+
+                if is_end {
+                    return Ok(Some(Frame {
+                        message: reassembly.message,
+                        payload: reassembly.buffer.freeze(),
+                    }));
+                }
+
+                self.pending = Some(reassembly);
+
+                Ok(None)
+            }
+        }
+    }
+
+    fn check_length(&self, length: usize) -> Result<(), WireError> {
+        if length > self.max_message_length {
+            return Err(WireError::Oversized(
+                "message_length",
+                length,
+                self.max_message_length,
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn encode_fragments(
+        &mut self,
+        frame: Frame,
+        destination: &mut BytesMut,
+    ) -> Result<(), WireError> {
+        let payload_length = frame.payload.len();
+
+        self.check_length(payload_length)?;
+
+        let mut remaining: Bytes = frame.payload;
+        let mut flags = FRAGMENT_START;
+
+        loop {
+            let chunk_length = remaining.len().min(self.chunk_length);
+            let chunk = remaining.split_to(chunk_length);
+
+            if remaining.is_empty() {
+                flags |= FRAGMENT_END;
+            }
+
+            self.scratch
+                .reserve(fields::FIXED_PART_LENGTH + chunk_length);
+            self.scratch.put_single::<fields::flags::Wired>(flags);
+            self.scratch.extend_from_slice(&chunk);
+
+            self.inner.encode(
+                Frame {
+                    message: frame.message.clone(),
+                    payload: self.scratch.split().freeze(),
+                },
+                destination,
+            )?;
+
+            if remaining.is_empty() {
+                return Ok(());
+            }
+
+            flags = 0;
+        }
+    }
+}
+
+impl<T: FrameTransform> Encoder<Frame> for FragmentCodec<T> {
+    type Error = WireError;
+
+    // A message is written whole or not at all, fragments already written are truncated away when
+    // a later one fails to encode
+    fn encode(&mut self, frame: Frame, destination: &mut BytesMut) -> Result<(), Self::Error> {
+        let start_offset = destination.len();
+
+        self.encode_fragments(frame, destination)
+            .inspect_err(|_| destination.truncate(start_offset))
+    }
+}
+
+impl<T: FrameTransform> Decoder for FragmentCodec<T> {
+    type Item = Frame;
+    type Error = WireError;
+
+    fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.expire(Instant::now())?;
+
+        while let Some(frame) = self.inner.decode(source)? {
+            if let Some(frame) = self.accept_fragment(frame)? {
+                return Ok(Some(frame));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn decode_eof(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(frame) = self.decode(source)? {
+            return Ok(Some(frame));
+        }
+
+        if self.pending.take().is_some() {
+            return Err(WireError::UnexpectedFragment(
+                "stream ended while a message is incomplete",
+            ));
+        }
+
+        self.inner.decode_eof(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fragments carry 8 bytes of the message each
+    const CHUNK_LENGTH: usize = 8;
+
+    fn inner_codec() -> FrameCodec {
+        FrameCodec::builder()
+            .max_payload_length(CHUNK_LENGTH + fields::FIXED_PART_LENGTH)
+            .build()
+            .unwrap()
+    }
+
+    fn codec() -> FragmentCodec {
+        FragmentCodec::new(inner_codec()).unwrap()
+    }
+
+    fn frame(code: u32, payload: &[u8]) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    // The encoded fragments of a message, one buffer each
+    fn fragments(code: u32, payload: &[u8]) -> Vec<BytesMut> {
+        let mut encoded = BytesMut::new();
+        codec().encode(frame(code, payload), &mut encoded).unwrap();
+
+        let mut inner = inner_codec();
+        let mut fragments = Vec::new();
+
+        while let Some(fragment) = inner.decode(&mut encoded).unwrap() {
+            let mut buffer = BytesMut::new();
+            inner.encode(fragment, &mut buffer).unwrap();
+            fragments.push(buffer);
+        }
+
+        fragments
+    }
+
+    fn decode_all(
+        codec: &mut FragmentCodec,
+        fragments: Vec<BytesMut>,
+    ) -> Result<Vec<Frame>, WireError> {
+        let mut frames = Vec::new();
+
+        for mut fragment in fragments {
+            if let Some(frame) = codec.decode(&mut fragment)? {
+                frames.push(frame);
+            }
+        }
+
+        Ok(frames)
+    }
+
+    #[test]
+    fn round_trips_split_messages() {
+        let payload: Vec<u8> = (0..100).collect();
+        let mut codec = codec();
+
+        let mut encoded = BytesMut::new();
+        codec.encode(frame(3, &payload), &mut encoded).unwrap();
+        codec.encode(frame(4, b"short"), &mut encoded).unwrap();
+        codec.encode(frame(5, b""), &mut encoded).unwrap();
+
+        let mut frames = Vec::new();
+
+        // One byte at a time, fragments straddle every read
+        let mut source = BytesMut::new();
+
+        for byte in encoded {
+            source.extend_from_slice(&[byte]);
+
+            if let Some(frame) = codec.decode(&mut source).unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            (frames[0].message.0, &frames[0].payload[..]),
+            (3, &payload[..])
+        );
+        assert_eq!(
+            (frames[1].message.0, &frames[1].payload[..]),
+            (4, &b"short"[..])
+        );
+        assert_eq!((frames[2].message.0, &frames[2].payload[..]), (5, &b""[..]));
+        assert_eq!(codec.deadline(), None);
+    }
+
+    #[test]
+    fn splits_into_chunk_sized_fragments() {
+        assert_eq!(fragments(1, &[0; CHUNK_LENGTH]).len(), 1);
+        assert_eq!(fragments(1, &[0; CHUNK_LENGTH + 1]).len(), 2);
+        assert_eq!(fragments(1, &[0; 10 * CHUNK_LENGTH]).len(), 10);
+    }
+
+    #[test]
+    fn rejects_interleaved_messages() {
+        let first = fragments(1, &[1; 3 * CHUNK_LENGTH]);
+        let second = fragments(2, &[2; 3 * CHUNK_LENGTH]);
+
+        // A second message starting before the first one ended
+        let interleaved = vec![first[0].clone(), second[0].clone()];
+
+        assert!(matches!(
+            decode_all(&mut codec(), interleaved),
+            Err(WireError::UnexpectedFragment(
+                "start fragment while a message is incomplete"
+            ))
+        ));
+
+        // Or continuing one it never started
+        let interleaved = vec![first[0].clone(), second[1].clone()];
+
+        assert!(matches!(
+            decode_all(&mut codec(), interleaved),
+            Err(WireError::UnexpectedFragment(
+                "message code changed between fragments"
+            ))
+        ));
+    }
+
+    #[test]
+    fn rejects_out_of_order_fragments() {
+        let mut fragments = fragments(1, &[1; 3 * CHUNK_LENGTH]);
+        fragments.swap(0, 1);
+
+        assert!(matches!(
+            decode_all(&mut codec(), fragments),
+            Err(WireError::UnexpectedFragment(
+                "continuation without a start fragment"
+            ))
+        ));
+    }
+
+    #[test]
+    fn rejects_messages_over_the_limit() {
+        let mut codec = codec().max_message_length(2 * CHUNK_LENGTH);
+
+        assert!(matches!(
+            codec.encode(frame(1, &[0; 2 * CHUNK_LENGTH + 1]), &mut BytesMut::new()),
+            Err(WireError::Oversized("message_length", length, max))
+                if length == 2 * CHUNK_LENGTH + 1 && max == 2 * CHUNK_LENGTH
+        ));
+
+        // A peer with a higher limit is cut off at the first fragment past it
+        let fragments = fragments(1, &[0; 3 * CHUNK_LENGTH]);
+
+        assert!(matches!(
+            decode_all(&mut codec, fragments),
+            Err(WireError::Oversized("message_length", length, _)) if length == 3 * CHUNK_LENGTH
+        ));
+    }
+
+    #[test]
+    fn expires_incomplete_messages_without_new_bytes() {
+        let timeout = Duration::from_secs(1);
+        let mut codec = codec().reassembly_timeout(timeout);
+        let mut fragments = fragments(1, &[1; 3 * CHUNK_LENGTH]);
+
+        assert_eq!(codec.deadline(), None);
+        assert!(codec.decode(&mut fragments[0]).unwrap().is_none());
+
+        let deadline = codec.deadline().unwrap();
+
+        codec.expire(deadline).unwrap();
+        assert!(matches!(
+            codec.expire(deadline + Duration::from_millis(1)),
+            Err(WireError::ReassemblyTimeout(expired)) if expired == timeout
+        ));
+        assert_eq!(codec.deadline(), None);
+
+        // What's left of the expired message has no start to continue
+        assert!(matches!(
+            codec.decode(&mut fragments[1]),
+            Err(WireError::UnexpectedFragment(
+                "continuation without a start fragment"
+            ))
+        ));
+    }
+
+    // Fails every fragment but the first one of a message
+    struct FailingContinuations;
+
+    impl FrameTransform for FailingContinuations {
+        fn apply(&self, payload: &Bytes) -> Result<Option<Bytes>, WireError> {
+            if payload[0] & FRAGMENT_START == 0 {
+                return Err(WireError::InvalidFrameFlags(payload[0]));
+            }
+
+            Ok(None)
+        }
+
+        fn revert(&self, payload: Bytes, _max_length: usize) -> Result<Bytes, WireError> {
+            Ok(payload)
+        }
+    }
+
+    #[test]
+    fn leaves_nothing_behind_when_a_later_fragment_fails() {
+        let failing_codec = || {
+            let inner = FrameCodec::builder()
+                .max_payload_length(CHUNK_LENGTH + fields::FIXED_PART_LENGTH)
+                .transform(FailingContinuations)
+                .build()
+                .unwrap();
+
+            FragmentCodec::new(inner).unwrap()
+        };
+        let mut codec = failing_codec();
+        let mut destination = BytesMut::from(&b"earlier"[..]);
+
+        assert!(matches!(
+            codec.encode(frame(1, &[1; 3 * CHUNK_LENGTH]), &mut destination),
+            Err(WireError::InvalidFrameFlags(0))
+        ));
+        assert_eq!(&destination[..], b"earlier");
+
+        // The next message follows what was there before, nothing of the failed one in between
+        codec.encode(frame(2, b"short"), &mut destination).unwrap();
+
+        let mut source = destination.split_off(b"earlier".len());
+        let decoded = failing_codec().decode(&mut source).unwrap().unwrap();
+
+        assert_eq!(
+            (decoded.message.0, &decoded.payload[..]),
+            (2, &b"short"[..])
+        );
+        assert!(source.is_empty());
+    }
+
+    #[test]
+    fn fails_streams_ending_mid_message() {
+        let mut codec = codec();
+        let mut fragments = fragments(1, &[1; 3 * CHUNK_LENGTH]);
+
+        assert!(codec.decode(&mut fragments[0]).unwrap().is_none());
+        assert!(matches!(
+            codec.decode_eof(&mut BytesMut::new()),
+            Err(WireError::UnexpectedFragment(
+                "stream ended while a message is incomplete"
+            ))
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+use super::{flags_length, FrameChecksum, FrameCodec, FrameHeader, FrameTransform, NoTransform};
+use crate::{errors::WireError, helpers::CheckedAddWire};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameCodecBuilder<T: FrameTransform = NoTransform> {
+    header: FrameHeader,
+    checksum: FrameChecksum,
+    transform: T,
+    max_length: Option<usize>,
+    max_payload_length: Option<usize>,
+}
+
+impl<T: FrameTransform> FrameCodecBuilder<T> {
+    pub fn header(mut self, header: FrameHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn checksum(mut self, checksum: FrameChecksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub fn transform<U: FrameTransform>(self, transform: U) -> FrameCodecBuilder<U> {
+        FrameCodecBuilder {
+            header: self.header,
+            checksum: self.checksum,
+            transform,
+            max_length: self.max_length,
+            max_payload_length: self.max_payload_length,
+        }
+    }
+
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    pub fn max_payload_length(mut self, max_payload_length: usize) -> Self {
+        self.max_payload_length = Some(max_payload_length);
+        self
+    }
+
+    pub fn build(self) -> Result<FrameCodec<T>, WireError> {
+        let header = self.header;
+        let max_payload_length = self
+            .max_payload_length
+            .unwrap_or_else(|| header.default_max_payload_length());
+
+        // The length prefix has to be able to carry the biggest allowed payload
+        let max_prefix_length = header.max_payload_length();
+
+        if max_payload_length > max_prefix_length {
+            return Err(WireError::Oversized(
+                "max_payload_length",
+                max_payload_length,
+                max_prefix_length,
+            ));
+        }
+
+        // Any payload within the payload limit has to fit into a frame with a full sized header
+        let required_max_length = header
+            .max_header_length()
+            .checked_add_wire("max_header_length", flags_length::<T>(), "flags_length")?
+            .checked_add_wire(
+                "max_header_length",
+                max_payload_length,
+                "max_payload_length",
+            )?
+            .checked_add_wire(
+                "max_frame_length",
+                self.checksum.trailer_length(),
+                "trailer_length",
+            )?;
+
+        let max_length = self.max_length.unwrap_or(required_max_length);
+
+        if max_length < required_max_length {
+            return Err(WireError::Underflow(
+                "max_length",
+                max_length,
+                required_max_length,
+            ));
+        }
+
+        Ok(FrameCodec {
+            header,
+            checksum: self.checksum,
+            transform: self.transform,
+            max_length,
+            max_payload_length,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{Decoder, Encoder},
+        Bytes, BytesMut, Frame, Message,
+    };
+
+    const U16_MAX_PAYLOAD_LENGTH: usize = u16::MAX as usize;
+    // [u8 message] | [u16 length]
+    const U16_HEADER_LENGTH: usize = 3;
+
+    #[test]
+    fn rejects_payload_limits_past_the_length_prefix() {
+        let result = FrameCodec::builder()
+            .max_payload_length(U16_MAX_PAYLOAD_LENGTH + 1)
+            .build();
+
+        assert!(matches!(
+            result,
+            Err(WireError::Oversized("max_payload_length", length, max))
+                if length == U16_MAX_PAYLOAD_LENGTH + 1 && max == U16_MAX_PAYLOAD_LENGTH
+        ));
+
+        assert!(FrameCodec::builder()
+            .max_payload_length(U16_MAX_PAYLOAD_LENGTH)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_frame_limits_below_the_largest_frame() {
+        let result = FrameCodec::builder()
+            .max_payload_length(100)
+            .max_length(100 + U16_HEADER_LENGTH - 1)
+            .build();
+
+        assert!(matches!(
+            result,
+            Err(WireError::Underflow("max_length", length, required))
+                if length == 100 + U16_HEADER_LENGTH - 1 && required == 100 + U16_HEADER_LENGTH
+        ));
+
+        // The trailer counts as well
+        let result = FrameCodec::builder()
+            .checksum(FrameChecksum::Crc32c)
+            .max_payload_length(100)
+            .max_length(100 + U16_HEADER_LENGTH)
+            .build();
+
+        assert!(matches!(
+            result,
+            Err(WireError::Underflow("max_length", _, required))
+                if required == 100 + U16_HEADER_LENGTH + 4
+        ));
+    }
+
+    #[test]
+    fn defaults_max_length_to_the_largest_frame() {
+        let codec = FrameCodec::builder()
+            .header(FrameHeader::VarInt)
+            .max_payload_length(20_000)
+            .build()
+            .unwrap();
+
+        // [varint message] | [varint length], both up to 8 bytes
+        assert_eq!(codec.max_length(), 20_000 + 16);
+        assert_eq!(codec.max_payload_length(), 20_000);
+    }
+
+    #[test]
+    fn round_trips_frames_past_64_kib_with_a_u32_prefix() {
+        let payload = Bytes::from((0..100_000u32).map(|i| i as u8).collect::<Vec<_>>());
+        let mut codec = FrameCodec::builder()
+            .header(FrameHeader::U32)
+            .max_payload_length(payload.len())
+            .build()
+            .unwrap();
+
+        let mut buffer = BytesMut::new();
+        codec
+            .encode(
+                Frame {
+                    message: Message(7),
+                    payload: payload.clone(),
+                },
+                &mut buffer,
+            )
+            .unwrap();
+
+        // Every byte but the last leaves the frame incomplete
+        let mut partial = BytesMut::from(&buffer[..buffer.len() - 1]);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+
+        let frame = codec.decode(&mut buffer).unwrap().unwrap();
+
+        assert_eq!(frame.message.0, 7);
+        assert_eq!(frame.payload, payload);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn enforces_the_configured_payload_limit() {
+        let mut codec = FrameCodec::builder()
+            .header(FrameHeader::U32)
+            .max_payload_length(1000)
+            .build()
+            .unwrap();
+
+        let frame = Frame {
+            message: Message(1),
+            payload: Bytes::from(vec![0; 1001]),
+        };
+
+        assert!(matches!(
+            codec.encode(frame, &mut BytesMut::new()),
+            Err(WireError::Oversized("payload_length", 1001, 1000))
+        ));
+
+        // [u8 message] | [u32 length = 1001]
+        let mut source = BytesMut::from(&[1, 0, 0, 0x03, 0xE9][..]);
+
+        assert!(matches!(
+            codec.decode(&mut source),
+            Err(WireError::Oversized("payload_length", 1001, 1000))
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+use super::{FrameCodec, FrameTransform, NoTransform};
+use crate::{
+    codec::{
+        bytes::BytesMut,
+        wired::{define_fields, VarInt},
+        Decoder, Encoder,
+    },
+    errors::WireError,
+    Frame,
+};
+
+// [message] | [u8 flags] | [varint channel] | [length][payload...]
+define_fields! {
+    (Channel, WiredVarInt, fixed),
+}
+
+pub(super) type ChannelWired = fields::channel::Wired;
+
+pub type ChannelId = u64;
+
+// FrameCodec with a channel id in every header, the id comes on top of the inner max_length
+#[derive(Clone, Copy)]
+pub struct ChannelCodec<T: FrameTransform = NoTransform> {
+    inner: FrameCodec<T>,
+}
+
+impl<T: FrameTransform> ChannelCodec<T> {
+    pub fn new(inner: FrameCodec<T>) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &FrameCodec<T> {
+        &self.inner
+    }
+}
+
+impl Default for ChannelCodec {
+    fn default() -> Self {
+        Self::new(FrameCodec::default())
+    }
+}
+
+impl<T: FrameTransform> Encoder<(ChannelId, Frame)> for ChannelCodec<T> {
+    type Error = WireError;
+
+    fn encode(
+        &mut self,
+        (channel, frame): (ChannelId, Frame),
+        destination: &mut BytesMut,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .encode_frame(Some(VarInt::from_u64(channel)?), frame, destination)
+    }
+}
+
+impl<T: FrameTransform> Decoder for ChannelCodec<T> {
+    type Item = (ChannelId, Frame);
+    type Error = WireError;
+
+    fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some((channel, frame)) = self.inner.decode_frame(true, source)? else {
+            return Ok(None);
+        };
+
+        // Always present when decoding with a channel
+        Ok(channel.map(|channel| (channel, frame)))
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    codec::{
+        bytes::{BytesMut, BytesMutPutExt},
+        wired::{define_fields, WiredInt},
+    },
+    errors::WireError,
+    helpers::crc32c,
+};
+
+// [frame...] | [u32 checksum], covers the message code, the length prefix and the payload
+define_fields! {
+    (Checksum, u32, fixed),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameChecksum {
+    #[default]
+    None,
+    Crc32c,
+}
+
+impl FrameChecksum {
+    pub(super) fn trailer_length(self) -> usize {
+        match self {
+            FrameChecksum::None => 0,
+            FrameChecksum::Crc32c => fields::FIXED_PART_LENGTH,
+        }
+    }
+
+    // Appends the trailer for the frame starting at `start_offset`
+    pub(super) fn append(self, destination: &mut BytesMut, start_offset: usize) {
+        match self {
+            FrameChecksum::None => (),
+            FrameChecksum::Crc32c => {
+                let checksum = crc32c(&destination[start_offset..]);
+
+                destination.put_single::<fields::checksum::Wired>(checksum);
+            }
+        }
+    }
+
+    // Same as append, for a frame whose bytes are split into `parts` instead of sitting in
+    // `destination`
+    #[cfg(feature = "transport")]
+    pub(super) fn append_parts(self, destination: &mut BytesMut, parts: &[&[u8]]) {
+        match self {
+            FrameChecksum::None => (),
+            FrameChecksum::Crc32c => {
+                let checksum = parts.iter().fold(0, |checksum, part| {
+                    crate::helpers::crc32c_extend(checksum, part)
+                });
+
+                destination.put_single::<fields::checksum::Wired>(checksum);
+            }
+        }
+    }
+
+    // `frame` is the whole frame including its trailer
+    pub(super) fn verify(self, frame: &[u8]) -> Result<(), WireError> {
+        match self {
+            FrameChecksum::None => Ok(()),
+            FrameChecksum::Crc32c => {
+                let body_length = frame.len().checked_sub(fields::FIXED_PART_LENGTH).ok_or(
+                    WireError::Underflow("checksum", frame.len(), fields::FIXED_PART_LENGTH),
+                )?;
+                let (body, trailer) = frame.split_at(body_length);
+
+                let expected = <fields::checksum::Wired as WiredInt>::read_raw(trailer).ok_or(
+                    WireError::Underflow("checksum", trailer.len(), fields::FIXED_PART_LENGTH),
+                )?;
+                let actual = crc32c(body);
+
+                if expected != actual {
+                    return Err(WireError::ChecksumMismatch(expected, actual));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{Decoder, Encoder, FrameCodec},
+        Bytes, Frame, Message,
+    };
+
+    fn codec() -> FrameCodec {
+        FrameCodec::builder()
+            .checksum(FrameChecksum::Crc32c)
+            .build()
+            .unwrap()
+    }
+
+    fn encoded() -> BytesMut {
+        let mut buffer = BytesMut::new();
+
+        codec()
+            .encode(
+                Frame {
+                    message: Message(9),
+                    payload: Bytes::from_static(b"checksummed payload"),
+                },
+                &mut buffer,
+            )
+            .unwrap();
+
+        buffer
+    }
+
+    fn split_trailer(frame: &[u8]) -> (&[u8], u32) {
+        let (body, trailer) = frame.split_at(frame.len() - fields::FIXED_PART_LENGTH);
+
+        (body, u32::from_be_bytes(trailer.try_into().unwrap()))
+    }
+
+    #[test]
+    fn round_trips_with_a_trailer() {
+        let mut buffer = encoded();
+        let (body, trailer) = split_trailer(&buffer);
+
+        assert_eq!(trailer, crc32c(body));
+
+        let frame = codec().decode(&mut buffer).unwrap().unwrap();
+
+        assert_eq!(frame.message.0, 9);
+        assert_eq!(&frame.payload[..], b"checksummed payload");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn rejects_a_flipped_payload_byte() {
+        let mut buffer = encoded();
+        let (_, trailer) = split_trailer(&buffer);
+
+        // [u8 message] | [u16 length] | payload
+        buffer[3] ^= 0x01;
+        let (body, _) = split_trailer(&buffer);
+        let computed = crc32c(body);
+
+        assert_ne!(computed, trailer);
+        assert!(matches!(
+            codec().decode(&mut buffer),
+            Err(WireError::ChecksumMismatch(expected, actual))
+                if expected == trailer && actual == computed
+        ));
+    }
+
+    #[test]
+    fn rejects_a_corrupted_trailer() {
+        let mut buffer = encoded();
+        let last = buffer.len() - 1;
+        buffer[last] ^= 0x80;
+
+        let (body, trailer) = split_trailer(&buffer);
+        let computed = crc32c(body);
+
+        assert!(matches!(
+            codec().decode(&mut buffer),
+            Err(WireError::ChecksumMismatch(expected, actual))
+                if expected == trailer && actual == computed
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::codec::wired::WiredInt;
+
+// Payload MAX_LENGTH in the tables below is only the default limit, FrameCodec enforces its own
+pub(super) mod u16_header {
+    use crate::codec::wired::define_fields;
+
+    // [u8 message] | [u16 length][payload...]
+    define_fields! {
+        (Message, u8, fixed),
+        (Payload, u16, length_prefix, 1300),
+    }
+}
+
+pub(super) mod u32_header {
+    use crate::codec::wired::define_fields;
+
+    // [u8 message] | [u32 length][payload...]
+    define_fields! {
+        (Message, u8, fixed),
+        (Payload, u32, length_prefix, 1300),
+    }
+}
+
+pub(super) mod varint_header {
+    use crate::codec::wired::define_fields;
+
+    // [varint message] | [varint length][payload...]
+    define_fields! {
+        (Message, WiredVarInt, fixed),
+        (Payload, WiredVarInt, length_prefix, 1300),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameHeader {
+    #[default]
+    U16,
+    U32,
+    VarInt,
+}
+
+impl FrameHeader {
+    pub(super) fn max_header_length(self) -> usize {
+        match self {
+            FrameHeader::U16 => {
+                u16_header::fields::MAX_LENGTH - u16_header::fields::payload::MAX_LENGTH
+            }
+            FrameHeader::U32 => {
+                u32_header::fields::MAX_LENGTH - u32_header::fields::payload::MAX_LENGTH
+            }
+            FrameHeader::VarInt => {
+                varint_header::fields::MAX_LENGTH - varint_header::fields::payload::MAX_LENGTH
+            }
+        }
+    }
+
+    pub(super) fn max_payload_length(self) -> usize {
+        match self {
+            FrameHeader::U16 => <u16_header::fields::payload::Wired as WiredInt>::MAX,
+            FrameHeader::U32 => <u32_header::fields::payload::Wired as WiredInt>::MAX,
+            FrameHeader::VarInt => <varint_header::fields::payload::Wired as WiredInt>::MAX,
+        }
+    }
+
+    pub(super) fn default_max_payload_length(self) -> usize {
+        match self {
+            FrameHeader::U16 => u16_header::fields::payload::MAX_LENGTH,
+            FrameHeader::U32 => u32_header::fields::payload::MAX_LENGTH,
+            FrameHeader::VarInt => varint_header::fields::payload::MAX_LENGTH,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{Decoder, Encoder, FrameCodec},
+        errors::WireError,
+        BytesMut, Frame, Message,
+    };
+
+    fn codec(header: FrameHeader) -> FrameCodec {
+        FrameCodec::builder().header(header).build().unwrap()
+    }
+
+    #[test]
+    fn rejects_codes_past_a_byte_under_fixed_headers() {
+        for header in [FrameHeader::U16, FrameHeader::U32] {
+            let result =
+                codec(header).encode(Frame::message_only(Message(256)), &mut BytesMut::new());
+
+            assert!(matches!(
+                result,
+                Err(WireError::LengthOverflow("message", 256, 255))
+            ));
+        }
+    }
+
+    #[test]
+    fn carries_wide_codes_under_the_varint_header() {
+        let mut codec = codec(FrameHeader::VarInt);
+        let mut buffer = BytesMut::new();
+
+        for code in [300, 70_000] {
+            codec
+                .encode(Frame::message_only(Message(code)), &mut buffer)
+                .unwrap();
+        }
+
+        for code in [300, 70_000] {
+            let frame = codec.decode(&mut buffer).unwrap().unwrap();
+            assert_eq!(frame.message.0, code);
+        }
+    }
+}
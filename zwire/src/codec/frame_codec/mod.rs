@@ -0,0 +1,440 @@
+mod builder;
+pub use builder::FrameCodecBuilder;
+
+mod channel;
+use channel::ChannelWired;
+pub use channel::{ChannelCodec, ChannelId};
+
+mod checksum;
+pub use checksum::FrameChecksum;
+
+mod header;
+pub use header::FrameHeader;
+use header::{u16_header, u32_header, varint_header};
+
+mod transform;
+#[cfg(feature = "lz4")]
+pub use transform::Lz4Transform;
+use transform::{check_flags, flags_length, FlagsWired, FLAG_TRANSFORMED};
+pub use transform::{FrameTransform, NoTransform};
+
+use super::{
+    bytes::{BytesMut, BytesMutPutExt, BytesMutTakeExt},
+    wired::{VarInt, WiredField, WiredInt, WiredLengthPrefixed},
+    Decoder, Encoder,
+};
+use crate::{errors::WireError, helpers::CheckedAddWire, Frame, Message};
+use ::bytes::{Buf, BufMut, Bytes};
+
+#[derive(Clone, Copy)]
+pub struct FrameCodec<T: FrameTransform = NoTransform> {
+    header: FrameHeader,
+    checksum: FrameChecksum,
+    transform: T,
+    max_length: usize,
+    max_payload_length: usize,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        FrameCodec {
+            header: FrameHeader::U16,
+            checksum: FrameChecksum::None,
+            transform: NoTransform,
+            max_length: u16_header::fields::MAX_LENGTH,
+            max_payload_length: u16_header::fields::payload::MAX_LENGTH,
+        }
+    }
+}
+
+impl FrameCodec {
+    pub fn builder() -> FrameCodecBuilder {
+        FrameCodecBuilder::default()
+    }
+
+    pub fn varint() -> Self {
+        FrameCodec {
+            header: FrameHeader::VarInt,
+            checksum: FrameChecksum::None,
+            transform: NoTransform,
+            max_length: varint_header::fields::MAX_LENGTH,
+            max_payload_length: varint_header::fields::payload::MAX_LENGTH,
+        }
+    }
+}
+
+impl<T: FrameTransform> FrameCodec<T> {
+    pub fn header(&self) -> FrameHeader {
+        self.header
+    }
+
+    pub fn checksum(&self) -> FrameChecksum {
+        self.checksum
+    }
+
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    pub fn max_payload_length(&self) -> usize {
+        self.max_payload_length
+    }
+
+    pub fn transform(&self) -> &T {
+        &self.transform
+    }
+
+    // Writes the header up to and including the length prefix and hands back where the frame
+    // starts along with the (transformed) payload, which the caller places before the trailer.
+    // The channel id is written after the flags when given, it isn't counted against max_length
+    fn encode_head_with<M, P>(
+        &self,
+        channel: Option<VarInt>,
+        frame: Frame,
+        destination: &mut BytesMut,
+        inline_payload: bool,
+    ) -> Result<(usize, Bytes), WireError>
+    where
+        M: WiredInt + WiredField,
+        M::Int: TryFrom<u32> + Copy,
+        P: WiredLengthPrefixed,
+    {
+        let payload_length = frame.payload.len();
+        let max_prefix_length = P::LengthPrefix::MAX;
+
+        if payload_length > max_prefix_length {
+            return Err(WireError::Oversized(
+                "payload_length",
+                payload_length,
+                max_prefix_length,
+            ));
+        }
+
+        if payload_length > self.max_payload_length {
+            return Err(WireError::Oversized(
+                "payload_length",
+                payload_length,
+                self.max_payload_length,
+            ));
+        }
+
+        let (flags, payload) = match self.transform.apply(&frame.payload)? {
+            Some(transformed) => (FLAG_TRANSFORMED, transformed),
+            None => (0, frame.payload),
+        };
+        let payload_length = payload.len();
+
+        // Transforms can grow the payload, it still has to fit the length prefix
+        if payload_length > max_prefix_length {
+            return Err(WireError::Oversized(
+                P::FIELD_NAME,
+                payload_length,
+                max_prefix_length,
+            ));
+        }
+
+        // U16 and U32 headers carry the message as a single byte, only VarInt takes any code
+        let message = M::Int::try_from(frame.message.0).map_err(|_| {
+            WireError::LengthOverflow(M::FIELD_NAME, frame.message.0.into(), M::MAX)
+        })?;
+        let message_length = M::to_bytes(message).as_ref().len();
+        let length_prefix = P::LengthPrefix::to_bytes_from_usize(payload_length, P::FIELD_NAME)?;
+        let prefix_length = length_prefix.as_ref().len();
+
+        let total_length = message_length
+            .checked_add_wire("message_length", flags_length::<T>(), "flags_length")?
+            .checked_add_wire("header_length", prefix_length, "length_prefix_length")?
+            .checked_add_wire("header_length", payload_length, "payload_length")?
+            .checked_add_wire(
+                "frame_length",
+                self.checksum.trailer_length(),
+                "trailer_length",
+            )?;
+
+        if total_length > self.max_length {
+            return Err(WireError::Oversized(
+                "total_length",
+                total_length,
+                self.max_length,
+            ));
+        }
+
+        let channel_length = channel.map_or(0, VarInt::encoded_size);
+        let reserved_length = if inline_payload {
+            total_length
+        } else {
+            total_length - payload_length
+        };
+
+        destination.reserve(reserved_length.checked_add_wire(
+            "total_length",
+            channel_length,
+            "channel_length",
+        )?);
+
+        let start_offset = destination.len();
+
+        destination.put_single::<M>(message); // repr
+
+        if T::ENABLED {
+            destination.put_single::<FlagsWired>(flags);
+        }
+
+        if let Some(channel) = channel {
+            destination.put_single::<ChannelWired>(channel);
+        }
+
+        destination.put_slice(length_prefix.as_ref());
+
+        Ok((start_offset, payload))
+    }
+
+    fn decode_with<M, P>(
+        &self,
+        with_channel: bool,
+        source: &mut BytesMut,
+    ) -> Result<Option<(Option<ChannelId>, Frame)>, WireError>
+    where
+        M: WiredInt + WiredField,
+        M::Int: Into<u64>,
+        P: WiredLengthPrefixed,
+    {
+        if source.is_empty() {
+            return Ok(None);
+        }
+
+        let Some(message_length) = M::encoded_size(source) else {
+            return Ok(None);
+        };
+
+        let channel_offset = message_length.checked_add_wire(
+            "message_length",
+            flags_length::<T>(),
+            "flags_length",
+        )?;
+
+        let channel_length = if with_channel {
+            let Some(channel_length) = source
+                .get(channel_offset..)
+                .and_then(ChannelWired::encoded_size)
+            else {
+                return Ok(None);
+            };
+
+            channel_length
+        } else {
+            0
+        };
+
+        let prefix_offset =
+            channel_offset.checked_add_wire("channel_offset", channel_length, "channel_length")?;
+
+        let Some(prefix_length) = source
+            .get(prefix_offset..)
+            .and_then(P::LengthPrefix::encoded_size)
+        else {
+            return Ok(None);
+        };
+
+        let header_length = prefix_offset.checked_add_wire(
+            "prefix_offset",
+            prefix_length,
+            "length_prefix_length",
+        )?;
+
+        let Some(prefix) = source.get(prefix_offset..header_length) else {
+            return Ok(None);
+        };
+
+        let Some(payload_length) = P::LengthPrefix::read(prefix, P::FIELD_NAME)? else {
+            return Ok(None);
+        };
+
+        if payload_length > self.max_payload_length {
+            return Err(WireError::Oversized(
+                "payload_length",
+                payload_length,
+                self.max_payload_length,
+            ));
+        }
+
+        let total_length = header_length
+            .checked_add_wire("header_length", payload_length, "payload_length")?
+            .checked_add_wire(
+                "frame_length",
+                self.checksum.trailer_length(),
+                "trailer_length",
+            )?;
+
+        if total_length - channel_length > self.max_length {
+            return Err(WireError::Oversized(
+                "total_length",
+                total_length - channel_length,
+                self.max_length,
+            ));
+        }
+
+        if source.len() < total_length {
+            return Ok(None);
+        }
+
+        self.checksum.verify(&source[..total_length])?;
+
+        // The header was measured above, a take falling short means the two disagree
+        let Some(message_code) = source.take_single::<M>()? else {
+            return Err(WireError::Truncated(M::FIELD_NAME));
+        };
+        let message_code: u64 = message_code.into();
+        let message_code = u32::try_from(message_code).map_err(|_| {
+            WireError::LengthOverflow(M::FIELD_NAME, message_code as u128, u32::MAX as usize)
+        })?;
+
+        let flags = if T::ENABLED {
+            source
+                .take_single::<FlagsWired>()?
+                .ok_or(WireError::Truncated("flags"))?
+        } else {
+            0
+        };
+
+        check_flags(flags)?;
+
+        let channel = if with_channel {
+            let channel = source
+                .take_single::<ChannelWired>()?
+                .ok_or(WireError::Truncated("channel"))?;
+
+            Some(channel.into())
+        } else {
+            None
+        };
+
+        // Limits were checked against the runtime configuration above, the table's MAX_LENGTH
+        // is only the default one
+        source.advance(prefix_length);
+        let mut payload = source.split_to(payload_length).freeze();
+        source.advance(self.checksum.trailer_length());
+
+        if flags & FLAG_TRANSFORMED != 0 {
+            payload = self.transform.revert(payload, self.max_payload_length)?;
+        }
+
+        Ok(Some((
+            channel,
+            Frame {
+                message: Message(message_code),
+                payload,
+            },
+        )))
+    }
+
+    fn encode_head(
+        &self,
+        channel: Option<VarInt>,
+        frame: Frame,
+        destination: &mut BytesMut,
+        inline_payload: bool,
+    ) -> Result<(usize, Bytes), WireError> {
+        match self.header {
+            FrameHeader::U16 => self.encode_head_with::<
+                u16_header::fields::message::Wired,
+                u16_header::fields::payload::Wired,
+            >(channel, frame, destination, inline_payload),
+            FrameHeader::U32 => self.encode_head_with::<
+                u32_header::fields::message::Wired,
+                u32_header::fields::payload::Wired,
+            >(channel, frame, destination, inline_payload),
+            FrameHeader::VarInt => self.encode_head_with::<
+                varint_header::fields::message::Wired,
+                varint_header::fields::payload::Wired,
+            >(channel, frame, destination, inline_payload),
+        }
+    }
+
+    fn encode_frame(
+        &self,
+        channel: Option<VarInt>,
+        frame: Frame,
+        destination: &mut BytesMut,
+    ) -> Result<(), WireError> {
+        let (start_offset, payload) = self.encode_head(channel, frame, destination, true)?;
+
+        destination.put_slice(&payload);
+
+        self.checksum.append(destination, start_offset);
+
+        Ok(())
+    }
+
+    // Encodes the frame without copying its payload: everything before the payload is split
+    // off `scratch` into `chunks`, followed by the payload itself. The trailer stays behind in
+    // `scratch`, ahead of the next frame's header, until the caller splits it off as well.
+    // Returns the frame's encoded length
+    #[cfg(feature = "transport")]
+    pub(crate) fn encode_chunks(
+        &self,
+        frame: Frame,
+        scratch: &mut BytesMut,
+        chunks: &mut std::collections::VecDeque<Bytes>,
+    ) -> Result<usize, WireError> {
+        let (start_offset, payload) = self.encode_head(None, frame, scratch, false)?;
+
+        if payload.is_empty() {
+            self.checksum.append(scratch, start_offset);
+
+            return Ok(scratch.len() - start_offset);
+        }
+
+        let head = scratch.split().freeze();
+        let head_length = head.len() - start_offset;
+        let payload_length = payload.len();
+
+        self.checksum
+            .append_parts(scratch, &[&head[start_offset..], &payload]);
+
+        chunks.push_back(head);
+        chunks.push_back(payload);
+
+        Ok(head_length + payload_length + scratch.len())
+    }
+
+    fn decode_frame(
+        &self,
+        with_channel: bool,
+        source: &mut BytesMut,
+    ) -> Result<Option<(Option<ChannelId>, Frame)>, WireError> {
+        match self.header {
+            FrameHeader::U16 => self.decode_with::<
+                u16_header::fields::message::Wired,
+                u16_header::fields::payload::Wired,
+            >(with_channel, source),
+            FrameHeader::U32 => self.decode_with::<
+                u32_header::fields::message::Wired,
+                u32_header::fields::payload::Wired,
+            >(with_channel, source),
+            FrameHeader::VarInt => self.decode_with::<
+                varint_header::fields::message::Wired,
+                varint_header::fields::payload::Wired,
+            >(with_channel, source),
+        }
+    }
+}
+
+impl<T: FrameTransform> Encoder<Frame> for FrameCodec<T> {
+    type Error = WireError;
+
+    fn encode(&mut self, frame: Frame, destination: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode_frame(None, frame, destination)
+    }
+}
+
+impl<T: FrameTransform> Decoder for FrameCodec<T> {
+    type Item = Frame;
+    type Error = WireError;
+
+    fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(self
+            .decode_frame(false, source)?
+            .map(|(_channel, frame)| frame))
+    }
+}
@@ -0,0 +1,207 @@
+use super::FrameTransform;
+use crate::{
+    codec::{
+        bytes::{Bytes, BytesMut, BytesMutPutExt},
+        wired::{define_fields, VarInt, WiredInt},
+    },
+    errors::WireError,
+};
+use alloc::string::ToString;
+use lz4_flex::block::{compress_into, decompress_into, get_maximum_output_size};
+
+// [varint original length] | [lz4 block...]
+define_fields! {
+    (OriginalLength, WiredVarInt, fixed),
+}
+
+const DEFAULT_THRESHOLD: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lz4Transform {
+    threshold: usize,
+}
+
+impl Default for Lz4Transform {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl Lz4Transform {
+    // Payloads shorter than `threshold` are sent raw
+    pub fn new(threshold: usize) -> Self {
+        Self { threshold }
+    }
+}
+
+impl FrameTransform for Lz4Transform {
+    fn apply(&self, payload: &Bytes) -> Result<Option<Bytes>, WireError> {
+        let payload_length = payload.len();
+
+        if payload_length < self.threshold {
+            return Ok(None);
+        }
+
+        let original_length = VarInt::try_from(payload_length as u64)?;
+        let header_length = original_length.encoded_size();
+        let output_length = header_length + get_maximum_output_size(payload_length);
+        let mut output = BytesMut::with_capacity(output_length);
+
+        output.put_single::<fields::originallength::Wired>(original_length);
+        output.resize(output_length, 0);
+
+        let compressed_length = compress_into(payload, &mut output[header_length..])
+            .map_err(|error| WireError::TransformFailed(error.to_string()))?;
+        let transformed_length = header_length + compressed_length;
+
+        if transformed_length >= payload_length {
+            return Ok(None);
+        }
+
+        output.truncate(transformed_length);
+
+        Ok(Some(output.freeze()))
+    }
+
+    fn revert(&self, payload: Bytes, max_length: usize) -> Result<Bytes, WireError> {
+        let (Some(header_length), Some(original_length)) = (
+            <fields::originallength::Wired as WiredInt>::encoded_size(&payload),
+            <fields::originallength::Wired as WiredInt>::read(&payload, "original_length")?,
+        ) else {
+            return Err(WireError::Underflow(
+                "original_length",
+                payload.len(),
+                fields::FIXED_PART_LENGTH,
+            ));
+        };
+
+        if original_length > max_length {
+            return Err(WireError::Oversized(
+                "original_length",
+                original_length,
+                max_length,
+            ));
+        }
+
+        let mut output = BytesMut::zeroed(original_length);
+
+        let decompressed_length = decompress_into(&payload[header_length..], &mut output)
+            .map_err(|error| WireError::TransformFailed(error.to_string()))?;
+
+        if decompressed_length != original_length {
+            return Err(WireError::Underflow(
+                "decompressed_length",
+                decompressed_length,
+                original_length,
+            ));
+        }
+
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::FLAG_TRANSFORMED, *};
+    use crate::{
+        codec::{Decoder, Encoder, FrameCodec, FrameHeader},
+        Frame, Message,
+    };
+
+    // [u8 message] | [u8 flags] | [u32 length]
+    const FLAGS_OFFSET: usize = 1;
+    const HEADER_LENGTH: usize = 6;
+
+    fn codec(max_payload_length: usize) -> FrameCodec<Lz4Transform> {
+        FrameCodec::builder()
+            .header(FrameHeader::U32)
+            .transform(Lz4Transform::default())
+            .max_payload_length(max_payload_length)
+            .build()
+            .unwrap()
+    }
+
+    fn encode(codec: &mut FrameCodec<Lz4Transform>, payload: Bytes) -> BytesMut {
+        let mut buffer = BytesMut::new();
+
+        codec
+            .encode(
+                Frame {
+                    message: Message(1),
+                    payload,
+                },
+                &mut buffer,
+            )
+            .unwrap();
+
+        buffer
+    }
+
+    #[test]
+    fn round_trips_compressed_payloads() {
+        let payload = Bytes::from(vec![7; 10_000]);
+        let mut codec = codec(payload.len());
+        let mut buffer = encode(&mut codec, payload.clone());
+
+        assert_eq!(buffer[FLAGS_OFFSET], FLAG_TRANSFORMED);
+        assert!(buffer.len() < payload.len() / 10);
+
+        assert_eq!(codec.decode(&mut buffer).unwrap().unwrap().payload, payload);
+    }
+
+    #[test]
+    fn sends_payloads_below_the_threshold_raw() {
+        let payload = Bytes::from(vec![7; DEFAULT_THRESHOLD - 1]);
+        let mut codec = codec(1000);
+        let mut buffer = encode(&mut codec, payload.clone());
+
+        assert_eq!(buffer[FLAGS_OFFSET], 0);
+        assert_eq!(&buffer[HEADER_LENGTH..], &payload[..]);
+
+        assert_eq!(codec.decode(&mut buffer).unwrap().unwrap().payload, payload);
+    }
+
+    #[test]
+    fn sends_payloads_that_dont_shrink_raw() {
+        // An xorshift sequence, lz4 finds nothing to repeat in it
+        let mut state: u32 = 0x9E37_79B9;
+        let payload: Bytes = (0..1000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        let buffer = encode(&mut codec(1000), payload.clone());
+
+        assert_eq!(buffer[FLAGS_OFFSET], 0);
+        assert_eq!(&buffer[HEADER_LENGTH..], &payload[..]);
+    }
+
+    #[test]
+    fn rejects_payloads_claiming_more_than_the_limit() {
+        // A few dozen bytes on the wire that inflate to 1 MiB
+        let mut buffer = encode(&mut codec(1024 * 1024), Bytes::from(vec![0; 1024 * 1024]));
+
+        assert!(buffer.len() < 8 * 1024);
+        assert!(matches!(
+            codec(8 * 1024).decode(&mut buffer),
+            Err(WireError::Oversized("original_length", 1048576, 8192))
+        ));
+
+        // The transform itself refuses before allocating for the claimed length
+        let mut payload = BytesMut::new();
+        payload.put_single::<fields::originallength::Wired>(VarInt::from_u32(u32::MAX));
+        payload.extend_from_slice(&[0; 8]);
+
+        assert!(matches!(
+            Lz4Transform::default().revert(payload.freeze(), 8 * 1024),
+            Err(WireError::Oversized("original_length", length, 8192))
+                if length == u32::MAX as usize
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(feature = "lz4")]
+mod lz4;
+#[cfg(feature = "lz4")]
+pub use lz4::Lz4Transform;
+
+use crate::{
+    codec::{
+        bytes::Bytes,
+        wired::{define_fields, WiredInt},
+    },
+    errors::WireError,
+};
+
+// [message] | [u8 flags] | [length][payload...], only present when a transform is configured
+define_fields! {
+    (Flags, u8, fixed),
+}
+
+pub(super) type FlagsWired = fields::flags::Wired;
+
+pub(super) const FLAG_TRANSFORMED: u8 = 0b0000_0001;
+const KNOWN_FLAGS: u8 = FLAG_TRANSFORMED;
+
+pub trait FrameTransform {
+    // Frames only carry the flags byte when this is set
+    const ENABLED: bool = true;
+
+    // `None` sends the payload as is, e.g. when it's below a size threshold or didn't shrink
+    fn apply(&self, payload: &Bytes) -> Result<Option<Bytes>, WireError>;
+
+    // Must never produce more than `max_length` bytes, that is the decompression bomb guard
+    fn revert(&self, payload: Bytes, max_length: usize) -> Result<Bytes, WireError>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoTransform;
+
+impl FrameTransform for NoTransform {
+    const ENABLED: bool = false;
+
+    #[inline]
+    fn apply(&self, _payload: &Bytes) -> Result<Option<Bytes>, WireError> {
+        Ok(None)
+    }
+
+    #[inline]
+    fn revert(&self, payload: Bytes, _max_length: usize) -> Result<Bytes, WireError> {
+        Ok(payload)
+    }
+}
+
+#[inline]
+pub(super) fn flags_length<T: FrameTransform>() -> usize {
+    if T::ENABLED {
+        <FlagsWired as WiredInt>::SIZE
+    } else {
+        0
+    }
+}
+
+#[inline]
+pub(super) fn check_flags(flags: u8) -> Result<(), WireError> {
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(WireError::InvalidFrameFlags(flags));
+    }
+
+    Ok(())
+}
@@ -1,7 +1,21 @@
+#[cfg(feature = "std")]
+mod fragment_codec;
 mod frame_codec;
+#[cfg(not(feature = "std"))]
+mod traits;
 
 pub mod bytes;
 pub mod wired;
 
-pub use frame_codec::FrameCodec;
+#[cfg(feature = "std")]
+pub use fragment_codec::FragmentCodec;
+#[cfg(feature = "lz4")]
+pub use frame_codec::Lz4Transform;
+pub use frame_codec::{
+    ChannelCodec, ChannelId, FrameChecksum, FrameCodec, FrameCodecBuilder, FrameHeader,
+    FrameTransform, NoTransform,
+};
+#[cfg(feature = "std")]
 pub use tokio_util::codec::{Decoder, Encoder};
+#[cfg(not(feature = "std"))]
+pub use traits::{Decoder, Encoder};
@@ -0,0 +1,22 @@
+use super::bytes::BytesMut;
+
+// Stand-ins for tokio-util's codec traits without std, same methods minus the io::Error bound on
+// Decoder::Error. The std feature swaps in tokio-util's, so codecs implemented here work with
+// FramedRead/FramedWrite there
+pub trait Decoder {
+    type Item;
+    type Error;
+
+    fn decode(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error>;
+
+    // Called once no more bytes will arrive
+    fn decode_eof(&mut self, source: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode(source)
+    }
+}
+
+pub trait Encoder<Item> {
+    type Error;
+
+    fn encode(&mut self, item: Item, destination: &mut BytesMut) -> Result<(), Self::Error>;
+}
@@ -1,12 +1,5 @@
 use crate::WireError;
-
-macro_rules! length_check {
-    ($source:expr) => {
-        if $source.len() < Self::SIZE {
-            return None;
-        }
-    };
-}
+use core::marker::PhantomData;
 
 macro_rules! impl_to_bytes {
     () => {
@@ -18,10 +11,17 @@ macro_rules! impl_to_bytes {
 }
 
 macro_rules! impl_to_bytes_from_usize {
-    () => {
+    ($to_bytes:ident) => {
         #[inline]
-        fn to_bytes_from_usize(value: usize) -> Self::ByteArray {
-            (value as Self::Int).to_be_bytes()
+        fn to_bytes_from_usize(
+            value: usize,
+            field_name: &'static str,
+        ) -> Result<Self::ByteArray, WireError> {
+            let value: Self::Int = value.try_into().map_err(|_| {
+                WireError::LengthOverflow(field_name, value as u128, <Self as WiredInt>::MAX)
+            })?;
+
+            Ok(value.$to_bytes())
         }
     };
 }
@@ -29,18 +29,18 @@ macro_rules! impl_to_bytes_from_usize {
 macro_rules! impl_max_and_byte_array {
     () => {
         const MAX: usize = Self::Int::MAX as usize;
-        type ByteArray = [u8; std::mem::size_of::<Self::Int>()];
+        type ByteArray = [u8; core::mem::size_of::<Self::Int>()];
     };
 }
 
-macro_rules! impl_read_raw_unchecked {
-    () => {
+// Short sources read as `None`, whatever their contents
+macro_rules! impl_read_raw {
+    ($from_bytes:ident) => {
         #[inline]
-        fn read_raw_unchecked(source: &[u8]) -> Self::Int {
-            let pointer = source.as_ptr() as *const Self::Int;
-            let value: Self::Int = unsafe { pointer.read_unaligned() };
+        fn read_raw(source: &[u8]) -> Option<Self::Int> {
+            let bytes = source.get(..Self::SIZE)?.try_into().ok()?;
 
-            value.to_be()
+            Some(<Self::Int>::$from_bytes(bytes))
         }
     };
 }
@@ -62,62 +62,241 @@ macro_rules! impl_read {
     };
 }
 
-macro_rules! impl_read_unchecked {
-    () => {
-        #[inline]
-        fn read_unchecked(source: &[u8], field_name: &'static str) -> Result<usize, WireError> {
-            let raw_value = Self::read_raw_unchecked(source);
+macro_rules! impl_wired_int_for {
+    ($ty:ty) => {
+        impl WiredInt for $ty {
+            type Int = $ty;
 
-            let value: usize = raw_value.try_into().map_err(|_| {
-                WireError::LengthOverflow(field_name, raw_value as u128, usize::MAX)
-            })?;
+            impl_max_and_byte_array!();
+
+            impl_to_bytes!();
+            impl_to_bytes_from_usize!(to_be_bytes);
 
-            Ok(value)
+            impl_read_raw!(from_be_bytes);
+            impl_read!();
         }
     };
 }
 
-macro_rules! impl_wired_int_for {
+macro_rules! impl_wired_int_le_for {
     ($ty:ty) => {
-        impl WiredInt for $ty {
+        impl WiredInt for Le<$ty> {
             type Int = $ty;
 
             impl_max_and_byte_array!();
 
-            impl_to_bytes!();
-            impl_to_bytes_from_usize!();
+            #[inline]
+            fn to_bytes(value: Self::Int) -> Self::ByteArray {
+                value.to_le_bytes()
+            }
 
-            impl_read_raw_unchecked!();
+            impl_to_bytes_from_usize!(to_le_bytes);
 
+            impl_read_raw!(from_le_bytes);
             impl_read!();
-            impl_read_unchecked!();
         }
     };
 }
 
+// Floats and bools are plain values, reading one as a length is an error
+macro_rules! impl_not_a_length {
+    () => {
+        // Never a valid length prefix
+        const MAX: usize = 0;
+
+        #[inline]
+        fn read(_source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError> {
+            Err(WireError::InvalidLengthPrefix(field_name))
+        }
+
+        #[inline]
+        fn to_bytes_from_usize(
+            _value: usize,
+            field_name: &'static str,
+        ) -> Result<Self::ByteArray, WireError> {
+            Err(WireError::InvalidLengthPrefix(field_name))
+        }
+    };
+}
+
+macro_rules! impl_wired_float_for {
+    ($wired:ty, $float:ty, $to_bytes:ident, $from_bytes:ident) => {
+        impl WiredInt for $wired {
+            type Int = $float;
+            type ByteArray = [u8; core::mem::size_of::<$float>()];
+
+            impl_not_a_length!();
+
+            #[inline]
+            fn to_bytes(value: Self::Int) -> Self::ByteArray {
+                value.$to_bytes()
+            }
+
+            impl_read_raw!($from_bytes);
+        }
+    };
+}
+
+// Little-endian variant of a number, selected with the `le` marker in define_fields
+pub struct Le<T>(PhantomData<T>);
+
 impl_wired_int_for!(u8);
 impl_wired_int_for!(u16);
 impl_wired_int_for!(u32);
 impl_wired_int_for!(u64);
 impl_wired_int_for!(u128);
 
+impl_wired_int_for!(i8);
+impl_wired_int_for!(i16);
+impl_wired_int_for!(i32);
+impl_wired_int_for!(i64);
+impl_wired_int_for!(i128);
+
+impl_wired_int_le_for!(u8);
+impl_wired_int_le_for!(u16);
+impl_wired_int_le_for!(u32);
+impl_wired_int_le_for!(u64);
+impl_wired_int_le_for!(u128);
+
+impl_wired_int_le_for!(i8);
+impl_wired_int_le_for!(i16);
+impl_wired_int_le_for!(i32);
+impl_wired_int_le_for!(i64);
+impl_wired_int_le_for!(i128);
+
+impl_wired_float_for!(f32, f32, to_be_bytes, from_be_bytes);
+impl_wired_float_for!(f64, f64, to_be_bytes, from_be_bytes);
+impl_wired_float_for!(Le<f32>, f32, to_le_bytes, from_le_bytes);
+impl_wired_float_for!(Le<f64>, f64, to_le_bytes, from_le_bytes);
+
+// Only 0 and 1 are valid, so every bool decodes back to the byte it came from
+impl WiredInt for bool {
+    type Int = bool;
+    type ByteArray = [u8; 1];
+
+    impl_not_a_length!();
+
+    #[inline]
+    fn check(source: &[u8], field_name: &'static str) -> Result<(), WireError> {
+        match source.first() {
+            Some(&byte) if byte > 1 => Err(WireError::InvalidBool(field_name, byte)),
+            _ => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn to_bytes(value: Self::Int) -> Self::ByteArray {
+        [value as u8]
+    }
+
+    #[inline]
+    fn read_raw(source: &[u8]) -> Option<Self::Int> {
+        source.first().map(|&byte| byte != 0)
+    }
+}
+
 pub trait WiredInt {
     type Int;
     type ByteArray: AsRef<[u8]> + AsMut<[u8]>;
 
-    const SIZE: usize = std::mem::size_of::<Self::Int>();
+    const SIZE: usize = core::mem::size_of::<Self::Int>();
+    const MIN_SIZE: usize = Self::SIZE;
     const MAX: usize;
 
-    fn read_raw_unchecked(source: &[u8]) -> Self::Int;
-    fn read_raw(source: &[u8]) -> Option<Self::Int> {
-        length_check!(source);
-
-        Some(Self::read_raw_unchecked(source))
+    // Bytes the integer at the start of `source` spans on the wire, `None` if it can't be told yet
+    #[inline]
+    fn encoded_size(_source: &[u8]) -> Option<usize> {
+        Some(Self::SIZE)
     }
 
-    fn read_unchecked(source: &[u8], field_name: &'static str) -> Result<usize, WireError>;
+    // The value at the start of `source`, `None` if it's too short to hold one
+    fn read_raw(source: &[u8]) -> Option<Self::Int>;
+
+    // Rejects a value at the start of `source` that read_raw would accept but not give back
+    // byte for byte, layouts check every field before anything is taken. A short source passes
+    #[inline]
+    fn check(_source: &[u8], _field_name: &'static str) -> Result<(), WireError> {
+        Ok(())
+    }
     fn read(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError>;
 
-    fn to_bytes_from_usize(value: usize) -> Self::ByteArray;
+    // Encodes a length, failing if it doesn't fit
+    fn to_bytes_from_usize(
+        value: usize,
+        field_name: &'static str,
+    ) -> Result<Self::ByteArray, WireError>;
     fn to_bytes(value: Self::Int) -> Self::ByteArray;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{
+        bytes::{BytesMut, BytesMutTakeExt},
+        wired::{define_fields, ScalarElement, WiredElement, WiredLayout},
+    };
+
+    define_fields! {
+        (Enabled, bool, fixed),
+        (Count, u8, fixed),
+    }
+
+    #[test]
+    fn accepts_only_canonical_bools() {
+        for byte in [0, 1] {
+            assert!(<bool as WiredInt>::check(&[byte], "flag").is_ok());
+            assert_eq!(bool::to_bytes(bool::read_raw(&[byte]).unwrap()), [byte]);
+        }
+
+        assert!(<bool as WiredInt>::check(&[], "flag").is_ok());
+
+        for byte in [2, 0x80, 0xFF] {
+            assert!(matches!(
+                <bool as WiredInt>::check(&[byte], "flag"),
+                Err(WireError::InvalidBool("flag", invalid)) if invalid == byte
+            ));
+        }
+    }
+
+    #[test]
+    fn layouts_reject_non_canonical_bools() {
+        let measure = |source: &[u8]| <fields::Layout as WiredLayout>::measure(source, 16);
+
+        assert_eq!(measure(&[1, 5]).unwrap(), Some(2));
+        assert_eq!(measure(&[0]).unwrap(), None);
+        assert!(matches!(
+            measure(&[2, 5]),
+            Err(WireError::InvalidBool("enabled", 2))
+        ));
+    }
+
+    #[test]
+    fn takes_reject_non_canonical_bools() {
+        let mut source = BytesMut::from(&[1, 0][..]);
+
+        assert_eq!(
+            source.take_single::<fields::enabled::Wired>().unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            source.take_single::<fields::enabled::Wired>().unwrap(),
+            Some(false)
+        );
+        assert_eq!(
+            source.take_single::<fields::enabled::Wired>().unwrap(),
+            None
+        );
+
+        let mut source = BytesMut::from(&[2, 5][..]);
+
+        assert!(matches!(
+            source.take_single::<fields::enabled::Wired>(),
+            Err(WireError::InvalidBool("enabled", 2))
+        ));
+        assert!(matches!(
+            ScalarElement::<fields::enabled::Wired>::take(&mut source),
+            Err(WireError::InvalidBool("enabled", 2))
+        ));
+        assert_eq!(&source[..], [2, 5]);
+    }
+}
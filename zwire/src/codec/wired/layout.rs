@@ -0,0 +1,98 @@
+use crate::errors::WireError;
+
+// Every define_fields! table has one, it measures a record by walking its fields in order, so
+// variable-length fields don't have to come last and there can be any number of them
+pub trait WiredLayout {
+    const FIXED_PART_LENGTH: usize;
+    const MAX_LENGTH: usize;
+
+    // Length of the record at the start of `source`, `None` until all of it arrived. Fails as
+    // soon as the fields seen so far exceed `max_length`
+    fn measure(source: &[u8], max_length: usize) -> Result<Option<usize>, WireError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{bytes::BytesMut, wired::WireCodec, Decoder, Encoder},
+        Bytes,
+    };
+
+    // [u8 presence] | [u16 id] | [u8 channels]? | [u32 rate]? | [u8 length][label...]?
+    #[derive(Debug, Clone, PartialEq, WireCodec)]
+    struct Settings {
+        #[wire(u16, fixed)]
+        id: u16,
+        #[wire(u8, fixed, optional)]
+        channels: Option<u8>,
+        #[wire(u32, fixed, optional)]
+        rate: Option<u32>,
+        #[wire(u8, length_prefix, 10, optional)]
+        label: Option<Bytes>,
+    }
+
+    fn round_trip(settings: Settings) -> BytesMut {
+        let mut encoded = BytesMut::new();
+        SettingsCodec::default()
+            .encode(settings.clone(), &mut encoded)
+            .unwrap();
+
+        let mut source = encoded.clone();
+        let decoded = SettingsCodec::default().decode(&mut source).unwrap();
+
+        assert_eq!(decoded, Some(settings));
+        assert!(source.is_empty());
+
+        encoded
+    }
+
+    #[test]
+    fn sizes_tables_with_optional_fields() {
+        // Only the bitmap and the required id are always there
+        assert_eq!(<fields::Layout as WiredLayout>::FIXED_PART_LENGTH, 1 + 2);
+        assert_eq!(
+            <fields::Layout as WiredLayout>::MAX_LENGTH,
+            1 + 2 + 1 + 4 + 1 + 10
+        );
+        assert_eq!(fields::presence::KNOWN_BITS, 0b111);
+    }
+
+    #[test]
+    fn round_trips_present_and_absent_fields() {
+        let encoded = round_trip(Settings {
+            id: 7,
+            channels: None,
+            rate: None,
+            label: None,
+        });
+        assert_eq!(&encoded[..], [0, 0, 7]);
+
+        let encoded = round_trip(Settings {
+            id: 7,
+            channels: Some(2),
+            rate: None,
+            label: Some(Bytes::from_static(b"hi")),
+        });
+        assert_eq!(&encoded[..], [0b101, 0, 7, 2, 2, b'h', b'i']);
+
+        let encoded = round_trip(Settings {
+            id: 7,
+            channels: Some(2),
+            rate: Some(48_000),
+            label: Some(Bytes::new()),
+        });
+        assert_eq!(encoded.len(), 1 + 2 + 1 + 4 + 1);
+    }
+
+    #[test]
+    fn rejects_unassigned_presence_bits() {
+        let mut source = BytesMut::from(&[0b1000, 0, 7][..]);
+
+        assert!(matches!(
+            SettingsCodec::default().decode(&mut source),
+            Err(WireError::UnknownPresenceBits("presence", 0b1000))
+        ));
+        assert_eq!(source.len(), 3);
+    }
+}
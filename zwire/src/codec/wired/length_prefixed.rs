@@ -1,4 +1,4 @@
-use super::{WiredInt, WiredField};
+use super::{WiredField, WiredInt};
 
 pub trait WiredLengthPrefixed: WiredField {
     type LengthPrefix: WiredInt;
@@ -1,17 +1,161 @@
 mod fixed_bytes;
 mod int;
+mod layout;
 mod length_prefixed;
+mod nested;
+mod repeated;
 mod string;
+mod varint;
 
+#[cfg(feature = "unicode")]
+pub use self::string::NfcText;
 pub use self::{
     fixed_bytes::WiredFixedBytes,
-    int::WiredInt,
+    int::{Le, WiredInt},
+    layout::WiredLayout,
     length_prefixed::WiredLengthPrefixed,
-    string::{WiredString, WiredStringPolicyKind},
+    nested::{NestedRecord, WiredNested, WiredPayload, WiredRecord},
+    repeated::{
+        FixedBytesElement, LengthPrefixedElement, ScalarElement, StringElement, WiredElement,
+        WiredRepeated,
+    },
+    string::{AsciiHyphen, Hostname, PrintableAscii, Utf8, WiredString, WiredStringPolicy},
+    varint::{VarInt, VarIntBytes, WiredVarInt},
 };
-pub use zenet_macros::{define_fields, define_message};
+pub use zenet_macros::{define_fields, define_message, WireCodec};
 
 pub trait WiredField {
     const FIELD_NAME: &'static str;
     const OFFSET: usize;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::WireError, Bytes, Frame, Message};
+
+    // Public as the generated protocol enum is
+    #[derive(Debug, Clone, WireCodec)]
+    pub struct Ping {
+        #[wire(u32, fixed)]
+        pub sequence: u32,
+    }
+
+    define_message!(
+        Call => CallProtocol,
+        {
+            Ping = 1 => Ping,
+            Hangup = 2,
+        }
+    );
+
+    define_message!(
+        WideMessage: u16 => WideProtocol,
+        {
+            Narrow = 1,
+            Wide = 300,
+            Other(_),
+        }
+    );
+
+    define_message!(
+        NarrowMessage,
+        {
+            Only = 1,
+        }
+    );
+
+    fn frame(code: u32, payload: &'static [u8]) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::from_static(payload),
+        }
+    }
+
+    #[test]
+    fn decodes_payloads_from_frames() {
+        assert!(matches!(
+            CallProtocol::try_from(frame(1, &[0, 0, 0, 7])),
+            Ok(CallProtocol::Ping(Ping { sequence: 7 }))
+        ));
+        assert!(matches!(
+            CallProtocol::try_from(frame(2, &[])),
+            Ok(CallProtocol::Hangup)
+        ));
+    }
+
+    #[test]
+    fn encodes_payloads_into_frames() {
+        let frame = Frame::try_from(CallProtocol::Ping(Ping { sequence: 7 })).unwrap();
+
+        assert_eq!(frame.message.0, 1);
+        assert_eq!(&frame.payload[..], [0, 0, 0, 7]);
+
+        let frame = Frame::try_from(CallProtocol::Hangup).unwrap();
+
+        assert_eq!(frame.message.0, 2);
+        assert!(frame.payload.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_codes_without_a_catch_all() {
+        assert!(matches!(
+            CallProtocol::try_from(frame(9, &[0, 0, 0, 7])),
+            Err(WireError::InvalidMessageType(9))
+        ));
+    }
+
+    #[test]
+    fn rejects_incomplete_payloads() {
+        assert!(matches!(
+            CallProtocol::try_from(frame(1, &[0, 0, 7])),
+            Err(WireError::Underflow("Ping", 3, 4))
+        ));
+    }
+
+    #[test]
+    fn keeps_unknown_codes_and_payloads_under_a_catch_all() {
+        let decoded = WideProtocol::try_from(frame(70, &[1, 2])).unwrap();
+        assert!(matches!(&decoded, WideProtocol::Other(70, payload) if payload[..] == [1, 2]));
+
+        let encoded = Frame::try_from(decoded).unwrap();
+        assert_eq!(encoded.message.0, 70);
+        assert_eq!(&encoded.payload[..], [1, 2]);
+
+        let encoded = Frame::try_from(WideProtocol::Wide).unwrap();
+        assert_eq!(encoded.message.0, 300);
+        assert!(matches!(
+            WideProtocol::try_from(encoded),
+            Ok(WideProtocol::Wide)
+        ));
+    }
+
+    #[test]
+    fn rejects_codes_past_the_message_repr() {
+        assert!(matches!(
+            WideMessage::try_from(&Message(70_000)),
+            Err(WireError::InvalidMessageType(70_000))
+        ));
+        assert!(matches!(
+            NarrowMessage::try_from(&Message(257)),
+            Err(WireError::InvalidMessageType(257))
+        ));
+        assert_eq!(Message::from(WideMessage::Wide).0, 300);
+    }
+
+    #[test]
+    fn compares_catch_all_codes_by_value() {
+        use std::collections::HashSet;
+
+        assert!(matches!(WideMessage::from(300), WideMessage::Wide));
+        assert!(matches!(WideMessage::from(70), WideMessage::Other(70)));
+
+        // Built by hand with a named code, it still encodes and compares as that message
+        let by_hand = WideMessage::Other(300);
+
+        assert_eq!(by_hand, WideMessage::Wide);
+        assert_ne!(WideMessage::Other(70), WideMessage::Other(71));
+        assert_eq!(HashSet::from([by_hand, WideMessage::Wide]).len(), 1);
+        assert_eq!(Message::from(by_hand).0, 300);
+    }
+}
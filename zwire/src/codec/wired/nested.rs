@@ -0,0 +1,28 @@
+use super::WiredField;
+use crate::{codec::Encoder, errors::WireError, BufDecoder};
+
+// Codecs whose records can be embedded in other records, derive(WireCodec) implements it
+pub trait WiredRecord:
+    Default
+    + Encoder<Self::Record, Error = WireError>
+    + BufDecoder<Item = Self::Record, Error = WireError>
+{
+    type Record;
+
+    const FIXED_PART_LENGTH: usize;
+    const MAX_LENGTH: usize;
+}
+
+// The codec of a record type, derive(WireCodec) implements it next to WiredRecord so a record can
+// be encoded and decoded knowing only its type, e.g. by define_message! protocols
+pub trait WiredPayload: Sized {
+    type Codec: WiredRecord<Record = Self>;
+}
+
+// A field carrying a record, either as is (the record has to have a fixed size then) or behind a
+// length prefix when the field also implements WiredLengthPrefixed
+pub trait WiredNested: WiredField {
+    type Codec: WiredRecord;
+}
+
+pub type NestedRecord<N> = <<N as WiredNested>::Codec as WiredRecord>::Record;
@@ -0,0 +1,390 @@
+use super::{WiredField, WiredFixedBytes, WiredInt, WiredLengthPrefixed, WiredString};
+use crate::{
+    codec::bytes::{ByteStr, BytesMut, BytesMutPutExt, BytesMutTakeExt, WireBuf},
+    errors::WireError,
+    helpers::CheckedAddWire,
+};
+use ::bytes::Bytes;
+use core::marker::PhantomData;
+
+// [count prefix] | [element]...
+pub trait WiredRepeated: WiredField {
+    type CountPrefix: WiredInt;
+    type Element: WiredElement;
+
+    const MAX_COUNT: usize;
+
+    // Encoded size of the whole list including its count prefix
+    fn encoded_length(
+        values: &[<Self::Element as WiredElement>::Value],
+    ) -> Result<usize, WireError> {
+        let count = values.len();
+        let max_count = Self::MAX_COUNT.min(Self::CountPrefix::MAX);
+
+        if count > max_count {
+            return Err(WireError::Oversized(Self::FIELD_NAME, count, max_count));
+        }
+
+        values.iter().try_fold(
+            Self::CountPrefix::to_bytes_from_usize(count, Self::FIELD_NAME)?
+                .as_ref()
+                .len(),
+            |total_length, value| {
+                total_length.checked_add_wire(
+                    "total_length",
+                    Self::Element::encoded_length(value, Self::FIELD_NAME)?,
+                    Self::FIELD_NAME,
+                )
+            },
+        )
+    }
+
+    // Size of the list at the start of `source`, `None` until all of it arrived
+    fn measure(source: &[u8]) -> Result<Option<usize>, WireError> {
+        let Some(mut cursor) = Self::CountPrefix::encoded_size(source) else {
+            return Ok(None);
+        };
+
+        let Some(prefix) = source.get(..cursor) else {
+            return Ok(None);
+        };
+
+        let Some(count) = Self::CountPrefix::read(prefix, Self::FIELD_NAME)? else {
+            return Ok(None);
+        };
+
+        if count > Self::MAX_COUNT {
+            return Err(WireError::Oversized(
+                Self::FIELD_NAME,
+                count,
+                Self::MAX_COUNT,
+            ));
+        }
+
+        for _ in 0..count {
+            let Some(rest) = source.get(cursor..) else {
+                return Ok(None);
+            };
+
+            let Some(size) = Self::Element::measure(rest, Self::FIELD_NAME)? else {
+                return Ok(None);
+            };
+
+            cursor = cursor.checked_add_wire("cursor", size, Self::FIELD_NAME)?;
+        }
+
+        if source.len() < cursor {
+            return Ok(None);
+        }
+
+        Ok(Some(cursor))
+    }
+}
+
+// One entry of a repeated field, implemented by the wrappers below for every single field kind
+pub trait WiredElement {
+    type Value;
+
+    const MAX_LENGTH: usize;
+
+    fn encoded_length(value: &Self::Value, field_name: &'static str) -> Result<usize, WireError>;
+
+    fn measure(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError>;
+
+    fn put(destination: &mut BytesMut, value: &Self::Value) -> Result<(), WireError>;
+
+    // `None` if the element isn't all there, nothing is consumed then
+    fn take<B: WireBuf>(source: &mut B) -> Result<Option<Self::Value>, WireError>;
+}
+
+pub struct ScalarElement<W>(PhantomData<W>);
+
+impl<W> WiredElement for ScalarElement<W>
+where
+    W: WiredInt + WiredField,
+    W::Int: Copy,
+{
+    type Value = W::Int;
+
+    const MAX_LENGTH: usize = W::SIZE;
+
+    #[inline]
+    fn encoded_length(value: &Self::Value, _field_name: &'static str) -> Result<usize, WireError> {
+        Ok(W::to_bytes(*value).as_ref().len())
+    }
+
+    #[inline]
+    fn measure(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError> {
+        W::check(source, field_name)?;
+
+        Ok(W::encoded_size(source).filter(|size| source.len() >= *size))
+    }
+
+    #[inline]
+    fn put(destination: &mut BytesMut, value: &Self::Value) -> Result<(), WireError> {
+        destination.put_single::<W>(*value);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn take<B: WireBuf>(source: &mut B) -> Result<Option<Self::Value>, WireError> {
+        source.take_single::<W>()
+    }
+}
+
+pub struct FixedBytesElement<W>(PhantomData<W>);
+
+impl<W: WiredFixedBytes<Output = Bytes>> WiredElement for FixedBytesElement<W> {
+    type Value = Bytes;
+
+    const MAX_LENGTH: usize = W::LENGTH;
+
+    #[inline]
+    fn encoded_length(value: &Self::Value, field_name: &'static str) -> Result<usize, WireError> {
+        if value.len() != W::LENGTH {
+            return Err(WireError::Underflow(field_name, value.len(), W::LENGTH));
+        }
+
+        Ok(W::LENGTH)
+    }
+
+    #[inline]
+    fn measure(source: &[u8], _field_name: &'static str) -> Result<Option<usize>, WireError> {
+        Ok((source.len() >= W::LENGTH).then_some(W::LENGTH))
+    }
+
+    #[inline]
+    fn put(destination: &mut BytesMut, value: &Self::Value) -> Result<(), WireError> {
+        destination.put_fixed_bytes::<W>(value)
+    }
+
+    #[inline]
+    fn take<B: WireBuf>(source: &mut B) -> Result<Option<Self::Value>, WireError> {
+        Ok(source.take_fixed_bytes::<W>())
+    }
+}
+
+pub struct LengthPrefixedElement<W>(PhantomData<W>);
+
+impl<W: WiredLengthPrefixed> LengthPrefixedElement<W> {
+    fn payload_length(payload_length: usize, field_name: &'static str) -> Result<usize, WireError> {
+        if payload_length > W::MAX_LENGTH {
+            return Err(WireError::Oversized(
+                field_name,
+                payload_length,
+                W::MAX_LENGTH,
+            ));
+        }
+
+        W::LengthPrefix::to_bytes_from_usize(payload_length, field_name)?
+            .as_ref()
+            .len()
+            .checked_add_wire("element_length", payload_length, field_name)
+    }
+
+    fn measure_payload(
+        source: &[u8],
+        field_name: &'static str,
+    ) -> Result<Option<usize>, WireError> {
+        let Some(size) = W::LengthPrefix::encoded_size(source) else {
+            return Ok(None);
+        };
+
+        let Some(prefix) = source.get(..size) else {
+            return Ok(None);
+        };
+
+        let Some(payload_length) = W::LengthPrefix::read(prefix, field_name)? else {
+            return Ok(None);
+        };
+
+        if payload_length > W::MAX_LENGTH {
+            return Err(WireError::Oversized(
+                field_name,
+                payload_length,
+                W::MAX_LENGTH,
+            ));
+        }
+
+        let total_length = size.checked_add_wire("element_length", payload_length, field_name)?;
+
+        Ok((source.len() >= total_length).then_some(total_length))
+    }
+}
+
+impl<W: WiredLengthPrefixed> WiredElement for LengthPrefixedElement<W> {
+    type Value = Bytes;
+
+    const MAX_LENGTH: usize = W::LengthPrefix::SIZE + W::MAX_LENGTH;
+
+    #[inline]
+    fn encoded_length(value: &Self::Value, field_name: &'static str) -> Result<usize, WireError> {
+        Self::payload_length(value.len(), field_name)
+    }
+
+    #[inline]
+    fn measure(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError> {
+        Self::measure_payload(source, field_name)
+    }
+
+    #[inline]
+    fn put(destination: &mut BytesMut, value: &Self::Value) -> Result<(), WireError> {
+        destination.put_length_prefixed::<W>(value)
+    }
+
+    #[inline]
+    fn take<B: WireBuf>(source: &mut B) -> Result<Option<Self::Value>, WireError> {
+        source.take_length_prefixed::<W>()
+    }
+}
+
+pub struct StringElement<W>(PhantomData<W>);
+
+impl<W: WiredString> WiredElement for StringElement<W> {
+    type Value = ByteStr;
+
+    const MAX_LENGTH: usize = <LengthPrefixedElement<W::Inner> as WiredElement>::MAX_LENGTH;
+
+    #[inline]
+    fn encoded_length(value: &Self::Value, field_name: &'static str) -> Result<usize, WireError> {
+        LengthPrefixedElement::<W::Inner>::payload_length(value.len(), field_name)
+    }
+
+    #[inline]
+    fn measure(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError> {
+        LengthPrefixedElement::<W::Inner>::measure_payload(source, field_name)
+    }
+
+    #[inline]
+    fn put(destination: &mut BytesMut, value: &Self::Value) -> Result<(), WireError> {
+        destination.put_length_prefixed_string::<W>(value.clone().into_bytes())
+    }
+
+    #[inline]
+    fn take<B: WireBuf>(source: &mut B) -> Result<Option<Self::Value>, WireError> {
+        source.take_length_prefixed_string::<W>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{bytes::BytesMutTakeExt, wired::define_fields};
+
+    define_fields! {
+        (Scores, u8, repeated, 4, (u16, fixed)),
+        (Keys, u8, repeated, 3, (4, fixed)),
+        (Hosts, u8, repeated, 3, (u8, length_prefix_string, 16, Hostname)),
+    }
+
+    fn put<R: WiredRepeated>(values: &[<R::Element as WiredElement>::Value]) -> BytesMut {
+        let mut destination = BytesMut::new();
+        destination.put_repeated::<R>(values).unwrap();
+
+        assert_eq!(destination.len(), R::encoded_length(values).unwrap());
+
+        destination
+    }
+
+    // Every cut short of the whole list reads as `None` and leaves the source as it was
+    fn assert_incomplete_until_whole<R: WiredRepeated>(encoded: &BytesMut) {
+        for length in 0..encoded.len() {
+            let mut source = BytesMut::from(&encoded[..length]);
+
+            assert!(
+                source.take_repeated::<R>().unwrap().is_none(),
+                "{length} bytes"
+            );
+            assert_eq!(source.len(), length);
+        }
+    }
+
+    #[test]
+    fn round_trips_scalar_elements() {
+        let mut encoded = put::<fields::scores::Wired>(&[1, 0x0203, u16::MAX]);
+
+        assert_eq!(&encoded[..], [3, 0, 1, 2, 3, 0xFF, 0xFF]);
+        assert_incomplete_until_whole::<fields::scores::Wired>(&encoded);
+        assert_eq!(
+            encoded.take_repeated::<fields::scores::Wired>().unwrap(),
+            Some(vec![1, 0x0203, u16::MAX])
+        );
+        assert!(encoded.is_empty());
+    }
+
+    #[test]
+    fn round_trips_fixed_bytes_elements() {
+        let keys = [Bytes::from_static(b"abcd"), Bytes::from_static(b"efgh")];
+        let mut encoded = put::<fields::keys::Wired>(&keys);
+
+        assert_eq!(&encoded[..], b"\x02abcdefgh");
+        assert_incomplete_until_whole::<fields::keys::Wired>(&encoded);
+        assert_eq!(
+            encoded.take_repeated::<fields::keys::Wired>().unwrap(),
+            Some(keys.to_vec())
+        );
+
+        // Elements have to have the exact size
+        assert!(matches!(
+            BytesMut::new().put_repeated::<fields::keys::Wired>(&[Bytes::from_static(b"abc")]),
+            Err(WireError::Underflow("keys", 3, 4))
+        ));
+    }
+
+    #[test]
+    fn round_trips_string_elements() {
+        let hosts = [ByteStr::from("a.example"), ByteStr::from("b")];
+        let mut encoded = put::<fields::hosts::Wired>(&hosts);
+
+        assert_eq!(&encoded[..], b"\x02\x09a.example\x01b");
+        assert_incomplete_until_whole::<fields::hosts::Wired>(&encoded);
+        assert_eq!(
+            encoded.take_repeated::<fields::hosts::Wired>().unwrap(),
+            Some(hosts.to_vec())
+        );
+    }
+
+    #[test]
+    fn rejects_lists_past_max_count() {
+        let mut destination = BytesMut::from(&b"head"[..]);
+
+        assert!(matches!(
+            destination.put_repeated::<fields::scores::Wired>(&[0; 5]),
+            Err(WireError::Oversized("scores", 5, 4))
+        ));
+        assert_eq!(&destination[..], b"head");
+
+        // Rejected on the count alone, before any element arrived
+        let mut source = BytesMut::from(&[5][..]);
+
+        assert!(matches!(
+            source.take_repeated::<fields::scores::Wired>(),
+            Err(WireError::Oversized("scores", 5, 4))
+        ));
+        assert!(matches!(
+            <fields::scores::Wired as WiredRepeated>::measure(&source),
+            Err(WireError::Oversized("scores", 5, 4))
+        ));
+    }
+
+    #[test]
+    fn rejects_lists_with_a_string_failing_its_policy() {
+        let mut destination = BytesMut::from(&b"head"[..]);
+        let hosts = [ByteStr::from("fine"), ByteStr::from("-bad")];
+
+        // The list written so far is taken back out
+        assert!(matches!(
+            destination.put_repeated::<fields::hosts::Wired>(&hosts),
+            Err(WireError::MalformedString(error)) if error.field == Some("hosts")
+        ));
+        assert_eq!(&destination[..], b"head");
+
+        let mut source = BytesMut::from(&b"\x02\x04fine\x04-bad"[..]);
+
+        assert!(matches!(
+            source.take_repeated::<fields::hosts::Wired>(),
+            Err(WireError::MalformedString(error)) if error.field == Some("hosts")
+        ));
+    }
+}
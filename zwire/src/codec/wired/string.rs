@@ -6,32 +6,263 @@ use crate::{
 
 pub trait WiredString: WiredField {
     type Inner: WiredLengthPrefixed;
+    type Policy: WiredStringPolicy;
+}
+
+// Runs on strings that already passed the UTF-8 check, both before they're written and after
+// they're read. define_fields! takes a policy by path, e.g. `length_prefix_string, 64, Hostname`
+// or `length_prefix_string, 64, crate::policies::ClientId`
+pub trait WiredStringPolicy {
+    fn validate(source: &ByteStr) -> Result<(), MalformedStringError>;
+}
 
-    const POLICY: WiredStringPolicyKind;
+#[inline]
+fn malformed(kind: MalformedStringKind) -> Result<(), MalformedStringError> {
+    Err(MalformedStringError { field: None, kind })
 }
 
-pub enum WiredStringPolicyKind {
-    Utf8,
-    AsciiHyphen,
+// Any UTF-8
+pub struct Utf8;
+
+impl WiredStringPolicy for Utf8 {
+    #[inline]
+    fn validate(_source: &ByteStr) -> Result<(), MalformedStringError> {
+        Ok(())
+    }
+}
+
+// ASCII letters, digits, `_` and `-`
+pub struct AsciiHyphen;
+
+impl WiredStringPolicy for AsciiHyphen {
+    #[inline]
+    fn validate(source: &ByteStr) -> Result<(), MalformedStringError> {
+        for &byte in source.as_bytes() {
+            if !(byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-') {
+                return malformed(MalformedStringKind::InvalidCharacter(byte));
+            }
+        }
+
+        Ok(())
+    }
 }
 
-impl WiredStringPolicyKind {
+// ASCII from space to `~`, no control characters
+pub struct PrintableAscii;
+
+impl WiredStringPolicy for PrintableAscii {
     #[inline]
-    pub fn validate(&self, source: &ByteStr) -> Result<(), MalformedStringError> {
-        match self {
-            WiredStringPolicyKind::Utf8 => Ok(()),
-            WiredStringPolicyKind::AsciiHyphen => {
-                for &byte in source.as_bytes() {
-                    if !(byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-') {
-                        return Err(MalformedStringError {
-                            field: None,
-                            kind: MalformedStringKind::InvalidCharacter(byte),
-                        });
-                    }
-                }
-
-                Ok(())
+    fn validate(source: &ByteStr) -> Result<(), MalformedStringError> {
+        for &byte in source.as_bytes() {
+            if !byte.is_ascii() {
+                return malformed(MalformedStringKind::NonAscii);
+            }
+
+            if !(byte == b' ' || byte.is_ascii_graphic()) {
+                return malformed(MalformedStringKind::InvalidCharacter(byte));
             }
         }
+
+        Ok(())
+    }
+}
+
+const HOSTNAME_MAX_LENGTH: usize = 253;
+const HOSTNAME_LABEL_MAX_LENGTH: usize = 63;
+
+// RFC 1123 hostnames: dot separated labels of ASCII letters, digits and `-`, none of them empty,
+// longer than 63 bytes or starting or ending with `-`. No trailing dot
+pub struct Hostname;
+
+impl WiredStringPolicy for Hostname {
+    fn validate(source: &ByteStr) -> Result<(), MalformedStringError> {
+        let bytes = source.as_bytes();
+
+        if bytes.len() > HOSTNAME_MAX_LENGTH {
+            return malformed(MalformedStringKind::TooLong(
+                bytes.len(),
+                HOSTNAME_MAX_LENGTH,
+            ));
+        }
+
+        for label in bytes.split(|&byte| byte == b'.') {
+            if label.is_empty() {
+                return malformed(MalformedStringKind::InvalidHostname("empty label"));
+            }
+
+            if label.len() > HOSTNAME_LABEL_MAX_LENGTH {
+                return malformed(MalformedStringKind::InvalidHostname(
+                    "label longer than 63 bytes",
+                ));
+            }
+
+            if label.starts_with(b"-") || label.ends_with(b"-") {
+                return malformed(MalformedStringKind::InvalidHostname(
+                    "label starts or ends with a hyphen",
+                ));
+            }
+
+            if let Some(&byte) = label
+                .iter()
+                .find(|&&byte| !(byte.is_ascii_alphanumeric() || byte == b'-'))
+            {
+                return malformed(MalformedStringKind::InvalidCharacter(byte));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// NFC normalized text of MIN..=MAX characters, counted as Unicode scalar values rather than
+// bytes. Strings that aren't normalized are rejected, not normalized on the way through, so the
+// bytes on the wire are the ones that were validated
+#[cfg(feature = "unicode")]
+pub struct NfcText<const MIN: usize, const MAX: usize>;
+
+#[cfg(feature = "unicode")]
+impl<const MIN: usize, const MAX: usize> WiredStringPolicy for NfcText<MIN, MAX> {
+    fn validate(source: &ByteStr) -> Result<(), MalformedStringError> {
+        let text = source.as_str();
+        let count = text.chars().count();
+
+        if !(MIN..=MAX).contains(&count) {
+            return malformed(MalformedStringKind::CharacterCount(count, MIN, MAX));
+        }
+
+        if !unicode_normalization::is_nfc(text) {
+            return malformed(MalformedStringKind::NotNormalized);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind<P: WiredStringPolicy>(source: impl Into<String>) -> Option<MalformedStringKind> {
+        P::validate(&ByteStr::from(source.into()))
+            .err()
+            .map(|error| error.kind)
+    }
+
+    #[test]
+    fn hostname_accepts_rfc_1123_names() {
+        let longest_label = "a".repeat(63);
+
+        for name in [
+            "localhost",
+            "a",
+            "relay-1.example.com",
+            "9lives.net",
+            &longest_label,
+        ] {
+            assert!(kind::<Hostname>(name).is_none(), "{name}");
+        }
+    }
+
+    #[test]
+    fn hostname_rejects_malformed_labels() {
+        for name in ["", "example..com", ".example.com", "example.com."] {
+            assert!(
+                matches!(
+                    kind::<Hostname>(name),
+                    Some(MalformedStringKind::InvalidHostname("empty label"))
+                ),
+                "{name:?}"
+            );
+        }
+
+        assert!(matches!(
+            kind::<Hostname>(format!("{}.com", "a".repeat(64))),
+            Some(MalformedStringKind::InvalidHostname(
+                "label longer than 63 bytes"
+            ))
+        ));
+
+        for name in ["-relay.com", "relay-.com", "relay.-"] {
+            assert!(
+                matches!(
+                    kind::<Hostname>(name),
+                    Some(MalformedStringKind::InvalidHostname(
+                        "label starts or ends with a hyphen"
+                    ))
+                ),
+                "{name:?}"
+            );
+        }
+
+        assert!(matches!(
+            kind::<Hostname>("relay_1.com"),
+            Some(MalformedStringKind::InvalidCharacter(b'_'))
+        ));
+    }
+
+    #[test]
+    fn hostname_rejects_names_past_253_bytes() {
+        let label = "a".repeat(63);
+        let name = [&label[..], &label, &label, &label].join(".");
+
+        assert_eq!(name.len(), 255);
+        assert!(matches!(
+            kind::<Hostname>(name),
+            Some(MalformedStringKind::TooLong(255, 253))
+        ));
+    }
+
+    #[test]
+    fn printable_ascii_rejects_control_and_non_ascii_bytes() {
+        assert!(kind::<PrintableAscii>("Hello, world ~").is_none());
+        assert!(matches!(
+            kind::<PrintableAscii>("tab\there"),
+            Some(MalformedStringKind::InvalidCharacter(b'\t'))
+        ));
+        assert!(matches!(
+            kind::<PrintableAscii>("bell\x07"),
+            Some(MalformedStringKind::InvalidCharacter(0x07))
+        ));
+        assert!(matches!(
+            kind::<PrintableAscii>("del\x7F"),
+            Some(MalformedStringKind::InvalidCharacter(0x7F))
+        ));
+        assert!(matches!(
+            kind::<PrintableAscii>("café"),
+            Some(MalformedStringKind::NonAscii)
+        ));
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn nfc_text_rejects_decomposed_input() {
+        // "é" precomposed, then as "e" and a combining acute accent
+        assert!(kind::<NfcText<1, 8>>("caf\u{E9}").is_none());
+        assert!(matches!(
+            kind::<NfcText<1, 8>>("cafe\u{301}"),
+            Some(MalformedStringKind::NotNormalized)
+        ));
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn nfc_text_counts_characters_rather_than_bytes() {
+        // 3 characters in 9 bytes
+        let text = "日本語";
+
+        assert_eq!(text.len(), 9);
+        assert!(kind::<NfcText<3, 3>>(text).is_none());
+        assert!(matches!(
+            kind::<NfcText<4, 9>>(text),
+            Some(MalformedStringKind::CharacterCount(3, 4, 9))
+        ));
+        assert!(matches!(
+            kind::<NfcText<1, 2>>(text),
+            Some(MalformedStringKind::CharacterCount(3, 1, 2))
+        ));
+        assert!(matches!(
+            kind::<NfcText<1, 2>>(""),
+            Some(MalformedStringKind::CharacterCount(0, 1, 2))
+        ));
     }
 }
@@ -0,0 +1,234 @@
+use super::WiredInt;
+use crate::WireError;
+
+// QUIC variable-length integer (RFC 9000 §16), the two most significant bits of the first byte
+// select the encoded size: 00 = 1, 01 = 2, 10 = 4, 11 = 8 bytes
+const LENGTH_TAG_SHIFT: u32 = 6;
+const LENGTH_TAG_MASK: u8 = 0b0011_1111;
+
+const ONE_BYTE_MAX: u64 = (1 << 6) - 1;
+const TWO_BYTES_MAX: u64 = (1 << 14) - 1;
+const FOUR_BYTES_MAX: u64 = (1 << 30) - 1;
+const EIGHT_BYTES_MAX: u64 = (1 << 62) - 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarInt(u64);
+
+impl VarInt {
+    pub const MAX: VarInt = VarInt(EIGHT_BYTES_MAX);
+
+    pub const fn from_u32(value: u32) -> Self {
+        Self(value as u64)
+    }
+
+    pub fn from_u64(value: u64) -> Result<Self, WireError> {
+        if value > EIGHT_BYTES_MAX {
+            return Err(WireError::VarIntOverflow(value));
+        }
+
+        Ok(Self(value))
+    }
+
+    pub const fn into_inner(self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn encoded_size(self) -> usize {
+        if self.0 <= ONE_BYTE_MAX {
+            1
+        } else if self.0 <= TWO_BYTES_MAX {
+            2
+        } else if self.0 <= FOUR_BYTES_MAX {
+            4
+        } else {
+            8
+        }
+    }
+}
+
+impl From<u8> for VarInt {
+    fn from(value: u8) -> Self {
+        Self(value as u64)
+    }
+}
+
+impl From<u16> for VarInt {
+    fn from(value: u16) -> Self {
+        Self(value as u64)
+    }
+}
+
+impl From<u32> for VarInt {
+    fn from(value: u32) -> Self {
+        Self::from_u32(value)
+    }
+}
+
+impl TryFrom<u64> for VarInt {
+    type Error = WireError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        Self::from_u64(value)
+    }
+}
+
+impl From<VarInt> for u64 {
+    fn from(value: VarInt) -> Self {
+        value.0
+    }
+}
+
+pub struct VarIntBytes {
+    buffer: [u8; 8],
+    length: usize,
+}
+
+impl AsRef<[u8]> for VarIntBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+}
+
+impl AsMut<[u8]> for VarIntBytes {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer[..self.length]
+    }
+}
+
+pub struct WiredVarInt;
+
+impl WiredInt for WiredVarInt {
+    type Int = VarInt;
+    type ByteArray = VarIntBytes;
+
+    const SIZE: usize = 8;
+    const MIN_SIZE: usize = 1;
+    const MAX: usize = if EIGHT_BYTES_MAX > usize::MAX as u64 {
+        usize::MAX
+    } else {
+        EIGHT_BYTES_MAX as usize
+    };
+
+    #[inline]
+    fn encoded_size(source: &[u8]) -> Option<usize> {
+        let first_byte = source.first()?;
+
+        Some(1 << (first_byte >> LENGTH_TAG_SHIFT))
+    }
+
+    #[inline]
+    fn read_raw(source: &[u8]) -> Option<Self::Int> {
+        let size = Self::encoded_size(source)?;
+        let (first_byte, rest) = source.get(..size)?.split_first()?;
+        let mut value = (first_byte & LENGTH_TAG_MASK) as u64;
+
+        for &byte in rest {
+            value = (value << 8) | byte as u64;
+        }
+
+        Some(VarInt(value))
+    }
+
+    #[inline]
+    fn read(source: &[u8], field_name: &'static str) -> Result<Option<usize>, WireError> {
+        let Some(raw_value) = Self::read_raw(source) else {
+            return Ok(None);
+        };
+
+        let raw_value = raw_value.into_inner();
+        let value: usize = raw_value
+            .try_into()
+            .map_err(|_| WireError::LengthOverflow(field_name, raw_value as u128, usize::MAX))?;
+
+        Ok(Some(value))
+    }
+
+    #[inline]
+    fn to_bytes_from_usize(
+        value: usize,
+        field_name: &'static str,
+    ) -> Result<Self::ByteArray, WireError> {
+        if value as u64 > EIGHT_BYTES_MAX {
+            return Err(WireError::LengthOverflow(
+                field_name,
+                value as u128,
+                Self::MAX,
+            ));
+        }
+
+        Ok(Self::to_bytes(VarInt(value as u64)))
+    }
+
+    #[inline]
+    fn to_bytes(value: Self::Int) -> Self::ByteArray {
+        let length = value.encoded_size();
+        let length_tag = (length.trailing_zeros() as u64) << (length * 8 - 2);
+        let tagged = (value.0 | length_tag).to_be_bytes();
+
+        let mut buffer = [0u8; 8];
+        buffer[..length].copy_from_slice(&tagged[8 - length..]);
+
+        VarIntBytes { buffer, length }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both sides of every width boundary, with the size each one encodes to
+    const BOUNDARIES: [(u64, usize); 7] = [
+        (ONE_BYTE_MAX, 1),
+        (ONE_BYTE_MAX + 1, 2),
+        (TWO_BYTES_MAX, 2),
+        (TWO_BYTES_MAX + 1, 4),
+        (FOUR_BYTES_MAX, 4),
+        (FOUR_BYTES_MAX + 1, 8),
+        (EIGHT_BYTES_MAX, 8),
+    ];
+
+    #[test]
+    fn round_trips_at_width_boundaries() {
+        for (value, size) in BOUNDARIES {
+            let varint = VarInt::from_u64(value).unwrap();
+            let bytes = WiredVarInt::to_bytes(varint);
+
+            assert_eq!(bytes.as_ref().len(), size, "{value}");
+            assert_eq!(varint.encoded_size(), size, "{value}");
+            assert_eq!(WiredVarInt::encoded_size(bytes.as_ref()), Some(size));
+            assert_eq!(WiredVarInt::read_raw(bytes.as_ref()), Some(varint));
+
+            let length = usize::try_from(value).unwrap();
+            let bytes = WiredVarInt::to_bytes_from_usize(length, "length").unwrap();
+
+            assert_eq!(
+                WiredVarInt::read(bytes.as_ref(), "length").unwrap(),
+                Some(length)
+            );
+        }
+    }
+
+    #[test]
+    fn reads_short_sources_as_none() {
+        let bytes = WiredVarInt::to_bytes(VarInt::from_u32(FOUR_BYTES_MAX as u32));
+
+        assert_eq!(WiredVarInt::read_raw(&bytes.as_ref()[..3]), None);
+        assert_eq!(WiredVarInt::read(&[], "length").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_values_past_the_largest_width() {
+        assert!(matches!(
+            VarInt::from_u64(EIGHT_BYTES_MAX + 1),
+            Err(WireError::VarIntOverflow(value)) if value == EIGHT_BYTES_MAX + 1
+        ));
+
+        if let Ok(length) = usize::try_from(EIGHT_BYTES_MAX + 1) {
+            assert!(matches!(
+                WiredVarInt::to_bytes_from_usize(length, "length"),
+                Err(WireError::LengthOverflow("length", value, _)) if value == length as u128
+            ));
+        }
+    }
+}
@@ -1,58 +1,166 @@
-use std::str::Utf8Error;
+use alloc::string::String;
+use core::{convert::Infallible, str::Utf8Error, time::Duration};
 
-#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "std", derive(miette::Diagnostic))]
 pub enum WireError {
+    #[cfg(feature = "std")]
     #[error("IO error: {0:#?}")]
     #[diagnostic(severity(Error))]
     Io(#[from] std::io::Error),
 
     #[error("oversized, {1} bytes > {2} bytes limit at field ({0})")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     Oversized(&'static str, usize, usize),
 
     #[error("underflow, field ({0}) has {1} bytes, needs {2}")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     Underflow(&'static str, usize, usize),
 
+    #[error("field ({0}) runs past the end its record was measured at")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    Truncated(&'static str),
+
     #[error("arithmetic overflow, attempted to add {0} ({1}) to {2} ({3})")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     ArithmeticOverflow(usize, &'static str, usize, &'static str),
 
     #[error("length overflow for field ({0}): {1} > {2}")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     LengthOverflow(&'static str, u128, usize),
 
+    #[error("field ({0}) can't carry a length")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    InvalidLengthPrefix(&'static str),
+
+    #[error("field ({0}) holds {1}, a bool is 0 or 1")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    InvalidBool(&'static str, u8),
+
+    #[error("presence bitmap ({0}) has unknown bits set (0b{1:b})")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    UnknownPresenceBits(&'static str, u64),
+
+    #[error("malformed extension ({0})")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    InvalidExtension(&'static str),
+
+    #[error("extension ({0}) with tag {1} appears more than once")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    DuplicateExtension(&'static str, u64),
+
+    #[error("varint overflow, {0} > {max}", max = (1u64 << 62) - 1)]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    VarIntOverflow(u64),
+
+    #[error("reassembly of a fragmented message timed out after {0:?}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    ReassemblyTimeout(Duration),
+
+    #[error("unexpected fragment, {0}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    UnexpectedFragment(&'static str),
+
+    #[error("checksum mismatch, frame carries 0x{0:08X}, computed 0x{1:08X}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    ChecksumMismatch(u32, u32),
+
+    #[error("invalid frame flags (0b{0:08b})")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    InvalidFrameFlags(u8),
+
+    #[error("frame transform failed: {0}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    TransformFailed(String),
+
+    #[error("channel {0} is already open")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    ChannelInUse(u64),
+
+    #[error("channel {0} is closed")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    ChannelClosed(u64),
+
+    #[error("channel {0} received more frames than it granted credits for")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    ChannelOverrun(u64),
+
+    #[error("{0} queue is closed, the scheduler's driver stopped")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    QueueClosed(&'static str),
+
+    #[error("datagrams unavailable, {0}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    DatagramsUnavailable(&'static str),
+
+    #[error("malformed datagram, {0}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    MalformedDatagram(&'static str),
+
+    #[error("{0} timed out after {1:?}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    Timeout(&'static str, Duration),
+
+    #[error("frame with message {0} carries an incomplete payload")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    IncompletePayload(u32),
+
     #[error("invalid message type ({0})")]
-    #[diagnostic(severity(Error))]
-    InvalidMessageType(u8),
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    InvalidMessageType(u32),
+
+    #[error("invalid message name ({0})")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    InvalidMessageName(String),
 
     #[error("malformed string ({0:?})")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     MalformedString(#[from] MalformedStringError),
 }
 
-#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+// Lets derived codecs convert every field with TryFrom, identity conversions can't fail
+impl From<Infallible> for WireError {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "std", derive(miette::Diagnostic))]
 #[error("{field:?}: {kind}")]
 pub struct MalformedStringError {
     pub field: Option<&'static str>,
     pub kind: MalformedStringKind,
 }
 
-#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(feature = "std", derive(miette::Diagnostic))]
 pub enum MalformedStringKind {
     #[error("{0:?}")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     InvalidUtf8(#[from] Utf8Error),
 
     #[error("string contains non-ASCII bytes")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     NonAscii,
 
     #[error("string exceeds maximum length {0} > {1}")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     TooLong(usize, usize),
 
     #[error("string contains an unallowed byte: 0x{0:02X}")]
-    #[diagnostic(severity(Error))]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
     InvalidCharacter(u8),
+
+    #[error("string is not a valid hostname: {0}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    InvalidHostname(&'static str),
+
+    #[error("string has {0} characters, expected {1}..={2}")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    CharacterCount(usize, usize, usize),
+
+    #[error("string is not NFC normalized")]
+    #[cfg_attr(feature = "std", diagnostic(severity(Error)))]
+    NotNormalized,
 }
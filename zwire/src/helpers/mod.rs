@@ -1,2 +1,5 @@
 mod checked_add;
 pub use checked_add::CheckedAddWire;
+
+mod crc32c;
+pub use crc32c::{crc32c, crc32c_extend};
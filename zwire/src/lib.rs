@@ -1,28 +1,55 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 pub mod codec;
 pub mod errors;
 pub mod helpers;
+#[cfg(feature = "std")]
 pub mod session;
+#[cfg(feature = "transport")]
+pub mod transport;
 
+#[cfg(feature = "std")]
+pub use codec::{bytes::BufferPool, FragmentCodec};
 pub use codec::{
-    bytes::{Bytes, BytesMut},
+    bytes::{Bytes, BytesMut, WireBuf},
     Decoder, Encoder, FrameCodec,
 };
 use errors::WireError;
 
 pub mod __zwire_macros_support {
     pub use crate::{
-        codec::wired::{
-            WiredField, WiredFixedBytes, WiredInt, WiredLengthPrefixed, WiredString,
-            WiredStringPolicyKind,
+        codec::{
+            bytes::{BytesMutPutExt, BytesMutTakeExt, WireBuf},
+            wired::{
+                FixedBytesElement, Le, LengthPrefixedElement, ScalarElement, StringElement, VarInt,
+                WiredElement, WiredField, WiredFixedBytes, WiredInt, WiredLayout,
+                WiredLengthPrefixed, WiredNested, WiredPayload, WiredRecord, WiredRepeated,
+                WiredString, WiredStringPolicy, WiredVarInt,
+            },
+            Decoder, Encoder,
         },
         errors::WireError,
-        Message,
+        helpers::CheckedAddWire,
+        BufDecoder, DecodeFromFrame, EncodeIntoFrame, Frame, Message,
     };
-    pub use tokio_util::bytes::Bytes;
+    pub use ::bytes::{Bytes, BytesMut};
+
+    // Generated code only names core items directly, the alloc ones come from here
+    pub use alloc::{string::String, vec::Vec};
+
+    // Built-in string policies, define_fields! resolves their bare names here
+    pub mod string_policy {
+        #[cfg(feature = "unicode")]
+        pub use crate::codec::wired::NfcText;
+        pub use crate::codec::wired::{AsciiHyphen, Hostname, PrintableAscii, Utf8};
+    }
 }
 
+// The U16 and U32 frame headers only carry codes up to 255, VarInt ones carry any
 #[derive(Debug, Clone)]
-pub struct Message(pub u8);
+pub struct Message(pub u32);
 
 impl Message {
     pub fn empty() -> Self {
@@ -67,21 +94,99 @@ pub trait EncodeIntoFrame: Encoder<Self::EncodeItem> {
     }
 }
 
-pub trait DecodeFromFrame: Decoder {
+// Decoder over any WireBuf, lets frame payloads be decoded in place instead of being copied into
+// a codec buffer first
+pub trait BufDecoder {
+    type Item;
+    type Error;
+
+    fn decode_buf<B: WireBuf>(&mut self, source: &mut B)
+        -> Result<Option<Self::Item>, Self::Error>;
+}
+
+pub trait DecodeFromFrame: BufDecoder {
     fn decode_from_frame(
         &mut self,
         frame: Frame,
-        codec_buffer: &mut BytesMut,
     ) -> Result<Option<(Self::Item, Message)>, Self::Error>
     where
         Self: Sized,
     {
-        codec_buffer.extend_from_slice(&frame.payload);
+        let mut source = frame.payload;
 
-        if let Some(payload) = self.decode(codec_buffer)? {
+        if let Some(payload) = self.decode_buf(&mut source)? {
             Ok(Some((payload, frame.message)))
         } else {
             Ok(None)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{bytes::ByteStr, wired::WireCodec};
+    use core::ops::Range;
+
+    #[derive(Debug, Clone, WireCodec)]
+    struct Chunk {
+        #[wire(4, fixed)]
+        key: Bytes,
+        #[wire(u8, length_prefix_string, 32, Utf8)]
+        name: ByteStr,
+        #[wire(u16, length_prefix, 1024)]
+        body: Bytes,
+    }
+
+    fn range(bytes: &[u8]) -> Range<usize> {
+        let start = bytes.as_ptr() as usize;
+
+        start..start + bytes.len()
+    }
+
+    fn within(inner: &[u8], outer: &Range<usize>) -> bool {
+        let inner = range(inner);
+
+        outer.start <= inner.start && inner.end <= outer.end
+    }
+
+    #[test]
+    fn decodes_frame_payloads_without_copying() {
+        let mut codec = FrameCodec::default();
+        let mut wire = BytesMut::new();
+
+        let frame = ChunkCodec::default()
+            .encode_into_frame(
+                Chunk {
+                    key: Bytes::from_static(b"abcd"),
+                    name: ByteStr::from_static("relay"),
+                    body: Bytes::from(vec![7; 512]),
+                },
+                Message(3),
+                &mut BytesMut::new(),
+            )
+            .unwrap();
+        codec.encode(frame, &mut wire).unwrap();
+
+        let wire_range = range(&wire);
+        let frame = codec.decode(&mut wire).unwrap().unwrap();
+        let payload_range = range(&frame.payload);
+
+        // The frame itself is split out of the read buffer
+        assert!(within(&frame.payload, &wire_range));
+
+        let (chunk, message) = ChunkCodec::default()
+            .decode_from_frame(frame)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(message.0, 3);
+        assert_eq!(&chunk.key[..], b"abcd");
+        assert_eq!(&chunk.name[..], "relay");
+        assert_eq!(chunk.body.len(), 512);
+
+        assert!(within(&chunk.key, &payload_range));
+        assert!(within(chunk.name.as_bytes(), &payload_range));
+        assert!(within(&chunk.body, &payload_range));
+    }
+}
@@ -0,0 +1,434 @@
+use crate::{
+    codec::{
+        bytes::{BufferLease, BufferPool},
+        FrameCodec, FrameTransform, NoTransform,
+    },
+    errors::WireError,
+    Bytes, Frame,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, IoSlice},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    time::Instant,
+};
+use tokio_util::bytes::Buf;
+
+// Chunks handed to a single vectored write, every frame takes two of them at most
+const MAX_IO_SLICES: usize = 64;
+
+const DEFAULT_MAX_FRAMES: usize = 32;
+const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+// Coalesces frames into one vectored write. Payloads aren't copied, the batch chains each frame's
+// header and payload `Bytes` as they are and writes them out together once a trigger fires, or
+// on flush. Writers that can't do vectored writes still get the whole batch, one chunk at a time
+pub struct FrameBatch<W, T: FrameTransform = NoTransform> {
+    writer: W,
+    codec: FrameCodec<T>,
+    // Declared ahead of `scratch` so they're dropped first, the heads split off it share its
+    // allocation and the pool can only keep it once they're gone
+    chunks: VecDeque<Bytes>,
+    // Headers and trailers, split off into `chunks` as payloads come between them. Leased on the
+    // first push and given back once a flush wrote everything out
+    scratch: Option<BufferLease>,
+    buffer_pool: BufferPool,
+    frames: usize,
+    bytes: usize,
+    max_frames: usize,
+    max_bytes: usize,
+    max_delay: Option<Duration>,
+    // When the oldest unwritten frame was pushed
+    first_pushed_at: Option<Instant>,
+}
+
+impl<W: AsyncWrite + Unpin, T: FrameTransform> FrameBatch<W, T> {
+    pub fn new(writer: W, codec: FrameCodec<T>) -> Self {
+        Self {
+            writer,
+            codec,
+            chunks: VecDeque::new(),
+            scratch: None,
+            buffer_pool: BufferPool::shared().clone(),
+            frames: 0,
+            bytes: 0,
+            max_frames: DEFAULT_MAX_FRAMES,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_delay: None,
+            first_pushed_at: None,
+        }
+    }
+
+    // Writes once this many frames are pending, 32 by default
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames.max(1);
+        self
+    }
+
+    // Writes once this many encoded bytes are pending, 64 KiB by default
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    // Longest a frame waits for others to join it, like Nagle's algorithm. Only checked on push
+    // and by flush_when_due, unset by default
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    // Leases the buffers headers are encoded into from `pool` instead of the shared one
+    pub fn buffer_pool(mut self, pool: &BufferPool) -> Self {
+        self.buffer_pool = pool.clone();
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    // Frames that weren't flushed are dropped
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub fn pending_frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // When the pending frames have to be written by, `None` without max_delay or frames
+    pub fn deadline(&self) -> Option<Instant> {
+        Some(self.first_pushed_at? + self.max_delay?)
+    }
+
+    // Encodes the frame into the batch and writes the batch out if that fires a trigger. A frame
+    // that fails to encode leaves the batch as it was
+    pub async fn push(&mut self, frame: Frame) -> Result<(), WireError> {
+        let scratch = self.scratch.get_or_insert_with(|| self.buffer_pool.lease());
+
+        self.bytes += self.codec.encode_chunks(frame, scratch, &mut self.chunks)?;
+        self.frames += 1;
+
+        let now = Instant::now();
+        let first_pushed_at = *self.first_pushed_at.get_or_insert(now);
+
+        let due = self
+            .max_delay
+            .is_some_and(|max_delay| now >= first_pushed_at + max_delay);
+
+        if due || self.frames >= self.max_frames || self.bytes >= self.max_bytes {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    // Writes every pending frame and flushes the writer. Cancelling it leaves the frames that
+    // weren't written yet pending
+    pub async fn flush(&mut self) -> Result<(), WireError> {
+        if let Some(scratch) = self.scratch.as_mut().filter(|scratch| !scratch.is_empty()) {
+            self.chunks.push_back(scratch.split().freeze());
+        }
+
+        while !self.chunks.is_empty() {
+            let mut slices = [IoSlice::new(&[]); MAX_IO_SLICES];
+            let mut count = 0;
+
+            for (slice, chunk) in slices.iter_mut().zip(&self.chunks) {
+                *slice = IoSlice::new(chunk);
+                count += 1;
+            }
+
+            let written = self.writer.write_vectored(&slices[..count]).await?;
+
+            if written == 0 {
+                return Err(WireError::Io(io::ErrorKind::WriteZero.into()));
+            }
+
+            self.advance(written);
+        }
+
+        self.frames = 0;
+        self.bytes = 0;
+        self.first_pushed_at = None;
+
+        // Nothing shares the buffer anymore, it goes back to the pool whole
+        self.scratch = None;
+
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+
+    // Waits for the deadline and flushes, never finishes while nothing is due. Meant to be raced
+    // against the source of the frames, e.g. in tokio::select!, so the last frames of a burst go
+    // out after max_delay
+    pub async fn flush_when_due(&mut self) -> Result<(), WireError> {
+        match self.deadline() {
+            Some(deadline) => {
+                tokio::time::sleep_until(deadline).await;
+
+                self.flush().await
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    // Drops written bytes from the front, the counters are only reset once everything is out
+    fn advance(&mut self, mut written: usize) {
+        while written > 0 {
+            let Some(chunk) = self.chunks.front_mut() else {
+                return;
+            };
+
+            if written < chunk.len() {
+                chunk.advance(written);
+
+                return;
+            }
+
+            written -= chunk.len();
+            self.chunks.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::FrameChecksum, BytesMut, Message};
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio_util::codec::Encoder;
+
+    // Takes at most `max_write` bytes per write, cutting through headers and payloads
+    #[derive(Default)]
+    struct Trickle {
+        written: Vec<u8>,
+        max_write: usize,
+        writes: usize,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            context: &mut Context<'_>,
+            buffer: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.poll_write_vectored(context, &[IoSlice::new(buffer)])
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            _context: &mut Context<'_>,
+            slices: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            let mut left = this.max_write;
+
+            for slice in slices {
+                let taken = slice.len().min(left);
+                this.written.extend_from_slice(&slice[..taken]);
+                left -= taken;
+            }
+
+            this.writes += 1;
+
+            Poll::Ready(Ok(this.max_write - left))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _context: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _context: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn frame(code: u32, payload_length: usize) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::from(vec![code as u8; payload_length]),
+        }
+    }
+
+    fn trickle(max_write: usize) -> Trickle {
+        Trickle {
+            max_write,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_once_max_frames_are_pending() {
+        let mut batch = FrameBatch::new(trickle(usize::MAX), FrameCodec::default()).max_frames(3);
+
+        batch.push(frame(1, 4)).await.unwrap();
+        batch.push(frame(2, 0)).await.unwrap();
+
+        assert_eq!(batch.pending_frames(), 2);
+        // [u8 message] | [u16 length] each
+        assert_eq!(batch.pending_bytes(), 3 + 4 + 3);
+        assert!(batch.get_ref().written.is_empty());
+
+        batch.push(frame(3, 4)).await.unwrap();
+
+        assert_eq!(batch.pending_frames(), 0);
+        assert_eq!(batch.get_ref().written.len(), 3 + 4 + 3 + 3 + 4);
+        assert_eq!(batch.get_ref().writes, 1);
+    }
+
+    #[tokio::test]
+    async fn writes_once_max_bytes_are_pending() {
+        let mut batch = FrameBatch::new(trickle(usize::MAX), FrameCodec::default()).max_bytes(100);
+
+        batch.push(frame(1, 40)).await.unwrap();
+        batch.push(frame(2, 40)).await.unwrap();
+        assert_eq!(batch.pending_bytes(), 86);
+
+        batch.push(frame(3, 40)).await.unwrap();
+
+        assert_eq!(batch.pending_bytes(), 0);
+        assert_eq!(batch.get_ref().written.len(), 129);
+    }
+
+    #[tokio::test]
+    async fn writes_once_the_oldest_frame_waited_max_delay() {
+        let max_delay = Duration::from_millis(20);
+        let mut batch =
+            FrameBatch::new(trickle(usize::MAX), FrameCodec::default()).max_delay(max_delay);
+
+        assert!(batch.deadline().is_none());
+
+        batch.push(frame(1, 4)).await.unwrap();
+        let deadline = batch.deadline().unwrap();
+
+        // A later push doesn't move the deadline
+        batch.push(frame(2, 4)).await.unwrap();
+        assert_eq!(batch.deadline(), Some(deadline));
+        assert_eq!(batch.pending_frames(), 2);
+
+        tokio::time::sleep_until(deadline).await;
+        batch.push(frame(3, 4)).await.unwrap();
+
+        assert_eq!(batch.pending_frames(), 0);
+        assert!(batch.deadline().is_none());
+
+        // The last frame of a burst goes out through flush_when_due
+        batch.push(frame(4, 4)).await.unwrap();
+        batch.flush_when_due().await.unwrap();
+
+        assert_eq!(batch.pending_frames(), 0);
+        assert_eq!(batch.get_ref().written.len(), 4 * (3 + 4));
+    }
+
+    #[tokio::test]
+    async fn writes_what_the_encoder_does_through_partial_writes() {
+        let codec = FrameCodec::builder()
+            .checksum(FrameChecksum::Crc32c)
+            .build()
+            .unwrap();
+        // Past MAX_IO_SLICES chunks, empty payloads share their chunk with the next header
+        let frames: Vec<Frame> = (0..50)
+            .map(|code| frame(code, (code as usize % 3) * 7))
+            .collect();
+
+        let mut expected = BytesMut::new();
+        for frame in frames.clone() {
+            codec.clone().encode(frame, &mut expected).unwrap();
+        }
+
+        for max_write in [1, 5, 64, usize::MAX] {
+            let mut batch = FrameBatch::new(trickle(max_write), codec).max_frames(usize::MAX);
+
+            for frame in frames.clone() {
+                batch.push(frame).await.unwrap();
+            }
+            batch.flush().await.unwrap();
+
+            assert_eq!(batch.into_inner().written, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_batch_when_a_frame_fails_to_encode() {
+        let codec = FrameCodec::builder()
+            .max_payload_length(10)
+            .build()
+            .unwrap();
+        let mut batch = FrameBatch::new(trickle(usize::MAX), codec);
+
+        batch.push(frame(1, 4)).await.unwrap();
+
+        assert!(matches!(
+            batch.push(frame(2, 11)).await,
+            Err(WireError::Oversized("payload_length", 11, 10))
+        ));
+        assert_eq!((batch.pending_frames(), batch.pending_bytes()), (1, 7));
+
+        batch.flush().await.unwrap();
+        assert_eq!(batch.get_ref().written.len(), 7);
+    }
+
+    #[tokio::test]
+    async fn returns_the_scratch_buffer_after_each_flush() {
+        let pool = BufferPool::new(4, 256);
+        let mut batch = FrameBatch::new(trickle(usize::MAX), FrameCodec::default())
+            .max_frames(usize::MAX)
+            .buffer_pool(&pool);
+
+        assert_eq!(pool.stats().misses, 0);
+
+        for _ in 0..3 {
+            for code in 1..=4 {
+                batch.push(frame(code, 16)).await.unwrap();
+            }
+
+            assert_eq!((pool.stats().leased, pool.stats().pooled), (1, 0));
+
+            batch.flush().await.unwrap();
+
+            assert_eq!((pool.stats().leased, pool.stats().pooled), (0, 1));
+        }
+
+        // Every batch after the first reuses the buffer the one before it gave back
+        let stats = pool.stats();
+
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert_eq!(batch.get_ref().written.len(), 3 * 4 * (3 + 16));
+
+        // Dropped with frames pending, it still gets the buffer back
+        batch.push(frame(1, 16)).await.unwrap();
+        drop(batch);
+
+        assert_eq!((pool.stats().leased, pool.stats().pooled), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn fails_writers_that_take_nothing() {
+        let mut batch = FrameBatch::new(trickle(0), FrameCodec::default());
+
+        batch.push(frame(1, 4)).await.unwrap();
+
+        assert!(matches!(
+            batch.flush().await,
+            Err(WireError::Io(error)) if error.kind() == io::ErrorKind::WriteZero
+        ));
+        assert_eq!(batch.pending_frames(), 1);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{
+    codec::{bytes::BufferPool, FrameCodec},
+    errors::WireError,
+    Bytes, BytesMut, Frame,
+};
+use quinn::{Connection, SendDatagramError};
+use tokio_util::codec::{Decoder, Encoder};
+
+// Frames over QUIC DATAGRAM frames, one frame per datagram. Datagrams are neither retransmitted
+// nor ordered, so a lost frame doesn't hold up the ones after it, e.g. for real-time audio.
+// Both sides have to use the same codec, frames never span datagrams
+pub struct DatagramTransport<C = FrameCodec> {
+    connection: Connection,
+    codec: C,
+    buffer_pool: BufferPool,
+}
+
+impl<C> DatagramTransport<C>
+where
+    C: Decoder<Item = Frame, Error = WireError> + Encoder<Frame, Error = WireError>,
+{
+    pub fn new(connection: Connection, codec: C) -> Self {
+        Self {
+            connection,
+            codec,
+            buffer_pool: BufferPool::shared().clone(),
+        }
+    }
+
+    // Leases the buffers frames are encoded into from `pool` instead of the shared one
+    pub fn buffer_pool(mut self, pool: &BufferPool) -> Self {
+        self.buffer_pool = pool.clone();
+        self
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    // Largest encoded frame the connection takes right now, it follows the path MTU and the
+    // peer's limit. `None` if datagrams can't be sent on it at all
+    pub fn max_frame_length(&self) -> Option<usize> {
+        self.connection.max_datagram_size()
+    }
+
+    // Queues the frame without waiting, the oldest queued datagrams are dropped when the send
+    // buffer is full
+    pub fn send(&mut self, frame: Frame) -> Result<(), WireError> {
+        let Some(max_datagram_length) = self.connection.max_datagram_size() else {
+            return Err(WireError::DatagramsUnavailable(
+                "disabled locally or by the peer",
+            ));
+        };
+
+        let mut codec_buffer = self.buffer_pool.lease();
+        self.codec.encode(frame, &mut codec_buffer)?;
+
+        let datagram_length = codec_buffer.len();
+
+        if datagram_length > max_datagram_length {
+            return Err(WireError::Oversized(
+                "datagram",
+                datagram_length,
+                max_datagram_length,
+            ));
+        }
+
+        // Copied out rather than split off, so the buffer goes back to the pool whole. quinn
+        // keeps queued datagrams around, a split would pin the lease's allocation until then
+        let datagram = Bytes::copy_from_slice(&codec_buffer);
+        drop(codec_buffer);
+
+        self.connection
+            .send_datagram(datagram)
+            .map_err(|error| match error {
+                SendDatagramError::UnsupportedByPeer => {
+                    WireError::DatagramsUnavailable("not supported by the peer")
+                }
+                SendDatagramError::Disabled => WireError::DatagramsUnavailable("disabled locally"),
+                // The limit shrank between the check above and the send
+                SendDatagramError::TooLarge => WireError::Oversized(
+                    "datagram",
+                    datagram_length,
+                    self.connection.max_datagram_size().unwrap_or(0),
+                ),
+                SendDatagramError::ConnectionLost(error) => WireError::Io(error.into()),
+            })
+    }
+
+    // Waits for the next datagram, it has to hold exactly one whole frame
+    pub async fn recv(&mut self) -> Result<Frame, WireError> {
+        let datagram = self
+            .connection
+            .read_datagram()
+            .await
+            .map_err(|error| WireError::Io(error.into()))?;
+
+        let mut source = BytesMut::from(datagram);
+
+        let Some(frame) = self.codec.decode(&mut source)? else {
+            return Err(WireError::MalformedDatagram("incomplete frame"));
+        };
+
+        if !source.is_empty() {
+            return Err(WireError::MalformedDatagram("bytes after the frame"));
+        }
+
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+    use quinn::{ClientConfig, Endpoint, ServerConfig, TransportConfig};
+    use rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer},
+        RootCertStore,
+    };
+    use std::sync::Arc;
+
+    // A loopback connection, the client side with `client_transport`
+    async fn connect(client_transport: TransportConfig) -> (Connection, Connection) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert_der: CertificateDer<'static> = certified.cert.der().clone();
+        let key_der = PrivateKeyDer::try_from(certified.signing_key.serialize_der()).unwrap();
+
+        let mut root_certs = RootCertStore::empty();
+        root_certs.add(cert_der.clone()).unwrap();
+
+        let server_config = ServerConfig::with_single_cert(vec![cert_der], key_der).unwrap();
+        let server = Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut client_config = ClientConfig::with_root_certificates(Arc::new(root_certs)).unwrap();
+        client_config.transport_config(Arc::new(client_transport));
+
+        let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_default_client_config(client_config);
+
+        let connecting = client
+            .connect(server.local_addr().unwrap(), "localhost")
+            .unwrap();
+
+        let (client, server) =
+            tokio::join!(connecting, async { server.accept().await.unwrap().await });
+
+        (client.unwrap(), server.unwrap())
+    }
+
+    fn frame(code: u32, payload: &[u8]) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    #[tokio::test]
+    async fn carries_one_frame_per_datagram_both_ways() {
+        let (client, server) = connect(TransportConfig::default()).await;
+        let mut client = DatagramTransport::new(client, FrameCodec::default());
+        let mut server = DatagramTransport::new(server, FrameCodec::default());
+
+        client.send(frame(1, b"first")).unwrap();
+        client.send(frame(2, b"second")).unwrap();
+
+        // Loopback doesn't drop or reorder them
+        for (code, payload) in [(1, &b"first"[..]), (2, &b"second"[..])] {
+            let received = server.recv().await.unwrap();
+
+            assert_eq!((received.message.0, &received.payload[..]), (code, payload));
+        }
+
+        server.send(frame(3, b"reply")).unwrap();
+
+        let received = client.recv().await.unwrap();
+        assert_eq!(
+            (received.message.0, &received.payload[..]),
+            (3, &b"reply"[..])
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_encode_buffers_to_the_pool() {
+        let (client, server) = connect(TransportConfig::default()).await;
+        let pool = BufferPool::new(4, 256);
+        let mut client = DatagramTransport::new(client, FrameCodec::default()).buffer_pool(&pool);
+        let mut server = DatagramTransport::new(server, FrameCodec::default());
+
+        for code in 1..=3 {
+            client.send(frame(code, &[code as u8; 100])).unwrap();
+        }
+
+        // Every send after the first reuses the buffer the one before it gave back
+        let stats = pool.stats();
+
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert_eq!((stats.leased, stats.pooled), (0, 1));
+
+        for code in 1..=3 {
+            let received = server.recv().await.unwrap();
+
+            assert_eq!(received.message.0, code);
+            assert_eq!(&received.payload[..], [code as u8; 100]);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_frames_past_the_datagram_limit() {
+        let (client, _server) = connect(TransportConfig::default()).await;
+        let codec = FrameCodec::builder()
+            .max_payload_length(4000)
+            .build()
+            .unwrap();
+        let mut client = DatagramTransport::new(client, codec);
+
+        let max_frame_length = client.max_frame_length().unwrap();
+        // [u8 message] | [u16 length]
+        let largest_payload = max_frame_length - 3;
+
+        client.send(frame(1, &vec![0; largest_payload])).unwrap();
+
+        assert!(matches!(
+            client.send(frame(1, &vec![0; largest_payload + 1])),
+            Err(WireError::Oversized("datagram", length, max))
+                if length == max_frame_length + 1 && max == max_frame_length
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_datagrams_that_arent_exactly_one_frame() {
+        let (client, server) = connect(TransportConfig::default()).await;
+        let mut server = DatagramTransport::new(server, FrameCodec::default());
+
+        // [u8 message] | [u16 length = 4] and only two payload bytes
+        client
+            .send_datagram(Bytes::from_static(&[1, 0, 4, 0, 0]))
+            .unwrap();
+
+        assert!(matches!(
+            server.recv().await,
+            Err(WireError::MalformedDatagram("incomplete frame"))
+        ));
+
+        client
+            .send_datagram(Bytes::from_static(&[1, 0, 1, 7, 0xFF]))
+            .unwrap();
+
+        assert!(matches!(
+            server.recv().await,
+            Err(WireError::MalformedDatagram("bytes after the frame"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_peers_that_dont_take_datagrams() {
+        let mut client_transport = TransportConfig::default();
+        client_transport.datagram_receive_buffer_size(None);
+
+        let (_client, server) = connect(client_transport).await;
+        let mut server = DatagramTransport::new(server, FrameCodec::default());
+
+        assert_eq!(server.max_frame_length(), None);
+        assert!(matches!(
+            server.send(frame(1, &[])),
+            Err(WireError::DatagramsUnavailable(_))
+        ));
+    }
+}
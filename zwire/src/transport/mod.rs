@@ -0,0 +1,19 @@
+mod batch;
+pub use batch::FrameBatch;
+
+#[cfg(feature = "quinn_integration")]
+mod datagram;
+#[cfg(feature = "quinn_integration")]
+pub use datagram::DatagramTransport;
+
+mod multiplex;
+pub use multiplex::{Channel, Multiplexer, Role};
+
+mod router;
+pub use router::{ErrorPolicy, RouteContext, Router};
+
+mod scheduler;
+pub use scheduler::{Priority, Scheduler, SchedulerBuilder, Scheduling, WhenFull};
+
+mod stream;
+pub use stream::ZwireStream;
@@ -0,0 +1,684 @@
+use crate::{
+    codec::{
+        bytes::{BytesMutPutExt, BytesMutTakeExt},
+        wired::{define_fields, VarInt, WiredInt},
+        ChannelCodec, ChannelId, FrameTransform,
+    },
+    errors::WireError,
+    BytesMut, DecodeFromFrame, EncodeIntoFrame, Frame, Message,
+};
+use futures::{
+    future::{self, Either},
+    stream::{self, BoxStream, SelectAll},
+    SinkExt, StreamExt, TryStreamExt,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{mpsc, Semaphore},
+};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+// Frames on the control channel, credits: [varint channel] | [varint credits] and closes:
+// [varint channel]
+define_fields! {
+    (Channel, WiredVarInt, fixed),
+    (Credits, WiredVarInt, fixed),
+}
+
+const CONTROL_CHANNEL: ChannelId = VarInt::MAX.into_inner();
+const CREDIT_MESSAGE: u32 = 1;
+const CLOSE_MESSAGE: u32 = 2;
+
+type OutboundStream = BoxStream<'static, (ChannelId, Frame)>;
+
+// Which end of the stream a Multiplexer is, each opens channels from its own half of the ids so
+// both can open at once without colliding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    // Opens odd ids
+    Initiator,
+    // Opens even ids
+    Acceptor,
+}
+
+impl Role {
+    fn first_id(self) -> ChannelId {
+        match self {
+            Role::Initiator => 1,
+            Role::Acceptor => 0,
+        }
+    }
+
+    fn owns(self, id: ChannelId) -> bool {
+        id % 2 == self.first_id()
+    }
+}
+
+struct ChannelState {
+    inbound: mpsc::Sender<Frame>,
+    // Queues the close behind the frames the channel already sent
+    outbound: mpsc::Sender<(ChannelId, Frame)>,
+    credits: Arc<Semaphore>,
+}
+
+struct Channels {
+    open: HashMap<ChannelId, ChannelState>,
+    // Closed here, frames still in flight for them are dropped until the peer confirms the close
+    closing: HashSet<ChannelId>,
+    next_id: ChannelId,
+}
+
+struct Shared {
+    channels: Mutex<Channels>,
+    registrations: mpsc::UnboundedSender<OutboundStream>,
+    control: mpsc::UnboundedSender<(ChannelId, Frame)>,
+    role: Role,
+    channel_capacity: usize,
+}
+
+fn control_frame(message: u32, id: ChannelId, credits: Option<usize>) -> Option<Frame> {
+    let channel = VarInt::from_u64(id).ok()?;
+
+    let mut payload = BytesMut::with_capacity(fields::MAX_LENGTH);
+    payload.put_single::<fields::channel::Wired>(channel);
+
+    if let Some(credits) = credits {
+        let credits = VarInt::from_u64(credits as u64).ok()?;
+        payload.put_single::<fields::credits::Wired>(credits);
+    }
+
+    Some(Frame {
+        message: Message(message),
+        payload: payload.freeze(),
+    })
+}
+
+impl Shared {
+    // The caller makes sure `id` isn't registered yet
+    fn register(self: &Arc<Self>, id: ChannelId) -> (Channel, ChannelState) {
+        let (inbound_sender, inbound) = mpsc::channel(self.channel_capacity);
+        // Credits bound the frames queued here, the extra slot is for the close
+        let (outbound, outbound_receiver) = mpsc::channel(self.channel_capacity + 1);
+        let credits = Arc::new(Semaphore::new(self.channel_capacity));
+
+        let outbound_stream = stream::unfold(outbound_receiver, move |mut receiver| async move {
+            let item = receiver.recv().await?;
+
+            Some((item, receiver))
+        });
+
+        // Only fails once the driver is gone, sends on the channel report it as closed then
+        let _ = self.registrations.send(outbound_stream.boxed());
+
+        let channel = Channel {
+            id,
+            outbound: outbound.clone(),
+            inbound,
+            credits: credits.clone(),
+            consumed: 0,
+            shared: self.clone(),
+        };
+
+        let state = ChannelState {
+            inbound: inbound_sender,
+            outbound,
+            credits,
+        };
+
+        (channel, state)
+    }
+
+    fn grant(&self, id: ChannelId, credits: usize) {
+        if let Some(frame) = control_frame(CREDIT_MESSAGE, id, Some(credits)) {
+            let _ = self.control.send((CONTROL_CHANNEL, frame));
+        }
+    }
+
+    fn accept_control(&self, frame: Frame) -> Result<(), WireError> {
+        let mut payload = frame.payload;
+        let payload_length = payload.len();
+
+        let Some(channel) = payload.take_single::<fields::channel::Wired>()? else {
+            return Err(WireError::Underflow(
+                "channel",
+                payload_length,
+                <fields::channel::Wired as WiredInt>::MIN_SIZE,
+            ));
+        };
+        let id = channel.into_inner();
+
+        match frame.message.0 {
+            CREDIT_MESSAGE => {
+                let Some(credits) = payload.take_single::<fields::credits::Wired>()? else {
+                    return Err(WireError::Underflow(
+                        "credits",
+                        payload_length,
+                        fields::FIXED_PART_LENGTH,
+                    ));
+                };
+
+                self.accept_credits(id, credits)
+            }
+            CLOSE_MESSAGE => {
+                self.accept_close(id);
+
+                Ok(())
+            }
+            code => Err(WireError::InvalidMessageType(code)),
+        }
+    }
+
+    fn accept_credits(&self, id: ChannelId, credits: VarInt) -> Result<(), WireError> {
+        let channels = self.channels.lock().unwrap();
+
+        // Credits for a channel that was closed meanwhile
+        let Some(state) = channels.open.get(&id) else {
+            return Ok(());
+        };
+
+        // A peer can only give back what it was sent
+        let credits = usize::try_from(credits.into_inner()).unwrap_or(usize::MAX);
+        let available = state.credits.available_permits().saturating_add(credits);
+
+        if available > self.channel_capacity {
+            return Err(WireError::Oversized(
+                "credits",
+                available,
+                self.channel_capacity,
+            ));
+        }
+
+        state.credits.add_permits(credits);
+
+        Ok(())
+    }
+
+    // The peer closed the channel or confirms a close from here. Either way it sends nothing
+    // more on it. Closes that aren't a confirmation are answered, so both ends forget the id
+    fn accept_close(&self, id: ChannelId) {
+        let mut channels = self.channels.lock().unwrap();
+
+        if channels.closing.remove(&id) {
+            return;
+        }
+
+        let Some(frame) = control_frame(CLOSE_MESSAGE, id, None) else {
+            return;
+        };
+
+        match channels.open.remove(&id) {
+            // Fails sends waiting for credits, frames that already arrived can still be read
+            Some(state) => {
+                state.credits.close();
+                let _ = state.outbound.try_send((CONTROL_CHANNEL, frame));
+            }
+            None => {
+                let _ = self.control.send((CONTROL_CHANNEL, frame));
+            }
+        }
+    }
+
+    fn close_channels(&self) {
+        let mut channels = self.channels.lock().unwrap();
+
+        for state in channels.open.values() {
+            state.credits.close();
+        }
+
+        channels.open.clear();
+    }
+}
+
+// Splits one byte stream into channels with credit based flow control, a channel can only have
+// `channel_capacity` frames in flight that its receiver hasn't consumed yet. A slow receiver
+// therefore only stalls senders on its own channel, never the shared stream. Both ends have to
+// use the same `channel_capacity` and opposite roles
+pub struct Multiplexer {
+    shared: Arc<Shared>,
+    incoming: mpsc::Receiver<Channel>,
+}
+
+impl Multiplexer {
+    // The returned driver has to be polled (e.g. spawned) for any frame to move. It finishes once
+    // the read side ends and every frame sent before was written, or once every Multiplexer and
+    // Channel handle is dropped
+    pub fn new<R, W, T>(
+        reader: R,
+        writer: W,
+        codec: ChannelCodec<T>,
+        role: Role,
+        channel_capacity: usize,
+    ) -> (Self, impl Future<Output = Result<(), WireError>>)
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+        T: FrameTransform + Clone,
+    {
+        let channel_capacity = channel_capacity.max(1);
+
+        let (registrations, registered) = mpsc::unbounded_channel();
+        let (control, control_receiver) = mpsc::unbounded_channel();
+        let (incoming_sender, incoming) = mpsc::channel(channel_capacity);
+
+        let shared = Arc::new(Shared {
+            channels: Mutex::new(Channels {
+                open: HashMap::new(),
+                closing: HashSet::new(),
+                next_id: role.first_id(),
+            }),
+            registrations,
+            control,
+            role,
+            channel_capacity,
+        });
+
+        let control_stream = stream::unfold(control_receiver, |mut receiver| async move {
+            let item = receiver.recv().await?;
+
+            Some((item, receiver))
+        });
+
+        let read = read_loop(
+            FramedRead::new(reader, codec.clone()),
+            Arc::downgrade(&shared),
+            incoming_sender,
+        );
+        let write = write_loop(
+            FramedWrite::new(writer, codec),
+            control_stream.boxed(),
+            registered,
+        );
+
+        let driver = async move {
+            match future::select(Box::pin(read), Box::pin(write)).await {
+                // The peer is done sending, still flush whatever is queued locally
+                Either::Left((read_result, write)) => {
+                    read_result?;
+                    write.await
+                }
+                Either::Right((write_result, _read)) => write_result,
+            }
+        };
+
+        (Self { shared, incoming }, driver)
+    }
+
+    // Ids aren't reused, the peer learns of the channel with its first frame
+    pub fn open(&self) -> Result<Channel, WireError> {
+        let mut channels = self.shared.channels.lock().unwrap();
+        let id = channels.next_id;
+
+        // Only once 2^61 channels were opened
+        if id >= CONTROL_CHANNEL {
+            return Err(WireError::ChannelInUse(CONTROL_CHANNEL));
+        }
+
+        channels.next_id += 2;
+
+        let (channel, state) = self.shared.register(id);
+        channels.open.insert(id, state);
+
+        Ok(channel)
+    }
+
+    // Channels opened by the peer, i.e. the first frame arrived on one of the peer's ids that
+    // isn't open here. Reading from the stream pauses while `channel_capacity` of them wait to
+    // be accepted
+    pub async fn accept(&mut self) -> Option<Channel> {
+        self.incoming.recv().await
+    }
+}
+
+pub struct Channel {
+    id: ChannelId,
+    outbound: mpsc::Sender<(ChannelId, Frame)>,
+    inbound: mpsc::Receiver<Frame>,
+    credits: Arc<Semaphore>,
+    consumed: usize,
+    shared: Arc<Shared>,
+}
+
+impl Channel {
+    pub fn id(&self) -> ChannelId {
+        self.id
+    }
+
+    // Waits until the peer has room for the frame on this channel, fails once either end closed
+    // it
+    pub async fn send(&self, frame: Frame) -> Result<(), WireError> {
+        self.credits
+            .acquire()
+            .await
+            .map_err(|_| WireError::ChannelClosed(self.id))?
+            .forget();
+
+        // Checked under the lock, so nothing is queued after the peer's close was answered
+        let channels = self.shared.channels.lock().unwrap();
+
+        if !channels.open.contains_key(&self.id) {
+            return Err(WireError::ChannelClosed(self.id));
+        }
+
+        self.outbound
+            .try_send((self.id, frame))
+            .map_err(|_| WireError::ChannelClosed(self.id))
+    }
+
+    // `None` once the peer closed the channel or its side of the stream ended
+    pub async fn recv(&mut self) -> Option<Frame> {
+        let frame = self.inbound.recv().await?;
+
+        // Credits go back in batches of half the capacity
+        self.consumed += 1;
+
+        if self.consumed >= (self.shared.channel_capacity / 2).max(1) {
+            self.shared.grant(self.id, self.consumed);
+            self.consumed = 0;
+        }
+
+        Some(frame)
+    }
+
+    pub async fn send_payload<C>(
+        &self,
+        codec: &mut C,
+        payload: C::EncodeItem,
+        message: impl Into<Message>,
+        codec_buffer: &mut BytesMut,
+    ) -> Result<(), WireError>
+    where
+        C: EncodeIntoFrame<Error = WireError>,
+    {
+        let frame = codec.encode_into_frame(payload, message, codec_buffer)?;
+
+        self.send(frame).await
+    }
+
+    pub async fn recv_payload<C>(
+        &mut self,
+        codec: &mut C,
+    ) -> Result<Option<(C::Item, Message)>, WireError>
+    where
+        C: DecodeFromFrame<Error = WireError>,
+    {
+        let frame = self.recv().await.ok_or(WireError::ChannelClosed(self.id))?;
+
+        codec.decode_from_frame(frame)
+    }
+}
+
+// Tells the peer, whose sends on the channel fail from then on
+impl Drop for Channel {
+    fn drop(&mut self) {
+        let Ok(mut channels) = self.shared.channels.lock() else {
+            return;
+        };
+
+        // Already gone if the peer closed it first
+        if channels.open.remove(&self.id).is_none() {
+            return;
+        }
+
+        if let Some(frame) = control_frame(CLOSE_MESSAGE, self.id, None) {
+            channels.closing.insert(self.id);
+
+            // Queued behind the channel's last frames
+            let _ = self.outbound.try_send((CONTROL_CHANNEL, frame));
+        }
+    }
+}
+
+async fn read_loop<R, T>(
+    mut framed: FramedRead<R, ChannelCodec<T>>,
+    shared: Weak<Shared>,
+    incoming: mpsc::Sender<Channel>,
+) -> Result<(), WireError>
+where
+    R: AsyncRead + Unpin,
+    T: FrameTransform,
+{
+    let result = async {
+        while let Some((id, frame)) = framed.try_next().await? {
+            let Some(shared) = shared.upgrade() else {
+                return Ok(());
+            };
+
+            if id == CONTROL_CHANNEL {
+                shared.accept_control(frame)?;
+
+                continue;
+            }
+
+            let (inbound, accepted) = {
+                let mut channels = shared.channels.lock().unwrap();
+
+                match channels.open.get(&id) {
+                    Some(state) => (state.inbound.clone(), None),
+                    // Late frames for a channel closed here, or one of ours that isn't open
+                    None if channels.closing.contains(&id) || shared.role.owns(id) => continue,
+                    None => {
+                        let (channel, state) = shared.register(id);
+                        let inbound = state.inbound.clone();
+                        channels.open.insert(id, state);
+
+                        (inbound, Some(channel))
+                    }
+                }
+            };
+
+            // Frames for channels nobody accepts anymore are dropped along with them
+            if let Some(channel) = accepted
+                && incoming.send(channel).await.is_err()
+            {
+                continue;
+            }
+
+            // The peer can't send more than the credits it got, a closed channel drops the frame
+            if let Err(mpsc::error::TrySendError::Full(_)) = inbound.try_send(frame) {
+                return Err(WireError::ChannelOverrun(id));
+            }
+        }
+
+        Ok(())
+    };
+
+    let result = result.await;
+
+    // Ends every channel's inbound side and fails senders waiting for credits
+    if let Some(shared) = shared.upgrade() {
+        shared.close_channels();
+    }
+
+    result
+}
+
+async fn write_loop<W, T>(
+    mut framed: FramedWrite<W, ChannelCodec<T>>,
+    control: OutboundStream,
+    mut registered: mpsc::UnboundedReceiver<OutboundStream>,
+) -> Result<(), WireError>
+where
+    W: AsyncWrite + Unpin,
+    T: FrameTransform,
+{
+    // Polls the channels in turn, a busy channel can't starve the others
+    let mut outbound: SelectAll<OutboundStream> = SelectAll::new();
+    outbound.push(control);
+
+    let mut registrations_open = true;
+
+    loop {
+        tokio::select! {
+            registration = registered.recv(), if registrations_open => match registration {
+                Some(stream) => outbound.push(stream),
+                None => registrations_open = false,
+            },
+            Some(item) = outbound.next(), if !outbound.is_empty() => {
+                framed.send(item).await?;
+            },
+            else => break,
+        }
+    }
+
+    framed.close().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bytes;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const CAPACITY: usize = 2;
+    const QUIET: Duration = Duration::from_millis(50);
+
+    fn pair() -> (Multiplexer, Multiplexer) {
+        let (initiator_stream, acceptor_stream) = tokio::io::duplex(64 * 1024);
+
+        let [initiator, acceptor] = [
+            (initiator_stream, Role::Initiator),
+            (acceptor_stream, Role::Acceptor),
+        ]
+        .map(|(stream, role)| {
+            let (reader, writer) = tokio::io::split(stream);
+            let (multiplexer, driver) =
+                Multiplexer::new(reader, writer, ChannelCodec::default(), role, CAPACITY);
+
+            tokio::spawn(driver);
+
+            multiplexer
+        });
+
+        (initiator, acceptor)
+    }
+
+    fn frame(code: u32) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::from(vec![code as u8; 4]),
+        }
+    }
+
+    #[tokio::test]
+    async fn opens_channels_and_carries_frames_both_ways() {
+        let (initiator, mut acceptor) = pair();
+
+        let mut outgoing = initiator.open().unwrap();
+        outgoing.send(frame(1)).await.unwrap();
+
+        let mut incoming = acceptor.accept().await.unwrap();
+        assert_eq!(incoming.id(), outgoing.id());
+        assert_eq!(incoming.recv().await.unwrap().payload, frame(1).payload);
+
+        incoming.send(frame(2)).await.unwrap();
+        assert_eq!(outgoing.recv().await.unwrap().message.0, 2);
+    }
+
+    #[tokio::test]
+    async fn opens_from_separate_id_spaces() {
+        let (initiator, mut acceptor) = pair();
+
+        let initiated = [initiator.open().unwrap(), initiator.open().unwrap()];
+        let accepted = [acceptor.open().unwrap(), acceptor.open().unwrap()];
+
+        assert_eq!(initiated.each_ref().map(Channel::id), [1, 3]);
+        assert_eq!(accepted.each_ref().map(Channel::id), [0, 2]);
+
+        // Both ends opening at once end up with two channels, not one
+        initiated[0].send(frame(1)).await.unwrap();
+        accepted[0].send(frame(2)).await.unwrap();
+
+        let mut incoming = acceptor.accept().await.unwrap();
+        assert_eq!(incoming.id(), 1);
+        assert_eq!(incoming.recv().await.unwrap().message.0, 1);
+    }
+
+    #[tokio::test]
+    async fn stalls_senders_without_credits() {
+        let (initiator, mut acceptor) = pair();
+        let outgoing = initiator.open().unwrap();
+
+        for code in 0..CAPACITY as u32 {
+            outgoing.send(frame(code)).await.unwrap();
+        }
+
+        assert!(timeout(QUIET, outgoing.send(frame(9))).await.is_err());
+
+        // Reading half the capacity gives that much back
+        let mut incoming = acceptor.accept().await.unwrap();
+        assert_eq!(incoming.recv().await.unwrap().message.0, 0);
+
+        timeout(QUIET, outgoing.send(frame(9)))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(incoming.recv().await.unwrap().message.0, 1);
+        assert_eq!(incoming.recv().await.unwrap().message.0, 9);
+    }
+
+    #[tokio::test]
+    async fn closing_fails_the_peers_pending_sends() {
+        let (initiator, mut acceptor) = pair();
+        let mut outgoing = initiator.open().unwrap();
+
+        for code in 0..CAPACITY as u32 {
+            outgoing.send(frame(code)).await.unwrap();
+        }
+
+        let incoming = acceptor.accept().await.unwrap();
+        let id = incoming.id();
+        let pending = outgoing.send(frame(9));
+
+        drop(incoming);
+
+        assert!(matches!(
+            timeout(QUIET * 4, pending).await.unwrap(),
+            Err(WireError::ChannelClosed(closed)) if closed == id
+        ));
+        assert!(matches!(
+            outgoing.send(frame(9)).await,
+            Err(WireError::ChannelClosed(_))
+        ));
+        assert!(outgoing.recv().await.is_none());
+
+        // Both ends forgot the id once the close was answered
+        tokio::time::sleep(QUIET).await;
+
+        assert!(!initiator
+            .shared
+            .channels
+            .lock()
+            .unwrap()
+            .closing
+            .contains(&id));
+        assert!(!acceptor
+            .shared
+            .channels
+            .lock()
+            .unwrap()
+            .closing
+            .contains(&id));
+    }
+
+    #[tokio::test]
+    async fn drops_frames_for_channels_closed_here() {
+        let (initiator, mut acceptor) = pair();
+        let outgoing = initiator.open().unwrap();
+
+        outgoing.send(frame(1)).await.unwrap();
+        drop(acceptor.accept().await.unwrap());
+
+        // In flight while the close travels, they must not reopen the channel
+        let _ = outgoing.send(frame(2)).await;
+        let _ = outgoing.send(frame(3)).await;
+
+        assert!(timeout(QUIET, acceptor.accept()).await.is_err());
+    }
+}
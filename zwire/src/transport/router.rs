@@ -0,0 +1,471 @@
+use crate::{
+    codec::wired::{WiredPayload, WiredRecord},
+    errors::WireError,
+    session::{ConnectionId, Session, SessionBackend, SessionManager},
+    BufDecoder, Frame, Message,
+};
+use futures::{future::BoxFuture, FutureExt, SinkExt, TryStreamExt};
+use std::{collections::HashMap, future::Future, sync::Arc};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+use tracing::warn;
+
+// Handlers answer with the frame to send back, if any
+type Handler<B> = Arc<
+    dyn Fn(RouteContext<B>, Frame) -> BoxFuture<'static, Result<Option<Frame>, WireError>>
+        + Send
+        + Sync,
+>;
+
+// What a failing route does to its connection, decoding a frame's payload counts as part of
+// the route
+#[derive(Debug, Clone)]
+pub enum ErrorPolicy {
+    // Skips the frame and goes on with the next one
+    Drop,
+    // Sends the frame back and goes on with the next one
+    Reply(Frame),
+    // Ends `serve` with the error
+    Close,
+}
+
+#[derive(Clone)]
+pub struct RouteContext<B: SessionBackend> {
+    pub connection_id: ConnectionId,
+    pub sessions: SessionManager<B>,
+}
+
+impl<B: SessionBackend> RouteContext<B> {
+    pub fn with_session<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&Session) -> R,
+    {
+        self.sessions.with_session(self.connection_id, f)
+    }
+
+    pub fn with_session_mut<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Session) -> R,
+    {
+        self.sessions.with_session_mut(self.connection_id, f)
+    }
+}
+
+struct Route<B: SessionBackend> {
+    handler: Handler<B>,
+    policy: ErrorPolicy,
+}
+
+// Dispatches a connection's frames to handlers by message code. Registering a code twice replaces
+// the earlier route, codes without one go to the fallback or are skipped
+pub struct Router<B: SessionBackend> {
+    sessions: SessionManager<B>,
+    routes: HashMap<u32, Route<B>>,
+    fallback: Option<Route<B>>,
+}
+
+impl<B: SessionBackend> Router<B> {
+    pub fn new(sessions: SessionManager<B>) -> Self {
+        Self {
+            sessions,
+            routes: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    pub fn route<H, F>(
+        &mut self,
+        message: impl Into<Message>,
+        policy: ErrorPolicy,
+        handler: H,
+    ) -> &mut Self
+    where
+        H: Fn(RouteContext<B>, Frame) -> F + Send + Sync + 'static,
+        F: Future<Output = Result<Option<Frame>, WireError>> + Send + 'static,
+    {
+        let route = Route {
+            handler: Arc::new(move |context, frame| handler(context, frame).boxed()),
+            policy,
+        };
+
+        self.routes.insert(message.into().0, route);
+
+        self
+    }
+
+    // The payload is decoded into `P` through its derived codec before the handler sees it
+    pub fn route_payload<P, H, F>(
+        &mut self,
+        message: impl Into<Message>,
+        policy: ErrorPolicy,
+        handler: H,
+    ) -> &mut Self
+    where
+        P: WiredPayload + Send + 'static,
+        H: Fn(RouteContext<B>, P) -> F + Send + Sync + 'static,
+        F: Future<Output = Result<Option<Frame>, WireError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+
+        self.route(message, policy, move |context, frame| {
+            let handler = handler.clone();
+
+            async move {
+                let mut payload = frame.payload;
+                let payload_length = payload.len();
+
+                let Some(record) = P::Codec::default().decode_buf(&mut payload)? else {
+                    return Err(WireError::Underflow(
+                        "payload",
+                        payload_length,
+                        P::Codec::FIXED_PART_LENGTH,
+                    ));
+                };
+
+                handler(context, record).await
+            }
+        })
+    }
+
+    // Every message of a define_message! enum, e.g. `AuthMessage::ALL`, goes to one handler as
+    // its protocol enum
+    pub fn route_protocol<M, P, H, F>(
+        &mut self,
+        messages: impl IntoIterator<Item = M>,
+        policy: ErrorPolicy,
+        handler: H,
+    ) -> &mut Self
+    where
+        M: Into<Message>,
+        P: TryFrom<Frame, Error = WireError> + Send + 'static,
+        H: Fn(RouteContext<B>, P) -> F + Send + Sync + 'static,
+        F: Future<Output = Result<Option<Frame>, WireError>> + Send + 'static,
+    {
+        let handler: Handler<B> = Arc::new(move |context, frame| match P::try_from(frame) {
+            Ok(message) => handler(context, message).boxed(),
+            Err(error) => futures::future::ready(Err(error)).boxed(),
+        });
+
+        for message in messages {
+            let route = Route {
+                handler: handler.clone(),
+                policy: policy.clone(),
+            };
+
+            self.routes.insert(message.into().0, route);
+        }
+
+        self
+    }
+
+    // Frames with a code no route was registered for
+    pub fn fallback<H, F>(&mut self, policy: ErrorPolicy, handler: H) -> &mut Self
+    where
+        H: Fn(RouteContext<B>, Frame) -> F + Send + Sync + 'static,
+        F: Future<Output = Result<Option<Frame>, WireError>> + Send + 'static,
+    {
+        self.fallback = Some(Route {
+            handler: Arc::new(move |context, frame| handler(context, frame).boxed()),
+            policy,
+        });
+
+        self
+    }
+
+    // Runs until the peer stops sending or a route with ErrorPolicy::Close fails. Frames are
+    // handled one at a time in the order they arrived, a reply goes out before the next frame
+    // is read
+    pub async fn serve<R, W, C>(
+        &self,
+        connection_id: ConnectionId,
+        reader: R,
+        writer: W,
+        codec: C,
+    ) -> Result<(), WireError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+        C: Decoder<Item = Frame, Error = WireError> + Encoder<Frame, Error = WireError> + Clone,
+    {
+        let mut frames = FramedRead::new(reader, codec.clone());
+        let mut replies = FramedWrite::new(writer, codec);
+
+        while let Some(frame) = frames.try_next().await? {
+            let message = frame.message.0;
+
+            let Some(route) = self.routes.get(&message).or(self.fallback.as_ref()) else {
+                continue;
+            };
+
+            let context = RouteContext {
+                connection_id,
+                sessions: self.sessions.clone(),
+            };
+
+            let reply = match (route.handler)(context, frame).await {
+                Ok(reply) => reply,
+                Err(error) => match &route.policy {
+                    ErrorPolicy::Drop => {
+                        warn!("{:#?} | dropped frame with message {}", error, message);
+
+                        None
+                    }
+                    ErrorPolicy::Reply(reply) => Some(reply.clone()),
+                    ErrorPolicy::Close => return Err(error),
+                },
+            };
+
+            if let Some(reply) = reply {
+                replies.send(reply).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec::{
+            wired::{define_message, WireCodec},
+            FrameCodec,
+        },
+        session::SimpleSessionBackend,
+        Bytes, BytesMut,
+    };
+
+    const CONNECTION: ConnectionId = 7;
+
+    #[derive(Debug, Clone, WireCodec)]
+    struct Ping {
+        #[wire(u32, fixed)]
+        sequence: u32,
+    }
+
+    define_message!(
+        Chat => ChatProtocol,
+        {
+            Hello = 20,
+            Bye = 21,
+            Other(_),
+        }
+    );
+
+    fn frame(code: u32, payload: &[u8]) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    fn router() -> Router<SimpleSessionBackend> {
+        let sessions = SessionManager::new(SimpleSessionBackend::new());
+        sessions.create(CONNECTION);
+
+        Router::new(sessions)
+    }
+
+    // Feeds the frames through `serve` and gives back what it ended with and the replies it sent
+    async fn serve(
+        router: &Router<SimpleSessionBackend>,
+        frames: Vec<Frame>,
+    ) -> (Result<(), WireError>, Vec<Frame>) {
+        let mut codec = FrameCodec::default();
+        let mut input = BytesMut::new();
+
+        for frame in frames {
+            codec.encode(frame, &mut input).unwrap();
+        }
+
+        let mut output = Vec::new();
+        let result = router
+            .serve(CONNECTION, &input[..], &mut output, codec)
+            .await;
+
+        let mut output = BytesMut::from(&output[..]);
+        let mut replies = Vec::new();
+
+        while let Some(reply) = codec.decode(&mut output).unwrap() {
+            replies.push(reply);
+        }
+
+        (result, replies)
+    }
+
+    fn codes(frames: &[Frame]) -> Vec<u32> {
+        frames.iter().map(|frame| frame.message.0).collect()
+    }
+
+    #[tokio::test]
+    async fn dispatches_by_code_and_skips_unrouted_frames() {
+        let mut router = router();
+        router
+            .route(Message(1), ErrorPolicy::Close, |_, frame| async move {
+                Ok(Some(Frame {
+                    message: Message(101),
+                    payload: frame.payload,
+                }))
+            })
+            .route(Message(2), ErrorPolicy::Close, |_, _| async { Ok(None) })
+            .route(Message(3), ErrorPolicy::Close, |_, _| async {
+                Ok(Some(frame(103, &[])))
+            });
+
+        let (result, replies) = serve(
+            &router,
+            vec![
+                frame(1, b"a"),
+                frame(9, &[]),
+                frame(2, &[]),
+                frame(3, &[]),
+                frame(1, b"b"),
+            ],
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(codes(&replies), [101, 103, 101]);
+        assert_eq!(replies[0].payload, &b"a"[..]);
+        assert_eq!(replies[2].payload, &b"b"[..]);
+    }
+
+    #[tokio::test]
+    async fn sends_unrouted_frames_to_the_fallback() {
+        let mut router = router();
+        router
+            .route(Message(1), ErrorPolicy::Close, |_, _| async {
+                Ok(Some(frame(101, &[])))
+            })
+            .fallback(ErrorPolicy::Close, |_, frame| async move {
+                Ok(Some(Frame {
+                    message: Message(frame.message.0 + 200),
+                    payload: frame.payload,
+                }))
+            });
+
+        let (result, replies) = serve(&router, vec![frame(5, &[]), frame(1, &[])]).await;
+
+        assert!(result.is_ok());
+        assert_eq!(codes(&replies), [205, 101]);
+    }
+
+    #[tokio::test]
+    async fn replaces_routes_registered_twice() {
+        let mut router = router();
+        router
+            .route(Message(1), ErrorPolicy::Close, |_, _| async {
+                Ok(Some(frame(101, &[])))
+            })
+            .route(Message(1), ErrorPolicy::Close, |_, _| async {
+                Ok(Some(frame(111, &[])))
+            });
+
+        let (_, replies) = serve(&router, vec![frame(1, &[])]).await;
+
+        assert_eq!(codes(&replies), [111]);
+    }
+
+    #[tokio::test]
+    async fn applies_the_failing_route_policy() {
+        let failing = |_, _| async { Err::<Option<Frame>, _>(WireError::Truncated("test")) };
+
+        let mut router = router();
+        router
+            .route(Message(1), ErrorPolicy::Drop, failing)
+            .route(Message(2), ErrorPolicy::Reply(frame(102, b"no")), failing)
+            .route(Message(3), ErrorPolicy::Close, failing)
+            .route(Message(4), ErrorPolicy::Close, |_, _| async {
+                Ok(Some(frame(104, &[])))
+            });
+
+        let (result, replies) = serve(
+            &router,
+            vec![
+                frame(1, &[]),
+                frame(4, &[]),
+                frame(2, &[]),
+                frame(3, &[]),
+                frame(4, &[]),
+            ],
+        )
+        .await;
+
+        // Close ends serve, the frame after it is never read
+        assert!(matches!(result, Err(WireError::Truncated("test"))));
+        assert_eq!(codes(&replies), [104, 102]);
+        assert_eq!(replies[1].payload, &b"no"[..]);
+    }
+
+    #[tokio::test]
+    async fn decodes_payloads_and_protocols_before_the_handler() {
+        let mut router = router();
+        router
+            .route_payload(
+                Message(1),
+                ErrorPolicy::Reply(frame(100, &[])),
+                |_, ping: Ping| async move { Ok(Some(frame(ping.sequence + 10, &[]))) },
+            )
+            .route_protocol(
+                Chat::ALL,
+                ErrorPolicy::Close,
+                |_, message: ChatProtocol| async move {
+                    let code = match message {
+                        ChatProtocol::Hello => 120,
+                        ChatProtocol::Bye => 121,
+                        ChatProtocol::Other(code, _) => code.into(),
+                    };
+
+                    Ok(Some(frame(code, &[])))
+                },
+            );
+
+        let mut ping = BytesMut::new();
+        PingCodec::default()
+            .encode(Ping { sequence: 5 }, &mut ping)
+            .unwrap();
+
+        let (result, replies) = serve(
+            &router,
+            vec![
+                frame(1, &ping),
+                // Too short for a Ping, the route's policy answers
+                frame(1, &[0]),
+                frame(21, &[]),
+                frame(20, &[]),
+            ],
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(codes(&replies), [15, 100, 121, 120]);
+    }
+
+    #[tokio::test]
+    async fn hands_routes_the_connection_session() {
+        let mut router = router();
+        router.route(Message(1), ErrorPolicy::Close, |context, _| async move {
+            let count = context
+                .with_session_mut(|session| {
+                    let count = session.get::<u32>().copied().unwrap_or(0) + 1;
+                    session.insert(count);
+
+                    count
+                })
+                .unwrap();
+
+            Ok(Some(frame(count, &[])))
+        });
+
+        let (_, replies) = serve(&router, vec![frame(1, &[]), frame(1, &[]), frame(1, &[])]).await;
+
+        assert_eq!(codes(&replies), [1, 2, 3]);
+        assert_eq!(
+            router
+                .sessions
+                .with_session(CONNECTION, |session| session.get::<u32>().copied()),
+            Some(Some(3))
+        );
+    }
+}
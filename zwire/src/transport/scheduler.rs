@@ -0,0 +1,537 @@
+use crate::{errors::WireError, Frame};
+use futures::SinkExt;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use tokio::{io::AsyncWrite, sync::Notify};
+use tokio_util::codec::{Encoder, FramedWrite};
+
+const CLASSES: usize = 3;
+
+// Ordered from most to least urgent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Priority {
+    // Handshakes, acks, session control, small and latency sensitive
+    Control,
+    // Live audio and the like, late frames are worth little
+    RealTime,
+    // Transfers that only care about throughput
+    Bulk,
+}
+
+impl Priority {
+    pub const ALL: [Priority; CLASSES] = [Priority::Control, Priority::RealTime, Priority::Bulk];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Control => "control",
+            Priority::RealTime => "real-time",
+            Priority::Bulk => "bulk",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduling {
+    // Always writes the most urgent queued frame, bulk only moves while nothing else is queued
+    Strict,
+    // Deficit round robin, every round a class may write up to its quantum of payload bytes,
+    // indexed by Priority. Quanta of at least one frame's size keep the rounds short
+    Weighted([usize; CLASSES]),
+}
+
+// What sending to a full queue does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhenFull {
+    // Waits until the driver made room
+    Await,
+    // Drops the frame being sent
+    DropNewest,
+    // Drops the longest queued frame to make room
+    DropOldest,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Class {
+    capacity: usize,
+    when_full: WhenFull,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SchedulerBuilder {
+    classes: [Class; CLASSES],
+    scheduling: Scheduling,
+}
+
+impl Default for SchedulerBuilder {
+    fn default() -> Self {
+        SchedulerBuilder {
+            classes: [
+                Class {
+                    capacity: 64,
+                    when_full: WhenFull::Await,
+                },
+                Class {
+                    capacity: 32,
+                    when_full: WhenFull::DropOldest,
+                },
+                Class {
+                    capacity: 256,
+                    when_full: WhenFull::Await,
+                },
+            ],
+            scheduling: Scheduling::Strict,
+        }
+    }
+}
+
+impl SchedulerBuilder {
+    // Frames the class queues before WhenFull kicks in, at least 1
+    pub fn capacity(mut self, priority: Priority, capacity: usize) -> Self {
+        self.classes[priority.index()].capacity = capacity.max(1);
+        self
+    }
+
+    pub fn when_full(mut self, priority: Priority, when_full: WhenFull) -> Self {
+        self.classes[priority.index()].when_full = when_full;
+        self
+    }
+
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    // The returned driver has to be polled (e.g. spawned) for any frame to be written. It
+    // finishes once every Scheduler handle is dropped and the queues are written out, or with
+    // the first write error
+    pub fn build<W, C>(
+        self,
+        writer: W,
+        codec: C,
+    ) -> (Scheduler, impl Future<Output = Result<(), WireError>>)
+    where
+        W: AsyncWrite + Unpin,
+        C: Encoder<Frame, Error = WireError>,
+    {
+        let mut quanta = [0; CLASSES];
+
+        if let Scheduling::Weighted(weights) = self.scheduling {
+            for (quantum, weight) in quanta.iter_mut().zip(weights) {
+                *quantum = weight.max(1);
+            }
+        }
+
+        let shared = Arc::new(Shared {
+            queues: Mutex::new(Queues {
+                frames: Default::default(),
+                deficits: [0; CLASSES],
+                cursor: 0,
+                topped_up: false,
+                senders: 1,
+                closed: false,
+            }),
+            classes: self.classes,
+            weighted: matches!(self.scheduling, Scheduling::Weighted(_)),
+            quanta,
+            queued: Notify::new(),
+            room: Default::default(),
+        });
+
+        let driver = write_loop(FramedWrite::new(writer, codec), shared.clone());
+
+        (Scheduler { shared }, driver)
+    }
+}
+
+struct Queues {
+    frames: [VecDeque<Frame>; CLASSES],
+    deficits: [usize; CLASSES],
+    cursor: usize,
+    // Whether the class under the cursor got its quantum this round
+    topped_up: bool,
+    senders: usize,
+    closed: bool,
+}
+
+impl Queues {
+    fn is_empty(&self) -> bool {
+        self.frames.iter().all(VecDeque::is_empty)
+    }
+
+    fn next_strict(&mut self) -> Option<(usize, Frame)> {
+        self.frames
+            .iter_mut()
+            .enumerate()
+            .find_map(|(class, frames)| Some((class, frames.pop_front()?)))
+    }
+
+    fn next_weighted(&mut self, quanta: &[usize; CLASSES]) -> Option<(usize, Frame)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        loop {
+            let class = self.cursor;
+            let frames = &mut self.frames[class];
+
+            match frames.front().map(|frame| frame.payload.len()) {
+                // Idle classes don't save up for later
+                None => {
+                    self.deficits[class] = 0;
+                    self.advance();
+                }
+                Some(cost) if cost <= self.deficits[class] => {
+                    self.deficits[class] -= cost;
+
+                    return Some((class, frames.pop_front()?));
+                }
+                Some(_) if !self.topped_up => {
+                    self.deficits[class] = self.deficits[class].saturating_add(quanta[class]);
+                    self.topped_up = true;
+                }
+                Some(_) => self.advance(),
+            }
+        }
+    }
+
+    fn advance(&mut self) {
+        self.cursor = (self.cursor + 1) % CLASSES;
+        self.topped_up = false;
+    }
+}
+
+struct Shared {
+    queues: Mutex<Queues>,
+    classes: [Class; CLASSES],
+    weighted: bool,
+    quanta: [usize; CLASSES],
+    // Wakes the driver after a frame was queued or the last handle dropped
+    queued: Notify,
+    // Wakes senders waiting on a full class after the driver took one of its frames
+    room: [Notify; CLASSES],
+}
+
+impl Shared {
+    fn close(&self) {
+        self.queues.lock().unwrap().closed = true;
+
+        for room in &self.room {
+            room.notify_waiters();
+        }
+    }
+}
+
+// Queues frames by priority class for a single writer, so urgent frames don't wait behind bulk
+// ones that were sent earlier. Frames within a class keep their order. Clones share the queues
+pub struct Scheduler {
+    shared: Arc<Shared>,
+}
+
+impl Scheduler {
+    pub fn builder() -> SchedulerBuilder {
+        SchedulerBuilder::default()
+    }
+
+    // Gives back the frame that was dropped to make room, the sent one under WhenFull::DropNewest
+    // or the oldest queued one under WhenFull::DropOldest
+    pub async fn send(&self, priority: Priority, frame: Frame) -> Result<Option<Frame>, WireError> {
+        let class = priority.index();
+        let Class {
+            capacity,
+            when_full,
+        } = self.shared.classes[class];
+
+        loop {
+            // Registered before checking, so room made in between isn't missed
+            let room = self.shared.room[class].notified();
+
+            {
+                let mut queues = self.shared.queues.lock().unwrap();
+
+                if queues.closed {
+                    return Err(WireError::QueueClosed(priority.name()));
+                }
+
+                let frames = &mut queues.frames[class];
+
+                let dropped = if frames.len() < capacity {
+                    None
+                } else {
+                    match when_full {
+                        WhenFull::Await => None,
+                        WhenFull::DropNewest => return Ok(Some(frame)),
+                        WhenFull::DropOldest => frames.pop_front(),
+                    }
+                };
+
+                if frames.len() < capacity {
+                    frames.push_back(frame);
+                    drop(queues);
+
+                    self.shared.queued.notify_one();
+
+                    return Ok(dropped);
+                }
+            }
+
+            room.await;
+        }
+    }
+
+    // Frames waiting in the priority's queue
+    pub fn queued(&self, priority: Priority) -> usize {
+        self.shared.queues.lock().unwrap().frames[priority.index()].len()
+    }
+}
+
+impl Clone for Scheduler {
+    fn clone(&self) -> Self {
+        self.shared.queues.lock().unwrap().senders += 1;
+
+        Scheduler {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        if let Ok(mut queues) = self.shared.queues.lock() {
+            queues.senders -= 1;
+        }
+
+        self.shared.queued.notify_one();
+    }
+}
+
+async fn write_loop<W, C>(
+    mut framed: FramedWrite<W, C>,
+    shared: Arc<Shared>,
+) -> Result<(), WireError>
+where
+    W: AsyncWrite + Unpin,
+    C: Encoder<Frame, Error = WireError>,
+{
+    let result = async {
+        loop {
+            let queued = shared.queued.notified();
+
+            let (next, senders) = {
+                let mut queues = shared.queues.lock().unwrap();
+
+                let next = if shared.weighted {
+                    queues.next_weighted(&shared.quanta)
+                } else {
+                    queues.next_strict()
+                };
+
+                (next, queues.senders)
+            };
+
+            match next {
+                Some((class, frame)) => {
+                    shared.room[class].notify_waiters();
+
+                    // Buffered until the queues run dry, FramedWrite still writes out once its
+                    // buffer grows past the backpressure boundary
+                    framed.feed(frame).await?;
+                }
+                None if senders == 0 => break,
+                None => {
+                    framed.flush().await?;
+                    queued.await;
+                }
+            }
+        }
+
+        framed.close().await
+    };
+
+    let result = result.await;
+
+    // Fails senders waiting on a full queue and every send after
+    shared.close();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::FrameCodec, Bytes, BytesMut, Message};
+    use std::time::Duration;
+    use tokio_util::codec::Decoder;
+
+    fn frame(code: u32, payload_length: usize) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::from(vec![0; payload_length]),
+        }
+    }
+
+    fn codes(output: &[u8]) -> Vec<u32> {
+        let mut codec = FrameCodec::default();
+        let mut source = BytesMut::from(output);
+        let mut codes = Vec::new();
+
+        while let Some(frame) = codec.decode(&mut source).unwrap() {
+            codes.push(frame.message.0);
+        }
+
+        codes
+    }
+
+    // Queues 3 control frames (10..), 3 real-time ones (20..) and 6 bulk ones (30..) of 100
+    // bytes each before the driver runs, and gives back the order they were written in
+    async fn written_order(scheduling: Scheduling) -> Vec<u32> {
+        let mut output = Vec::new();
+        let (scheduler, driver) = Scheduler::builder()
+            .scheduling(scheduling)
+            .build(&mut output, FrameCodec::default());
+
+        for code in 30..36 {
+            scheduler
+                .send(Priority::Bulk, frame(code, 100))
+                .await
+                .unwrap();
+        }
+        for code in 20..23 {
+            scheduler
+                .send(Priority::RealTime, frame(code, 100))
+                .await
+                .unwrap();
+        }
+        for code in 10..13 {
+            scheduler
+                .send(Priority::Control, frame(code, 100))
+                .await
+                .unwrap();
+        }
+
+        drop(scheduler);
+        driver.await.unwrap();
+
+        codes(&output)
+    }
+
+    #[tokio::test]
+    async fn writes_the_most_urgent_class_first_under_strict() {
+        assert_eq!(
+            written_order(Scheduling::Strict).await,
+            [10, 11, 12, 20, 21, 22, 30, 31, 32, 33, 34, 35]
+        );
+    }
+
+    #[tokio::test]
+    async fn shares_rounds_by_quantum_under_weighted() {
+        // Bulk gets three frames a round, the others one
+        assert_eq!(
+            written_order(Scheduling::Weighted([100, 100, 300])).await,
+            [10, 20, 30, 31, 32, 11, 21, 33, 34, 35, 12, 22]
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_the_sent_frame_under_drop_newest() {
+        let (scheduler, _driver) = Scheduler::builder()
+            .capacity(Priority::Bulk, 2)
+            .when_full(Priority::Bulk, WhenFull::DropNewest)
+            .build(Vec::new(), FrameCodec::default());
+
+        for code in 1..=2 {
+            let dropped = scheduler
+                .send(Priority::Bulk, frame(code, 0))
+                .await
+                .unwrap();
+            assert!(dropped.is_none());
+        }
+
+        let dropped = scheduler.send(Priority::Bulk, frame(3, 0)).await.unwrap();
+
+        assert_eq!(dropped.map(|frame| frame.message.0), Some(3));
+        assert_eq!(scheduler.queued(Priority::Bulk), 2);
+    }
+
+    #[tokio::test]
+    async fn drops_the_oldest_frame_under_drop_oldest() {
+        let mut output = Vec::new();
+        let (scheduler, driver) = Scheduler::builder()
+            .capacity(Priority::RealTime, 2)
+            .build(&mut output, FrameCodec::default());
+
+        for code in 1..=2 {
+            let dropped = scheduler
+                .send(Priority::RealTime, frame(code, 0))
+                .await
+                .unwrap();
+            assert!(dropped.is_none());
+        }
+
+        let dropped = scheduler
+            .send(Priority::RealTime, frame(3, 0))
+            .await
+            .unwrap();
+        assert_eq!(dropped.map(|frame| frame.message.0), Some(1));
+
+        drop(scheduler);
+        driver.await.unwrap();
+
+        assert_eq!(codes(&output), [2, 3]);
+    }
+
+    #[tokio::test]
+    async fn waits_for_room_under_await() {
+        let mut output = Vec::new();
+        let (scheduler, driver) = Scheduler::builder()
+            .capacity(Priority::Control, 1)
+            .build(&mut output, FrameCodec::default());
+
+        scheduler
+            .send(Priority::Control, frame(1, 0))
+            .await
+            .unwrap();
+
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(20),
+            scheduler.send(Priority::Control, frame(2, 0)),
+        );
+        assert!(waiting.await.is_err());
+
+        // Once the driver runs it makes room for the waiting send
+        let sender = async move {
+            let dropped = scheduler
+                .send(Priority::Control, frame(2, 0))
+                .await
+                .unwrap();
+            assert!(dropped.is_none());
+        };
+
+        let (result, ()) = tokio::join!(driver, sender);
+        result.unwrap();
+
+        assert_eq!(codes(&output), [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn fails_sends_once_the_driver_stopped() {
+        let (writer, reader) = tokio::io::duplex(64);
+        drop(reader);
+
+        let (scheduler, driver) = Scheduler::builder().build(writer, FrameCodec::default());
+
+        scheduler.send(Priority::Bulk, frame(1, 0)).await.unwrap();
+
+        assert!(matches!(driver.await, Err(WireError::Io(_))));
+        assert!(matches!(
+            scheduler.send(Priority::Control, frame(2, 0)).await,
+            Err(WireError::QueueClosed("control"))
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{
+    codec::{
+        bytes::{BufferLease, BufferPool},
+        FrameCodec,
+    },
+    errors::WireError,
+    DecodeFromFrame, EncodeIntoFrame, Frame, Message,
+};
+use futures::{Sink, SinkExt, Stream, TryStreamExt};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+
+// A pair of byte streams, e.g. a QUIC bi stream, carrying frames both ways. Sends flush before
+// they return, so a reply can be awaited right after its request
+pub struct ZwireStream<R, W, C = FrameCodec> {
+    reader: FramedRead<R, C>,
+    writer: FramedWrite<W, C>,
+    codec_buffer: BufferLease,
+    send_timeout: Option<Duration>,
+    recv_timeout: Option<Duration>,
+}
+
+impl<R, W, C> ZwireStream<R, W, C>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    C: Decoder<Item = Frame, Error = WireError> + Encoder<Frame, Error = WireError> + Clone,
+{
+    pub fn new(reader: R, writer: W, codec: C) -> Self {
+        Self {
+            reader: FramedRead::new(reader, codec.clone()),
+            writer: FramedWrite::new(writer, codec),
+            codec_buffer: BufferPool::shared().lease(),
+            send_timeout: None,
+            recv_timeout: None,
+        }
+    }
+
+    // Leases the scratch buffer typed sends encode into from `pool` instead of the shared one
+    pub fn buffer_pool(mut self, pool: &BufferPool) -> Self {
+        self.codec_buffer = pool.lease();
+        self
+    }
+
+    // Bounds every send, including waiting for the writer to take the bytes
+    pub fn send_timeout(mut self, send_timeout: Duration) -> Self {
+        self.send_timeout = Some(send_timeout);
+        self
+    }
+
+    // Bounds every receive, from the call until a whole frame arrived
+    pub fn recv_timeout(mut self, recv_timeout: Duration) -> Self {
+        self.recv_timeout = Some(recv_timeout);
+        self
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader.into_inner(), self.writer.into_inner())
+    }
+
+    pub async fn send_frame(&mut self, frame: Frame) -> Result<(), WireError> {
+        with_timeout("send", self.send_timeout, self.writer.send(frame)).await
+    }
+
+    // `None` once the peer finished its side
+    pub async fn recv_frame(&mut self) -> Result<Option<Frame>, WireError> {
+        with_timeout("recv", self.recv_timeout, self.reader.try_next()).await
+    }
+
+    // Encodes the payload with a fresh `P` codec, e.g. `send::<AuthPayloadCodec>(payload, code)`.
+    // Shadows SinkExt::send, single frames go through send_frame
+    pub async fn send<P>(
+        &mut self,
+        payload: P::EncodeItem,
+        message: impl Into<Message>,
+    ) -> Result<(), WireError>
+    where
+        P: EncodeIntoFrame<Error = WireError> + Default,
+    {
+        let frame = P::default().encode_into_frame(payload, message, &mut self.codec_buffer)?;
+
+        self.send_frame(frame).await
+    }
+
+    // Decodes the next frame's payload with a fresh `P` codec, whatever its message. The payload
+    // has to hold a whole record, frames aren't split across reads
+    pub async fn recv_typed<P>(&mut self) -> Result<Option<(P::Item, Message)>, WireError>
+    where
+        P: DecodeFromFrame<Error = WireError> + Default,
+    {
+        let Some(frame) = self.recv_frame().await? else {
+            return Ok(None);
+        };
+        let message = frame.message.0;
+
+        match P::default().decode_from_frame(frame)? {
+            Some(decoded) => Ok(Some(decoded)),
+            None => Err(WireError::IncompletePayload(message)),
+        }
+    }
+}
+
+async fn with_timeout<T>(
+    operation: &'static str,
+    duration: Option<Duration>,
+    future: impl Future<Output = Result<T, WireError>>,
+) -> Result<T, WireError> {
+    let Some(duration) = duration else {
+        return future.await;
+    };
+
+    tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_elapsed| WireError::Timeout(operation, duration))?
+}
+
+// Timeouts only apply to the methods above, polling the stream or sink directly is unbounded
+impl<R, W, C> Stream for ZwireStream<R, W, C>
+where
+    R: AsyncRead + Unpin,
+    W: Unpin,
+    C: Decoder<Item = Frame, Error = WireError> + Unpin,
+{
+    type Item = Result<Frame, WireError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.reader).poll_next(context)
+    }
+}
+
+impl<R, W, C> Sink<Frame> for ZwireStream<R, W, C>
+where
+    R: Unpin,
+    W: AsyncWrite + Unpin,
+    C: Encoder<Frame, Error = WireError> + Unpin,
+{
+    type Error = WireError;
+
+    fn poll_ready(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.writer).poll_ready(context)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: Frame) -> Result<(), Self::Error> {
+        Pin::new(&mut self.writer).start_send(frame)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.writer).poll_flush(context)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.writer).poll_close(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::wired::WireCodec, Bytes};
+    use tokio::io::{DuplexStream, ReadHalf, WriteHalf};
+
+    type Side = ZwireStream<ReadHalf<DuplexStream>, WriteHalf<DuplexStream>>;
+
+    #[derive(Debug, Clone, PartialEq, WireCodec)]
+    struct Greeting {
+        #[wire(u16, fixed)]
+        id: u16,
+        #[wire(u32, fixed)]
+        sequence: u32,
+    }
+
+    fn pair(capacity: usize) -> (Side, Side) {
+        let (left, right) = tokio::io::duplex(capacity);
+        let (left_reader, left_writer) = tokio::io::split(left);
+        let (right_reader, right_writer) = tokio::io::split(right);
+
+        (
+            ZwireStream::new(left_reader, left_writer, FrameCodec::default()),
+            ZwireStream::new(right_reader, right_writer, FrameCodec::default()),
+        )
+    }
+
+    fn frame(code: u32, payload: &[u8]) -> Frame {
+        Frame {
+            message: Message(code),
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    #[tokio::test]
+    async fn carries_frames_both_ways_until_the_peer_finishes() {
+        let (mut left, mut right) = pair(1024);
+
+        left.send_frame(frame(1, b"ping")).await.unwrap();
+        let received = right.recv_frame().await.unwrap().unwrap();
+        assert_eq!(
+            (received.message.0, &received.payload[..]),
+            (1, &b"ping"[..])
+        );
+
+        right.send_frame(frame(2, b"pong")).await.unwrap();
+        let received = left.recv_frame().await.unwrap().unwrap();
+        assert_eq!(
+            (received.message.0, &received.payload[..]),
+            (2, &b"pong"[..])
+        );
+
+        drop(left);
+        assert!(right.recv_frame().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn round_trips_typed_payloads() {
+        let (mut left, mut right) = pair(1024);
+        let greeting = Greeting {
+            id: 3,
+            sequence: 70_000,
+        };
+
+        left.send::<GreetingCodec>(greeting.clone(), Message(9))
+            .await
+            .unwrap();
+
+        let (received, message) = right.recv_typed::<GreetingCodec>().await.unwrap().unwrap();
+        assert_eq!(received, greeting);
+        assert_eq!(message.0, 9);
+
+        // A payload shorter than the record's fixed part
+        left.send_frame(frame(4, &[0, 3])).await.unwrap();
+
+        assert!(matches!(
+            right.recv_typed::<GreetingCodec>().await,
+            Err(WireError::IncompletePayload(4))
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out_receives_with_nothing_arriving() {
+        let (_left, right) = pair(1024);
+        let mut right = right.recv_timeout(Duration::from_millis(20));
+
+        assert!(matches!(
+            right.recv_frame().await,
+            Err(WireError::Timeout("recv", duration)) if duration == Duration::from_millis(20)
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out_sends_the_peer_doesnt_read() {
+        let (left, _right) = pair(64);
+        let mut left = left.send_timeout(Duration::from_millis(20));
+
+        assert!(matches!(
+            left.send_frame(frame(1, &[0; 1000])).await,
+            Err(WireError::Timeout("send", _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn works_as_a_stream_and_a_sink() {
+        let (mut left, right) = pair(1024);
+
+        let mut frames = futures::stream::iter((1..=3).map(|code| Ok(frame(code, &[code as u8]))));
+        left.send_all(&mut frames).await.unwrap();
+        drop(left);
+
+        let received: Vec<Frame> = right.try_collect().await.unwrap();
+        let received: Vec<(u32, Vec<u8>)> = received
+            .into_iter()
+            .map(|frame| (frame.message.0, frame.payload.to_vec()))
+            .collect();
+
+        assert_eq!(received, [(1, vec![1]), (2, vec![2]), (3, vec![3])]);
+    }
+}